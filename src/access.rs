@@ -0,0 +1,97 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::{codec, error::ApiError, store::StoreResult, tenant::TenantStore, Item, SharedStore};
+
+/// Per-item read counters, kept apart from the items so counting a read
+/// never rewrites the item itself.
+pub const ACCESS_TREE: &str = "access_stats";
+
+const DEFAULT_FREQUENT_LIMIT: usize = 10;
+const MAX_FREQUENT_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct AccessStats {
+    pub access_count: u64,
+    pub last_accessed: i64,
+}
+
+/// Counts one read of `id` at `now`. The increment is a single atomic update,
+/// so concurrent reads are all counted.
+pub fn record(db: &SharedStore, id: &str, now: i64) -> StoreResult<()> {
+    let tree = db.tree(ACCESS_TREE)?;
+    let encoding = tree.encoding();
+    tree.update(id.as_bytes(), &mut |current| {
+        let mut stats: AccessStats = current
+            .and_then(|raw| codec::decode(raw).ok())
+            .unwrap_or_default();
+        stats.access_count += 1;
+        stats.last_accessed = now;
+        codec::encode(encoding, &stats).ok()
+    })?;
+    Ok(())
+}
+
+/// Drops the counters of a deleted item.
+pub fn forget(db: &SharedStore, id: &str) -> StoreResult<()> {
+    db.tree(ACCESS_TREE)?.remove(id.as_bytes())?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FrequentQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct FrequentItem {
+    #[serde(flatten)]
+    item: Item,
+    #[serde(flatten)]
+    stats: AccessStats,
+}
+
+fn most_accessed(db: &SharedStore, limit: usize) -> StoreResult<Vec<FrequentItem>> {
+    let mut counted: Vec<(Vec<u8>, AccessStats)> = Vec::new();
+    for entry in db.tree(ACCESS_TREE)?.iter() {
+        let (id, raw) = entry?;
+        if let Ok(stats) = codec::decode(&raw) {
+            counted.push((id, stats));
+        }
+    }
+    counted.sort_by(|(a_id, a), (b_id, b)| {
+        b.access_count
+            .cmp(&a.access_count)
+            .then(b.last_accessed.cmp(&a.last_accessed))
+            .then_with(|| a_id.cmp(b_id))
+    });
+
+    let mut frequent = Vec::new();
+    for (id, stats) in counted {
+        if frequent.len() == limit {
+            break;
+        }
+        if let Some(item) = db
+            .get(&id)?
+            .and_then(|raw| codec::decode::<Item>(&raw).ok())
+        {
+            frequent.push(FrequentItem { item, stats });
+        }
+    }
+    Ok(frequent)
+}
+
+/// `GET /items/frequent`: the most-read items, most reads first, each with
+/// its `access_count` and `last_accessed`. Empty unless `TRACK_ACCESS` is on.
+pub async fn frequent(
+    db: TenantStore,
+    query: web::Query<FrequentQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_FREQUENT_LIMIT)
+        .min(MAX_FREQUENT_LIMIT);
+    let db = db.into_inner();
+    let items = web::block(move || most_accessed(&db, limit)).await??;
+    Ok(HttpResponse::Ok().json(items))
+}
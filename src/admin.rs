@@ -0,0 +1,57 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+
+use crate::{error::ApiError, tenant::TenantStore, time};
+
+/// Version of the stored item layout, bumped whenever it changes shape.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Facts about the store gathered once at startup.
+#[derive(Debug, Clone, Copy)]
+pub struct StartupInfo {
+    /// No database existed before this process started.
+    pub fresh: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AdminInfo {
+    fresh: bool,
+    schema_version: u32,
+    item_count: usize,
+}
+
+/// Reports whether this run created the database, so provisioning can seed
+/// default items only on first start.
+pub async fn info(
+    db: TenantStore,
+    startup: web::Data<StartupInfo>,
+) -> Result<HttpResponse, ApiError> {
+    let db = db.into_inner();
+    let item_count = web::block(move || db.iter().count()).await?;
+
+    Ok(HttpResponse::Ok().json(AdminInfo {
+        fresh: startup.fresh,
+        schema_version: SCHEMA_VERSION,
+        item_count,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    commit: &'static str,
+    built_at: Option<String>,
+}
+
+/// `GET /version`: which build is running. Served without an API key.
+pub async fn version() -> impl Responder {
+    let built_at = env!("NEONOTE_BUILT_AT")
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| time::millis_to_iso(secs * 1000));
+    HttpResponse::Ok().json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("NEONOTE_GIT_COMMIT"),
+        built_at,
+    })
+}
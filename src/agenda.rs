@@ -0,0 +1,257 @@
+//! The agenda: what falls on each day of a range, for clients that render
+//! an org-mode style schedule from one call. Items due on a day and events
+//! running on it are listed under it, repeating items once per occurrence,
+//! and open items already past due are carried over.
+
+use actix_web::{web, HttpResponse};
+use chrono::{Days, NaiveDate};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::SharedClock,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    recurrence::{self, Rule},
+    tenant::TenantStore,
+    tz, Item,
+};
+
+const DEFAULT_DAYS: u64 = 7;
+const MAX_DAYS: u64 = 366;
+/// Most occurrences of one repeating item listed in a range: one a day,
+/// with room for the busiest weekly rules.
+const MAX_EXPANDED: usize = 7 * MAX_DAYS as usize;
+
+#[derive(Debug, Deserialize)]
+pub struct AgendaQuery {
+    /// First day, `YYYY-MM-DD`; today by default.
+    from: Option<String>,
+    /// Last day, inclusive; a week from `from` by default.
+    to: Option<String>,
+    tz: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    /// The item is due at `at`.
+    Due,
+    /// The item runs from `at` to `end`.
+    Event,
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    kind: Kind,
+    at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<i64>,
+    item: Item,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Day {
+    /// `YYYY-MM-DD`.
+    date: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize)]
+struct Agenda {
+    /// Open items due before today, earliest first. Empty unless the range
+    /// includes today.
+    overdue: Vec<Item>,
+    days: Vec<Day>,
+}
+
+fn parse_day(raw: &str, field: &str) -> Result<NaiveDate, ApiError> {
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map_err(|_| ApiError::BadRequest(format!("Invalid {field} '{raw}', expected YYYY-MM-DD")))
+}
+
+/// How far each of `item`'s occurrences that can reach `from..until` is
+/// from the stored one: just `0` for an item that doesn't repeat.
+fn shifts(item: &Item, from: i64, until: i64) -> Vec<i64> {
+    let Some(anchor) = recurrence::anchor(item) else {
+        return Vec::new();
+    };
+    // An occurrence before `from` still counts when its event runs into it.
+    let reach = [item.due_date, item.start_time, item.end_time]
+        .into_iter()
+        .flatten()
+        .map(|at| at - anchor)
+        .max()
+        .unwrap_or(0)
+        .max(0);
+    match item.recurrence.as_deref().map(str::parse::<Rule>) {
+        Some(Ok(rule)) => rule
+            .occurrences_between(anchor, from.saturating_sub(reach), until, MAX_EXPANDED)
+            .into_iter()
+            .map(|at| at - anchor)
+            .collect(),
+        _ => vec![0],
+    }
+}
+
+fn out_of_range() -> ApiError {
+    ApiError::BadRequest("Date is too far in the future".to_string())
+}
+
+/// A run of whole days in one zone, `first` to `last` inclusive.
+pub struct Range {
+    first: NaiveDate,
+    last: NaiveDate,
+    /// The day after `last`, whose start ends the range.
+    next: NaiveDate,
+    zone: Tz,
+}
+
+impl Range {
+    /// Refuses ranges running up to the last representable day, whose end
+    /// can't be computed in every zone.
+    pub fn new(first: NaiveDate, last: NaiveDate, zone: Tz) -> Result<Self, ApiError> {
+        let next = last
+            .checked_add_days(Days::new(1))
+            .filter(|next| next.checked_add_days(Days::new(1)).is_some())
+            .ok_or_else(out_of_range)?;
+        Ok(Range {
+            first,
+            last,
+            next,
+            zone,
+        })
+    }
+
+    fn starts(&self) -> i64 {
+        tz::start_of_day(self.first, self.zone)
+    }
+
+    fn ends(&self) -> i64 {
+        tz::start_of_day(self.next, self.zone)
+    }
+
+    /// The entries `item` puts in the range, with the day of each.
+    fn entries(&self, item: &Item) -> Vec<(NaiveDate, Entry)> {
+        let mut entries = Vec::new();
+        for shift in shifts(item, self.starts(), self.ends()) {
+            if let Some(due) = item.due_date.map(|due| due + shift) {
+                if (self.starts()..self.ends()).contains(&due) {
+                    let entry = Entry {
+                        kind: Kind::Due,
+                        at: due,
+                        end: None,
+                        item: item.clone(),
+                    };
+                    entries.push((tz::day_of(due, self.zone), entry));
+                }
+            }
+            let Some(start) = item.start_time.map(|start| start + shift) else {
+                continue;
+            };
+            let end = item.end_time.map(|end| end + shift);
+            let last = end.unwrap_or(start);
+            if start >= self.ends() || last < self.starts() {
+                continue;
+            }
+            // An event spanning several days shows on each of them.
+            let first_day = tz::day_of(start, self.zone).max(self.first);
+            let last_day = tz::day_of(last, self.zone).min(self.last);
+            for day in first_day.iter_days().take_while(|day| *day <= last_day) {
+                let entry = Entry {
+                    kind: Kind::Event,
+                    at: start,
+                    end,
+                    item: item.clone(),
+                };
+                entries.push((day, entry));
+            }
+        }
+        entries
+    }
+
+    /// Every day of the range with the entries `items` put on it, in time
+    /// order.
+    pub fn days(&self, items: &[Item]) -> Vec<Day> {
+        let mut days: Vec<Day> = self
+            .first
+            .iter_days()
+            .take_while(|day| *day <= self.last)
+            .map(|date| Day {
+                date: date.to_string(),
+                entries: Vec::new(),
+            })
+            .collect();
+        for item in items {
+            for (date, entry) in self.entries(item) {
+                let index = (date - self.first).num_days() as usize;
+                days[index].entries.push(entry);
+            }
+        }
+        for day in &mut days {
+            day.entries
+                .sort_by(|a, b| a.at.cmp(&b.at).then_with(|| a.item.id.cmp(&b.item.id)));
+        }
+        days
+    }
+}
+
+/// The items an agenda or calendar draws from: everything not archived or
+/// snoozed.
+pub async fn scheduled(db: &TenantStore, now: i64) -> Result<Vec<Item>, ApiError> {
+    let filter = ItemFilter {
+        exclude_archived: true,
+        exclude_snoozed: true,
+        now,
+        ..ItemFilter::default()
+    };
+    Ok(filter::scan_blocking(db, filter).await?)
+}
+
+/// `GET /agenda?from=&to=`: every day from `from` to `to`, inclusive, in
+/// `?tz=`, each with the items due on it and the events running on it in
+/// time order, plus the open items overdue as of today.
+pub async fn agenda(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    query: web::Query<AgendaQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let zone = tz::parse(query.tz.as_deref()).map_err(ApiError::BadRequest)?;
+    let now = clock.now_millis();
+    let today = tz::day_of(now, zone);
+    let first = match &query.from {
+        Some(raw) => parse_day(raw, "from")?,
+        None => today,
+    };
+    let last = match &query.to {
+        Some(raw) => parse_day(raw, "to")?,
+        None => first
+            .checked_add_days(Days::new(DEFAULT_DAYS - 1))
+            .ok_or_else(out_of_range)?,
+    };
+    let span = (last - first).num_days();
+    if span < 0 || span as u64 >= MAX_DAYS {
+        return Err(ApiError::BadRequest(format!(
+            "to must be on or after from, and at most {MAX_DAYS} days later"
+        )));
+    }
+    let range = Range::new(first, last, zone)?;
+
+    let items = scheduled(&db, now).await?;
+    let days = range.days(&items);
+
+    let mut overdue = Vec::new();
+    let today_starts = tz::start_of_day(today, zone);
+    for item in items {
+        let open = item.completed != Some(true);
+        if open
+            && (first..=last).contains(&today)
+            && item.due_date.is_some_and(|due| due < today_starts)
+        {
+            overdue.push(item);
+        }
+    }
+    filter::sort_by_key(&mut overdue, |item| item.due_date);
+
+    Ok(HttpResponse::Ok().json(Agenda { overdue, days }))
+}
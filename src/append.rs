@@ -0,0 +1,202 @@
+use actix_web::{rt, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::{
+    clock::SharedClock,
+    codec, commit_items,
+    config::Config,
+    error::ApiError,
+    tenant::{Tenant, TenantStore},
+    validation, Item, SharedStore,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct AppendPayload {
+    pub text: String,
+}
+
+/// Adds `text` as a new line at the end of the item's content.
+fn append_line(item: &mut Item, text: &str) {
+    item.content = Some(match item.content.take() {
+        Some(content) if !content.is_empty() => format!("{content}\n{text}"),
+        _ => text.to_string(),
+    });
+}
+
+/// Appends `text` to the stored item, retrying if another write lands first,
+/// so concurrent appenders never overwrite each other the way a client-side
+/// read-modify-write could. The result is validated like any other edit, so
+/// appends can't grow content past the limit, and is logged, reindexed and
+/// its replaced version kept as a revision in the same batch.
+pub fn append_stored(
+    db: &SharedStore,
+    tenant: &Tenant,
+    config: &Config,
+    id: &str,
+    text: &str,
+    now: i64,
+) -> Result<Item, ApiError> {
+    let failed = |_| ApiError::Internal("Failed to update item");
+    loop {
+        let current = db
+            .get(id.as_bytes())
+            .map_err(failed)?
+            .ok_or(ApiError::NotFound("Item not found"))?;
+        let previous: Item =
+            codec::decode(&current).map_err(|_| ApiError::Internal("Deserialization failed"))?;
+        let mut item = previous.clone();
+        append_line(&mut item, text);
+        item.touch(now);
+        validation::validate_item(&mut item, config).map_err(ApiError::Invalid)?;
+
+        if commit_items(db, tenant, &[(Some(previous), item.clone())], Vec::new())? {
+            return Ok(item);
+        }
+    }
+}
+
+/// Appends waiting to be written, per tenant and item.
+type BufferKey = (Option<String>, String);
+
+struct Pending {
+    db: SharedStore,
+    lines: Vec<String>,
+    bytes: usize,
+}
+
+/// Write coalescing for `POST /items/{id}/append`, enabled by
+/// `APPEND_COALESCE_MS`. Appends to an item are held for that window and then
+/// written together, so a client appending several times a second costs one
+/// store write per window rather than one per line. A buffer that reaches
+/// `APPEND_FLUSH_BYTES` is written straight away, and whatever is left is
+/// written on shutdown.
+pub struct AppendBuffer {
+    window: Option<Duration>,
+    flush_bytes: usize,
+    config: Config,
+    clock: SharedClock,
+    pending: Mutex<HashMap<BufferKey, Pending>>,
+}
+
+impl AppendBuffer {
+    pub fn new(config: &Config, clock: SharedClock) -> Self {
+        AppendBuffer {
+            window: config.append_coalesce_ms.map(Duration::from_millis),
+            flush_bytes: config.append_flush_bytes,
+            config: config.clone(),
+            clock,
+            pending: Mutex::default(),
+        }
+    }
+
+    /// The buffered lines. A panic while the lock was held leaves them
+    /// consistent, since every update is a single insert or remove, so a
+    /// poisoned lock is taken over rather than propagated.
+    fn pending(&self) -> MutexGuard<'_, HashMap<BufferKey, Pending>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Buffers `text` for `key`. Returns how many lines are now waiting, and
+    /// whether this was the first, which starts the window.
+    fn push(&self, key: &BufferKey, db: &SharedStore, text: &str) -> (usize, bool) {
+        let mut pending = self.pending();
+        let first = !pending.contains_key(key);
+        let entry = pending.entry(key.clone()).or_insert_with(|| Pending {
+            db: db.clone(),
+            lines: Vec::new(),
+            bytes: 0,
+        });
+        entry.lines.push(text.to_string());
+        entry.bytes += text.len();
+        (entry.lines.len(), first)
+    }
+
+    fn is_full(&self, key: &BufferKey) -> bool {
+        let pending = self.pending();
+        pending
+            .get(key)
+            .is_some_and(|entry| entry.bytes >= self.flush_bytes)
+    }
+
+    /// Writes out the lines buffered for `key`, if any, as one append.
+    fn flush(&self, key: &BufferKey) -> Option<Result<Item, ApiError>> {
+        let entry = self.pending().remove(key)?;
+        let text = entry.lines.join("\n");
+        Some(append_stored(
+            &entry.db,
+            &Tenant(key.0.clone()),
+            &self.config,
+            &key.1,
+            &text,
+            self.clock.now_millis(),
+        ))
+    }
+
+    /// Writes out everything still buffered. Called on shutdown.
+    pub fn flush_all(&self) {
+        let keys: Vec<BufferKey> = self.pending().keys().cloned().collect();
+        for key in keys {
+            if let Some(Err(e)) = self.flush(&key) {
+                eprintln!("Dropped buffered appends to {}: {e:?}", key.1);
+            }
+        }
+    }
+}
+
+/// `POST /items/{id}/append`: appends a line to the item's content. Returns
+/// the updated item, or 202 when the line was buffered for a later write.
+pub async fn append_content(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    buffer: web::Data<AppendBuffer>,
+    path: web::Path<String>,
+    payload: web::Json<AppendPayload>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let db = db.into_inner();
+    let text = payload.into_inner().text;
+
+    let Some(window) = buffer.window else {
+        let now = clock.now_millis();
+        let item =
+            web::block(move || append_stored(&db, &tenant, &config, &id, &text, now)).await??;
+        return Ok(HttpResponse::Ok().json(item));
+    };
+
+    if db.get(id.as_bytes())?.is_none() {
+        return Err(ApiError::NotFound("Item not found"));
+    }
+
+    let key: BufferKey = (tenant.0, id.clone());
+    let (buffered, first) = buffer.push(&key, &db, &text);
+
+    if buffer.is_full(&key) {
+        let buffer = buffer.into_inner();
+        return match web::block(move || buffer.flush(&key)).await? {
+            Some(item) => Ok(HttpResponse::Ok().json(item?)),
+            // A timer flushed it first; the line is stored either way.
+            None => Ok(HttpResponse::Accepted().json(json!({ "id": id, "buffered": 0 }))),
+        };
+    }
+
+    if first {
+        let buffer = buffer.into_inner();
+        let key = key.clone();
+        rt::spawn(async move {
+            rt::time::sleep(window).await;
+            let flushed = web::block(move || buffer.flush(&key).map(|r| (key, r))).await;
+            if let Ok(Some((key, Err(e)))) = flushed {
+                eprintln!("Dropped buffered appends to {}: {e:?}", key.1);
+            }
+        });
+    }
+    Ok(HttpResponse::Accepted().json(json!({ "id": id, "buffered": buffered })))
+}
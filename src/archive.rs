@@ -0,0 +1,45 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    clock::SharedClock,
+    error::ApiError,
+    load_item, save_item,
+    tenant::{Tenant, TenantStore},
+};
+
+fn set_archived(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    id: &str,
+    archived: bool,
+) -> Result<HttpResponse, ApiError> {
+    let mut item = load_item(&db, id)?;
+    if item.archived != archived {
+        item.archived = archived;
+        item.touch(clock.now_millis());
+        save_item(&db, &tenant, &item)?;
+    }
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// `POST /items/{id}/archive`: hides the item from listings without
+/// deleting it. Archiving an archived item changes nothing.
+pub async fn archive(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    set_archived(db, tenant, clock, &path.into_inner(), true)
+}
+
+/// `POST /items/{id}/unarchive`: returns an archived item to listings.
+pub async fn unarchive(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    set_archived(db, tenant, clock, &path.into_inner(), false)
+}
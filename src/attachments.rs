@@ -0,0 +1,157 @@
+use actix_multipart::Multipart;
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use async_graphql::SimpleObject;
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    clock::SharedClock,
+    config::Config,
+    error::ApiError,
+    load_item, save_item,
+    store::{BatchOp, StoreResult},
+    tenant::{Tenant, TenantStore},
+    Item, SharedStore,
+};
+
+/// Blobs live in their own tree so listing items never reads file contents.
+const BLOB_TREE: &str = "attachments";
+
+#[derive(Debug, Serialize, Deserialize, Clone, SimpleObject)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+}
+
+/// Removes the stored blobs for every attachment on `item`.
+pub fn remove_blobs(db: &SharedStore, item: &Item) -> StoreResult<()> {
+    let ops = item
+        .attachments
+        .iter()
+        .map(|attachment| BatchOp::Remove(attachment.id.clone().into_bytes()))
+        .collect();
+    db.tree(BLOB_TREE)?.batch(ops)
+}
+
+pub async fn upload_attachments(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let mut item = load_item(&db, &id)?;
+    let malformed = |_| ApiError::BadRequest("Malformed multipart body".to_string());
+
+    // Read every file before storing anything, so a rejected upload leaves no
+    // orphaned blobs behind.
+    let mut pending = Vec::new();
+    while let Some(mut field) = payload.try_next().await.map_err(malformed)? {
+        let Some(filename) = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .map(str::to_string)
+        else {
+            // Plain form fields carry no file; skip them.
+            continue;
+        };
+        let content_type = field
+            .content_type()
+            .map(|mime| mime.to_string())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+
+        let mut data = Vec::new();
+        while let Some(chunk) = field.try_next().await.map_err(malformed)? {
+            if (data.len() + chunk.len()) as u64 > config.max_attachment_bytes {
+                return Err(ApiError::PayloadTooLarge(
+                    "Attachment too large".to_string(),
+                ));
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        let attachment = Attachment {
+            id: Uuid::new_v4().to_string(),
+            filename,
+            content_type,
+            size: data.len() as u64,
+        };
+        pending.push((attachment, data));
+    }
+
+    if pending.is_empty() {
+        return Err(ApiError::BadRequest("No file in upload".to_string()));
+    }
+
+    let blobs = db.tree(BLOB_TREE)?;
+    let (uploaded, ops): (Vec<Attachment>, Vec<BatchOp>) = pending
+        .into_iter()
+        .map(|(attachment, data)| {
+            let op = BatchOp::Insert(attachment.id.clone().into_bytes(), data);
+            (attachment, op)
+        })
+        .unzip();
+    blobs
+        .batch(ops)
+        .map_err(|_| ApiError::Internal("Failed to store attachment"))?;
+
+    item.attachments.extend(uploaded.iter().cloned());
+    item.touch(clock.now_millis());
+    save_item(&db, &tenant, &item)?;
+    Ok(HttpResponse::Created().json(uploaded))
+}
+
+pub async fn download_attachment(
+    db: TenantStore,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, attachment_id) = path.into_inner();
+    let item = load_item(&db, &id)?;
+    let attachment = item
+        .attachments
+        .iter()
+        .find(|a| a.id == attachment_id)
+        .ok_or(ApiError::NotFound("Attachment not found"))?;
+    let blob = db
+        .tree(BLOB_TREE)?
+        .get(attachment.id.as_bytes())?
+        .ok_or(ApiError::NotFound("Attachment not found"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(attachment.content_type.as_str())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(attachment.filename.clone())],
+        })
+        .body(blob.to_vec()))
+}
+
+pub async fn delete_attachment(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, attachment_id) = path.into_inner();
+    let mut item = load_item(&db, &id)?;
+    let before = item.attachments.len();
+    item.attachments.retain(|a| a.id != attachment_id);
+    if item.attachments.len() == before {
+        return Err(ApiError::NotFound("Attachment not found"));
+    }
+
+    item.touch(clock.now_millis());
+    save_item(&db, &tenant, &item)?;
+    db.tree(BLOB_TREE)
+        .and_then(|tree| tree.remove(attachment_id.as_bytes()))
+        .map_err(|_| ApiError::Internal("Delete failed"))?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,151 @@
+//! Append-only record of item writes. Entries are keyed by timestamp so a
+//! time window is a range scan, and are written in the same atomic batch as
+//! the change they describe wherever the write allows it.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+use crate::{
+    codec,
+    error::ApiError,
+    store::{BatchOp, Guard, StoreResult},
+    tenant::{Tenant, TenantStore},
+    time, SharedStore,
+};
+
+pub const AUDIT_TREE: &str = "audit";
+
+/// Orders entries written in the same millisecond.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+    /// Moved to the trash.
+    Delete,
+    /// Brought back from the trash.
+    Restore,
+    /// Removed from the trash for good.
+    Purge,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: i64,
+    /// Label of the API key used; `null` for the primary key.
+    pub api_key_label: Option<String>,
+    pub action: Action,
+    pub item_id: String,
+}
+
+impl Entry {
+    pub fn new(tenant: &Tenant, action: Action, item_id: &str, timestamp: i64) -> Self {
+        Entry {
+            timestamp,
+            api_key_label: tenant.0.clone(),
+            action,
+            item_id: item_id.to_string(),
+        }
+    }
+
+    /// Sorts by time, then by write order within a millisecond. The random
+    /// suffix keeps entries apart should the counter restart mid-millisecond.
+    fn key(&self) -> Vec<u8> {
+        let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        format!(
+            "{:020}-{seq:020}-{}",
+            self.timestamp,
+            Uuid::new_v4().simple()
+        )
+        .into_bytes()
+    }
+
+    fn op(&self, db: &SharedStore) -> StoreResult<BatchOp> {
+        Ok(BatchOp::Insert(
+            self.key(),
+            codec::encode(db.encoding(), self)?,
+        ))
+    }
+}
+
+/// Applies `ops` to the item keyspace together with `entries`, atomically.
+pub fn commit(db: &SharedStore, ops: Vec<BatchOp>, entries: &[Entry]) -> StoreResult<()> {
+    commit_with(db, ops, Vec::new(), entries)
+}
+
+/// [`commit`], also applying `sides` to other trees in the same batch.
+pub fn commit_with(
+    db: &SharedStore,
+    ops: Vec<BatchOp>,
+    sides: Vec<(&str, Vec<BatchOp>)>,
+    entries: &[Entry],
+) -> StoreResult<()> {
+    commit_if(db, &[], ops, sides, entries).map(|_| ())
+}
+
+/// [`commit_with`], applied only if `guards` pass on the item keyspace; see
+/// [`crate::store::Store::batch_if`]. Returns whether it was.
+pub fn commit_if(
+    db: &SharedStore,
+    guards: &[Guard],
+    ops: Vec<BatchOp>,
+    mut sides: Vec<(&str, Vec<BatchOp>)>,
+    entries: &[Entry],
+) -> StoreResult<bool> {
+    let audit_ops = entries
+        .iter()
+        .map(|entry| entry.op(db))
+        .collect::<StoreResult<_>>()?;
+    sides.push((AUDIT_TREE, audit_ops));
+    db.batch_if(guards, ops, sides)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    item_id: Option<String>,
+    /// Earliest entry, as epoch millis or ISO-8601, inclusive.
+    from: Option<String>,
+    /// Latest entry, as epoch millis or ISO-8601, inclusive.
+    to: Option<String>,
+}
+
+fn parse_bound(raw: Option<&str>, name: &str) -> Result<Option<i64>, String> {
+    raw.map(|raw| {
+        time::parse_millis(raw)
+            .ok_or_else(|| format!("Invalid {name} '{raw}', expected epoch millis or ISO-8601"))
+    })
+    .transpose()
+}
+
+/// `GET /admin/audit`: logged writes in time order, optionally narrowed to
+/// one item and a time window.
+pub async fn list(
+    db: TenantStore,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let from = parse_bound(query.from.as_deref(), "from").map_err(ApiError::BadRequest)?;
+    let to = parse_bound(query.to.as_deref(), "to").map_err(ApiError::BadRequest)?;
+    let tree = db.tree(AUDIT_TREE)?;
+
+    let query = query.into_inner();
+    let entries = web::block(move || {
+        let entries = match from {
+            // Keys are `{timestamp:020}-...`, so every entry at `from` or
+            // later sorts after the bare padded timestamp.
+            Some(from) => tree.iter_after(format!("{:020}", from.max(0)).as_bytes()),
+            None => tree.iter(),
+        };
+        entries
+            .filter_map(|entry| codec::decode::<Entry>(&entry.ok()?.1).ok())
+            .take_while(|entry| to.is_none_or(|to| entry.timestamp <= to))
+            .filter(|entry| query.item_id.as_ref().is_none_or(|id| *id == entry.item_id))
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
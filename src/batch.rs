@@ -0,0 +1,527 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+use crate::{
+    audit::{self, Action},
+    changed_meanwhile,
+    clock::SharedClock,
+    codec, complete,
+    config::Config,
+    dependencies,
+    error::ApiError,
+    etag, id_taken,
+    ids::SharedIdGenerator,
+    index::IndexOps,
+    links, load_item, new_item_id, revisions, still_at,
+    store::{BatchOp, Check, Guard, StoreError},
+    subtasks::{self, ChildPolicy, Orphans},
+    tenant::{Tenant, TenantStore},
+    trash, validation, CreateItemPayload, Item, SharedStore, UpdateItemPayload,
+};
+
+/// Most IDs one `POST /items/get-many` may ask for.
+const MAX_GET_MANY: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct BatchQuery {
+    /// Store everything or nothing. With `atomic=false` the valid rows are
+    /// stored and each row's outcome is reported.
+    #[serde(default = "default_atomic")]
+    atomic: bool,
+}
+
+fn default_atomic() -> bool {
+    true
+}
+
+/// What happened to one row of a batch.
+#[derive(Debug, Serialize)]
+struct Outcome {
+    index: usize,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+impl Outcome {
+    fn failed(index: usize, error: ApiError) -> Self {
+        Outcome {
+            index,
+            status: error.status_code().as_u16(),
+            id: None,
+            error: Some(error.body()),
+        }
+    }
+}
+
+/// One row of `POST /items/batch`. A row without an `op` is a create body,
+/// as the endpoint took before it handled other operations.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Operation {
+    Create {
+        item: Value,
+    },
+    Update {
+        id: String,
+        changes: Box<UpdateItemPayload>,
+        /// The row's `If-Match`, checked as the header is for a single write.
+        #[serde(default)]
+        if_match: Option<String>,
+    },
+    Delete {
+        id: String,
+        #[serde(default)]
+        if_match: Option<String>,
+        /// What happens to the item's subtasks, as `?children=` says for
+        /// `DELETE /items/{id}`.
+        #[serde(default)]
+        children: ChildPolicy,
+    },
+}
+
+impl Operation {
+    fn parse(row: Value) -> Result<Self, serde_json::Error> {
+        if row.get("op").is_none() {
+            return Ok(Operation::Create { item: row });
+        }
+        serde_json::from_value(row)
+    }
+}
+
+/// A row ready to store.
+enum Change {
+    Create(Item),
+    /// An update, with the next occurrence if it completes a repeating item.
+    Update {
+        old: Box<Item>,
+        new: Item,
+        follow_ons: Vec<Change>,
+    },
+    /// A delete, with the changes its child policy makes to the item's
+    /// subtasks.
+    Delete {
+        item: Item,
+        follow_ons: Vec<Change>,
+    },
+}
+
+impl Change {
+    fn status(&self) -> u16 {
+        match self {
+            Change::Create(_) => 201,
+            Change::Update { .. } => 200,
+            Change::Delete { .. } => 204,
+        }
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            Change::Create(item)
+            | Change::Update { new: item, .. }
+            | Change::Delete { item, .. } => &item.id,
+        }
+    }
+
+    /// The version of the item the change was checked against; `None` for
+    /// a create, which needs its ID still free.
+    fn version(&self) -> Option<u64> {
+        match self {
+            Change::Create(_) => None,
+            Change::Update { old, .. } => Some(old.version),
+            Change::Delete { item, .. } => Some(item.version),
+        }
+    }
+
+    /// This change followed by those it brings with it.
+    fn with_follow_ons(&self) -> impl Iterator<Item = &Change> {
+        let follow_ons = match self {
+            Change::Create(_) => &[],
+            Change::Update { follow_ons, .. } | Change::Delete { follow_ons, .. } => {
+                follow_ons.as_slice()
+            }
+        };
+        std::iter::once(self).chain(follow_ons)
+    }
+
+    /// The item as stored, or as it was when deleted.
+    fn into_item(self) -> Item {
+        match self {
+            Change::Create(item)
+            | Change::Update { new: item, .. }
+            | Change::Delete { item, .. } => item,
+        }
+    }
+}
+
+/// Everything a row needs to be checked against.
+struct Context<'a> {
+    db: &'a SharedStore,
+    ids: &'a SharedIdGenerator,
+    config: &'a Config,
+    now: i64,
+}
+
+/// Parses and validates one row into a change ready to store. `touched`
+/// holds the IDs earlier rows create, update or delete, subtasks included;
+/// a second row for one of them is refused, since both would be checked
+/// against the stored item.
+fn prepare(
+    row: Value,
+    index: usize,
+    cx: &Context,
+    touched: &mut HashSet<String>,
+) -> Result<Change, Outcome> {
+    let failed = |error| Outcome::failed(index, error);
+    let bad_request = |e: serde_json::Error| failed(ApiError::BadRequest(e.to_string()));
+    let invalid = |errors| failed(ApiError::Invalid(errors));
+    let operation = Operation::parse(row).map_err(bad_request)?;
+    if let Operation::Update { id, .. } | Operation::Delete { id, .. } = &operation {
+        if !touched.insert(id.clone()) {
+            let message = "Item is changed by an earlier row of this batch".to_string();
+            return Err(failed(ApiError::Conflict(message)));
+        }
+    }
+
+    match operation {
+        Operation::Create { item } => {
+            let payload: CreateItemPayload = serde_json::from_value(item).map_err(bad_request)?;
+            let id = new_item_id(&payload, cx.ids, cx.now).map_err(failed)?;
+            // Like `POST /items`, a client ID never overwrites an item,
+            // whether stored or created by an earlier row.
+            if cx
+                .db
+                .get(id.as_bytes())
+                .map_err(|e| failed(e.into()))?
+                .is_some()
+                || !touched.insert(id.clone())
+            {
+                return Err(failed(id_taken()));
+            }
+            let mut item = Item::from_payload(id, cx.now, &payload);
+            validation::validate_item(&mut item, cx.config).map_err(invalid)?;
+            validation::check_references(cx.db, &item).map_err(failed)?;
+            Ok(Change::Create(item))
+        }
+        Operation::Update {
+            id,
+            changes,
+            if_match,
+        } => {
+            let old = Box::new(load_item(cx.db, &id).map_err(failed)?);
+            if let Some(if_match) = &if_match {
+                etag::require(if_match, &old).map_err(failed)?;
+            }
+            let mut new = Item::clone(&old);
+            new.apply_update(&changes);
+            validation::validate_item(&mut new, cx.config).map_err(invalid)?;
+            validation::check_references(cx.db, &new).map_err(failed)?;
+            new.touch(cx.now);
+            let next = complete::roll_forward(&old, &mut new, cx.ids, cx.now);
+            Ok(Change::Update {
+                old,
+                new,
+                follow_ons: next.into_iter().map(Change::Create).collect(),
+            })
+        }
+        Operation::Delete {
+            id,
+            if_match,
+            children,
+        } => {
+            let item = load_item(cx.db, &id).map_err(failed)?;
+            if let Some(if_match) = &if_match {
+                etag::require(if_match, &item).map_err(failed)?;
+            }
+            let Orphans { detached, deleted } =
+                subtasks::orphans(cx.db, &item, children, cx.now).map_err(failed)?;
+            let follow_ons: Vec<Change> = detached
+                .into_iter()
+                .filter_map(|(old, new)| {
+                    Some(Change::Update {
+                        old: Box::new(old?),
+                        new,
+                        follow_ons: Vec::new(),
+                    })
+                })
+                .chain(deleted.into_iter().map(|item| Change::Delete {
+                    item,
+                    follow_ons: Vec::new(),
+                }))
+                .collect();
+            for orphan in &follow_ons {
+                if !touched.insert(orphan.id().to_string()) {
+                    let message =
+                        "A subtask of the item is changed by an earlier row of this batch";
+                    return Err(failed(ApiError::Conflict(message.to_string())));
+                }
+            }
+            Ok(Change::Delete { item, follow_ons })
+        }
+    }
+}
+
+/// Writes every change, and the dependents completed items unblock, in one
+/// batch with their audit entries, keeping the versions updates replace as
+/// revisions, moving deleted items to the trash and updating the indexes,
+/// then strips links to the deleted items. The batch is applied only while
+/// every item is still stored as it was checked, and every created ID still
+/// free; otherwise nothing is stored and the request fails with 409.
+fn apply(db: &SharedStore, tenant: &Tenant, changes: &[&Change], now: i64) -> Result<(), ApiError> {
+    fn failed<E>(message: &'static str) -> impl Fn(E) -> ApiError {
+        move |_| ApiError::Internal(message)
+    }
+    let changes: Vec<&Change> = changes
+        .iter()
+        .flat_map(|change| change.with_follow_ons())
+        .collect();
+    let pairs: Vec<(Option<Item>, Item)> = changes
+        .iter()
+        .map(|change| match change {
+            Change::Update { old, new, .. } => (Some(Item::clone(old)), new.clone()),
+            Change::Create(item) | Change::Delete { item, .. } => (None, item.clone()),
+        })
+        .collect();
+    let unblocked: Vec<Change> = dependencies::unblocked(db, &pairs)
+        .into_iter()
+        .filter_map(|(old, new)| {
+            Some(Change::Update {
+                old: Box::new(old?),
+                new,
+                follow_ons: Vec::new(),
+            })
+        })
+        .collect();
+    let changes: Vec<&Change> = changes.iter().copied().chain(&unblocked).collect();
+    let mut ops = Vec::new();
+    let mut trashed = Vec::new();
+    let mut entries = Vec::new();
+    let mut index_ops = IndexOps::default();
+    let mut revision_ops = Vec::new();
+    let insert = |item: &Item| -> Result<BatchOp, ApiError> {
+        let bytes = codec::encode(db.encoding(), item).map_err(failed("Serialization failed"))?;
+        Ok(BatchOp::Insert(item.id.as_bytes().to_vec(), bytes))
+    };
+    for &change in &changes {
+        let action = match change {
+            Change::Create(item) => {
+                ops.push(insert(item)?);
+                index_ops.add(None, Some(item));
+                Action::Create
+            }
+            Change::Update { old, new, .. } => {
+                let op =
+                    revisions::op(db, old, now).map_err(failed("Failed to record revision"))?;
+                revision_ops.push(op);
+                ops.push(insert(new)?);
+                index_ops.add(Some(old), Some(new));
+                Action::Update
+            }
+            Change::Delete { item, .. } => {
+                ops.push(BatchOp::Remove(item.id.as_bytes().to_vec()));
+                let trash_op = trash::trash_op(db, item, now);
+                trashed.push(trash_op.map_err(failed("Serialization failed"))?);
+                index_ops.add(Some(item), None);
+                Action::Delete
+            }
+        };
+        entries.push(audit::Entry::new(tenant, action, change.id(), now));
+    }
+    let mut sides = vec![
+        (trash::TRASH_TREE, trashed),
+        (revisions::REVISION_TREE, revision_ops),
+    ];
+    sides.extend(index_ops.into_sides());
+    let checks: Vec<(&[u8], Box<Check>)> = changes
+        .iter()
+        .map(|change| (change.id().as_bytes(), still_at(change.version())))
+        .collect();
+    let guards: Vec<Guard> = checks
+        .iter()
+        .map(|(key, check)| (*key, check.as_ref()))
+        .collect();
+    let applied = audit::commit_if(db, &guards, ops, sides, &entries)
+        .map_err(failed("Failed to apply batch"))?;
+    if !applied {
+        return Err(changed_meanwhile());
+    }
+
+    for change in changes {
+        if let Change::Delete { item, .. } = change {
+            links::strip_links(db, tenant, &item.id, now)
+                .map_err(failed("Failed to clean up links"))?;
+        }
+    }
+    Ok(())
+}
+
+/// `POST /items/batch`: applies every row of the body array. A row is either
+/// a create body or an operation: `{"op": "create", "item": {..}}`,
+/// `{"op": "update", "id": .., "changes": {..}}` or `{"op": "delete", "id": ..}`.
+/// Deleted items go to the trash, as with `DELETE /items/{id}`, and their
+/// subtasks are handled as the row's `children` says. Update and delete rows
+/// may carry an `if_match`, refused with 412 like a stale `If-Match` header.
+///
+/// By default the batch is atomic: any failing row fails the whole request
+/// with 422 and the failing rows' outcomes, and nothing is stored. Otherwise
+/// the response holds each row's item, or for a delete the item as it was;
+/// it is a 201 when anything was created. With `?atomic=false` the valid rows
+/// are stored and the response is a 207 with one outcome per row, so a client
+/// can resend just the failures.
+pub async fn create_batch(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    config: web::Data<Config>,
+    query: web::Query<BatchQuery>,
+    rows: web::Json<Vec<Value>>,
+) -> Result<HttpResponse, ApiError> {
+    let now = clock.now_millis();
+    let cx = Context {
+        db: &db,
+        ids: &ids,
+        config: &config,
+        now,
+    };
+    let mut touched = HashSet::new();
+    let prepared: Vec<Result<Change, Outcome>> = rows
+        .into_inner()
+        .into_iter()
+        .enumerate()
+        .map(|(index, row)| prepare(row, index, &cx, &mut touched))
+        .collect();
+
+    if query.atomic && prepared.iter().any(Result::is_err) {
+        let failures: Vec<Outcome> = prepared.into_iter().filter_map(Result::err).collect();
+        return Err(ApiError::Unprocessable {
+            message: "Some rows of the batch failed; nothing was stored".to_string(),
+            details: json!(failures),
+        });
+    }
+
+    let changes: Vec<&Change> = prepared.iter().flatten().collect();
+    apply(&db, &tenant, &changes, now)?;
+
+    if query.atomic {
+        let created = changes
+            .iter()
+            .any(|change| matches!(change, Change::Create(_)));
+        let items: Vec<Item> = prepared
+            .into_iter()
+            .flatten()
+            .map(Change::into_item)
+            .collect();
+        return Ok(match created {
+            true => HttpResponse::Created().json(items),
+            false => HttpResponse::Ok().json(items),
+        });
+    }
+    let outcomes: Vec<Outcome> = prepared
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| match result {
+            Ok(change) => Outcome {
+                index,
+                status: change.status(),
+                id: Some(change.into_item().id),
+                error: None,
+            },
+            Err(outcome) => outcome,
+        })
+        .collect();
+    Ok(HttpResponse::MultiStatus().json(outcomes))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetManyPayload {
+    ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Found {
+    items: Vec<Item>,
+    /// Requested IDs with no stored item, in request order.
+    missing: Vec<String>,
+}
+
+/// `POST /items/get-many`: looks up each ID in the body and returns the items
+/// found, in request order, along with the IDs that weren't.
+pub async fn get_many(
+    db: TenantStore,
+    payload: web::Json<GetManyPayload>,
+) -> Result<HttpResponse, ApiError> {
+    let ids = payload.into_inner().ids;
+    if ids.len() > MAX_GET_MANY {
+        return Err(ApiError::BadRequest(format!(
+            "At most {MAX_GET_MANY} ids may be fetched at once"
+        )));
+    }
+
+    let db = db.into_inner();
+    let found = web::block(move || {
+        let mut found = Found {
+            items: Vec::new(),
+            missing: Vec::new(),
+        };
+        for id in ids {
+            match db.get(id.as_bytes())? {
+                Some(raw) => match codec::decode::<Item>(&raw) {
+                    Ok(item) => found.items.push(item),
+                    Err(_) => found.missing.push(id),
+                },
+                None => found.missing.push(id),
+            }
+        }
+        Ok::<_, StoreError>(found)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Encoding, commit_items, store::MemoryStore};
+    use std::sync::Arc;
+
+    #[test]
+    fn a_batch_overtaken_after_its_checks_stores_nothing() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let tenant = Tenant(None);
+        let item: Item = serde_json::from_value(json!({
+            "id": "a", "type": "note", "title": "a", "content": null, "tags": [],
+            "code_location": null, "created_at": 0, "completed": null,
+            "due_date": null, "start_time": null, "end_time": null,
+        }))
+        .unwrap();
+        assert!(commit_items(&db, &tenant, &[(None, item.clone())], Vec::new()).unwrap());
+        let mut fresh = item.clone();
+        fresh.id = "b".to_string();
+
+        // Checked against version 1, then another request edits `a` before
+        // the batch is written.
+        let stale = Change::Update {
+            old: Box::new(item.clone()),
+            new: Item {
+                title: "stale".to_string(),
+                ..item.clone()
+            },
+            follow_ons: Vec::new(),
+        };
+        let create = Change::Create(fresh);
+        let mut edited = item.clone();
+        edited.touch(1);
+        assert!(commit_items(&db, &tenant, &[(Some(item), edited)], Vec::new()).unwrap());
+
+        let result = apply(&db, &tenant, &[&create, &stale], 2);
+        assert!(matches!(result, Err(ApiError::Conflict(_))));
+        assert!(db.get(b"b").unwrap().is_none());
+        assert_eq!(load_item(&db, "a").unwrap().title, "a");
+    }
+}
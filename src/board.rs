@@ -0,0 +1,79 @@
+//! A kanban board: the listing's items grouped into one column per status.
+
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{
+    clock::SharedClock,
+    config::Config,
+    error::ApiError,
+    filter::{self, ItemFilter, Sort},
+    tenant::TenantStore,
+    time::{TimeFormat, TimeView},
+    Item,
+};
+
+#[derive(Serialize)]
+struct Column {
+    status: String,
+    items: Vec<TimeView<Item>>,
+}
+
+/// The column `item` belongs in. Completed items are done whatever their
+/// status says, and items without one start in the first column.
+fn column_of<'a>(item: &'a Item, config: &'a Config) -> &'a str {
+    if item.completed == Some(true) {
+        config.done_status()
+    } else {
+        item.status.as_deref().unwrap_or(&config.statuses[0])
+    }
+}
+
+/// `GET /board`: one column per configured status, in order, holding the
+/// items the listing's filters select, such as `?project=`, sorted as
+/// `?sort=` asks. Statuses no longer configured get columns of their own
+/// after the rest, so nothing goes missing.
+pub async fn board(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let (filter, sort, time) = ItemFilter::from_query(&query, clock.now_millis())
+        .and_then(|filter| {
+            let sort = Sort::from_query(&query)?;
+            let time = TimeFormat::from_query(&query)?;
+            Ok((filter, sort, time))
+        })
+        .map_err(ApiError::BadRequest)?;
+
+    let mut items = filter::scan_blocking(&db, filter).await?;
+    if let Some(sort) = sort {
+        sort.apply(&mut items);
+    }
+
+    let mut columns: Vec<Column> = config
+        .statuses
+        .iter()
+        .map(|status| Column {
+            status: status.clone(),
+            items: Vec::new(),
+        })
+        .collect();
+    for item in items {
+        let status = column_of(&item, &config).to_string();
+        let index = match columns.iter().position(|c| c.status == status) {
+            Some(index) => index,
+            None => {
+                columns.push(Column {
+                    status,
+                    items: Vec::new(),
+                });
+                columns.len() - 1
+            }
+        };
+        columns[index].items.push(time.view(item));
+    }
+    Ok(HttpResponse::Ok().json(columns))
+}
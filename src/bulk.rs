@@ -0,0 +1,155 @@
+//! Edits applied to every item a filter selects, for cleanups that would
+//! otherwise take one request per item.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+
+use crate::{
+    clock::SharedClock,
+    complete,
+    config::Config,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    ids::SharedIdGenerator,
+    save_items,
+    tenant::{Tenant, TenantStore},
+    validation, Item, UpdateItemPayload,
+};
+
+/// Query parameters `POST /items/bulk-update` accepts: the listing's filters
+/// and `dry_run`. Anything else is refused rather than ignored, since an
+/// ignored filter would widen the update.
+const BULK_PARAMS: &[&str] = &[
+    "type",
+    "tags",
+    "tags_mode",
+    "tags_any",
+    "hierarchical",
+    "missing",
+    "updated_after",
+    "updated_before",
+    "created_after",
+    "created_before",
+    "due_after",
+    "due_before",
+    "start_between",
+    "priority",
+    "completed",
+    "overdue",
+    "blocked",
+    "project",
+    "include_archived",
+    "include_snoozed",
+    "q",
+    "dry_run",
+];
+
+#[derive(Debug, Deserialize)]
+pub struct BulkUpdatePayload {
+    #[serde(default)]
+    add_tags: Vec<String>,
+    #[serde(default)]
+    remove_tags: Vec<String>,
+    /// Fields to set, merged as in `PATCH /items/{id}`.
+    set: Option<UpdateItemPayload>,
+}
+
+#[derive(Debug, Serialize)]
+struct BulkResult {
+    /// Items changed, or with `dry_run` the items that would be.
+    ids: Vec<String>,
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Rejected {
+    id: String,
+    errors: Vec<validation::FieldError>,
+}
+
+/// `POST /items/bulk-update?{filter}`: applies the body's edits to every item
+/// the listing filter in the query selects, all in one batch. Items the edits
+/// leave as they were are skipped. With `dry_run=true` nothing is written and
+/// the response names the items that would change. If any edited item fails
+/// validation, nothing is written and the failures come back as a 422.
+/// Repeating items the edits complete roll forward as they would one at a
+/// time, their next occurrences created in the same batch.
+pub async fn bulk_update(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    config: web::Data<Config>,
+    query: web::Query<HashMap<String, String>>,
+    payload: web::Json<BulkUpdatePayload>,
+) -> Result<HttpResponse, ApiError> {
+    filter::reject_unknown_params(&query, BULK_PARAMS).map_err(ApiError::BadRequest)?;
+    if query.keys().all(|key| key == "dry_run") {
+        return Err(ApiError::BadRequest(
+            "A bulk update needs at least one filter".to_string(),
+        ));
+    }
+    let now = clock.now_millis();
+    let filter = ItemFilter::from_query(&query, now).map_err(ApiError::BadRequest)?;
+    let dry_run = match query.get("dry_run").map(String::as_str) {
+        None | Some("false") => false,
+        Some("true") => true,
+        Some(other) => {
+            return Err(ApiError::BadRequest(format!(
+                "Invalid value '{other}' for dry_run"
+            )))
+        }
+    };
+
+    let items = filter::scan_blocking(&db, filter).await?;
+    let remove_tags = validation::normalize_tags(&payload.remove_tags);
+    let mut changes: Vec<(Option<Item>, Item)> = Vec::new();
+    let mut next_occurrences = Vec::new();
+    let mut rejected = Vec::new();
+    for old in items {
+        let mut item = old.clone();
+        if let Some(set) = &payload.set {
+            item.apply_update(set);
+        }
+        item.tags.extend(payload.add_tags.iter().cloned());
+        item.tags = validation::normalize_tags(&item.tags);
+        item.tags.retain(|tag| !remove_tags.contains(tag));
+        let checked = validation::validate_item(&mut item, &config)
+            .map_err(ApiError::Invalid)
+            .and_then(|()| validation::check_references(&db, &item));
+        match checked {
+            Ok(()) => {}
+            Err(ApiError::Invalid(errors)) => {
+                rejected.push(Rejected {
+                    id: item.id,
+                    errors,
+                });
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        if serde_json::to_value(&item).ok() == serde_json::to_value(&old).ok() {
+            continue;
+        }
+        item.touch(now);
+        if let Some(next) = complete::roll_forward(&old, &mut item, &ids, now) {
+            next_occurrences.push((None, next));
+        }
+        changes.push((Some(old), item));
+    }
+
+    if !rejected.is_empty() {
+        return Err(ApiError::Unprocessable {
+            message: "Some matching items would fail validation; nothing was changed".to_string(),
+            details: json!(rejected),
+        });
+    }
+    let ids = changes.iter().map(|(_, item)| item.id.clone()).collect();
+    changes.extend(next_occurrences);
+    if !dry_run {
+        save_items(&db, &tenant, &changes, Vec::new())?;
+    }
+    Ok(HttpResponse::Ok().json(BulkResult { ids, dry_run }))
+}
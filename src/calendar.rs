@@ -0,0 +1,45 @@
+//! A month calendar: the agenda's entries laid out over every day of one
+//! month, so clients drawing a month grid need no date math of their own.
+
+use actix_web::{web, HttpResponse};
+use chrono::{Months, NaiveDate};
+use serde::Serialize;
+
+use crate::{
+    agenda::{self, Day, Range},
+    clock::SharedClock,
+    error::ApiError,
+    tenant::TenantStore,
+    tz::TzQuery,
+};
+
+#[derive(Serialize)]
+struct Month {
+    year: i32,
+    month: u32,
+    days: Vec<Day>,
+}
+
+/// `GET /calendar/{year}/{month}`: each day of the month in `?tz=`, with
+/// the items due on it and the events running on it in time order. Events
+/// spanning several days show on each, clipped to the month.
+pub async fn month(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    path: web::Path<(i32, u32)>,
+    query: web::Query<TzQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let (year, month) = path.into_inner();
+    let zone = query.zone().map_err(ApiError::BadRequest)?;
+    let first = NaiveDate::from_ymd_opt(year, month, 1)
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid month {year}-{month}")))?;
+    let last = first
+        .checked_add_months(Months::new(1))
+        .and_then(|next| next.pred_opt())
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid month {year}-{month}")))?;
+    let range = Range::new(first, last, zone)?;
+
+    let items = agenda::scheduled(&db, clock.now_millis()).await?;
+    let days = range.days(&items);
+    Ok(HttpResponse::Ok().json(Month { year, month, days }))
+}
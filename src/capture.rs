@@ -0,0 +1,106 @@
+use crate::{store::StoreResult, SharedStore};
+use sha2::{Digest, Sha256};
+
+/// Index of recent capture hashes, used to collapse repeated captures.
+pub const HASH_TREE: &str = "capture_hashes";
+
+pub fn hash_tree(db: &SharedStore) -> StoreResult<SharedStore> {
+    db.tree(HASH_TREE)
+}
+
+/// Hashes the capture text after collapsing runs of whitespace, so trailing
+/// newlines or double spaces from a script don't defeat deduplication.
+pub fn capture_hash(text: &str) -> String {
+    let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// One unit of a capture line.
+#[derive(Debug, PartialEq)]
+pub enum Token {
+    /// A whitespace-delimited word, taken as-is.
+    Word(String),
+    /// A `"double quoted"` segment, quotes removed.
+    Quoted(String),
+    /// A `key:"quoted value"` pair.
+    Field { key: String, value: String },
+}
+
+fn is_field_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Splits a capture line on whitespace, keeping `"quoted segments"` and
+/// `key:"quoted values"` together. A quote that is never closed is treated
+/// as an ordinary character, so unbalanced input splits as plain words.
+pub fn tokenize(line: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+
+    while !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..end];
+
+        if let Some(after) = rest.strip_prefix('"') {
+            if let Some(close) = after.find('"') {
+                tokens.push(Token::Quoted(after[..close].to_string()));
+                rest = after[close + 1..].trim_start();
+                continue;
+            }
+        } else if let Some((key, _)) = word.split_once(":\"") {
+            let after = &rest[key.len() + 2..];
+            if let (true, Some(close)) = (is_field_key(key), after.find('"')) {
+                tokens.push(Token::Field {
+                    key: key.to_string(),
+                    value: after[..close].to_string(),
+                });
+                rest = after[close + 1..].trim_start();
+                continue;
+            }
+        }
+
+        tokens.push(Token::Word(word.to_string()));
+        rest = rest[end..].trim_start();
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(s: &str) -> Token {
+        Token::Word(s.into())
+    }
+
+    #[test]
+    fn keeps_quoted_segments_and_fields_together() {
+        assert_eq!(
+            tokenize(r#"#todo "call the bank" due:"next friday" soon"#),
+            vec![
+                word("#todo"),
+                Token::Quoted("call the bank".into()),
+                Token::Field {
+                    key: "due".into(),
+                    value: "next friday".into(),
+                },
+                word("soon"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unbalanced_quotes_split_as_plain_words() {
+        assert_eq!(
+            tokenize(r#"say "hello world #note"#),
+            vec![word("say"), word("\"hello"), word("world"), word("#note")]
+        );
+        assert_eq!(
+            tokenize(r#"due:"tomorrow morning"#),
+            vec![word("due:\"tomorrow"), word("morning")]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+/// Source of the current time, injected so time-dependent behavior can be
+/// driven deterministically.
+pub trait Clock: Send + Sync {
+    /// Milliseconds since the Unix epoch.
+    fn now_millis(&self) -> i64;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The host's wall clock.
+#[derive(Default)]
+pub struct SystemClock {
+    /// Most recent reading, reused if the clock ever reports a time before
+    /// the epoch.
+    last: AtomicI64,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(elapsed) => {
+                let now = elapsed.as_millis() as i64;
+                self.last.store(now, Ordering::Relaxed);
+                now
+            }
+            Err(_) => {
+                let last = self.last.load(Ordering::Relaxed);
+                eprintln!("System clock is before the Unix epoch; using last known time {last}");
+                last
+            }
+        }
+    }
+}
+
+/// Clock that only moves when told to.
+#[cfg(test)]
+pub struct FakeClock(AtomicI64);
+
+#[cfg(test)]
+impl FakeClock {
+    pub fn new(now_millis: i64) -> Self {
+        FakeClock(AtomicI64::new(now_millis))
+    }
+
+    pub fn advance(&self, millis: i64) {
+        self.0.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now_millis(&self) -> i64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fake_clock_only_moves_when_advanced() {
+        let clock = FakeClock::new(1_000);
+        assert_eq!(clock.now_millis(), 1_000);
+        assert_eq!(clock.now_millis(), 1_000);
+        clock.advance(250);
+        assert_eq!(clock.now_millis(), 1_250);
+    }
+}
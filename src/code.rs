@@ -0,0 +1,84 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    error::ApiError,
+    filter::{self, ItemFilter},
+    tenant::TenantStore,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ByCodeQuery {
+    file: String,
+    /// Treat `file` as a directory and match everything beneath it.
+    #[serde(default)]
+    prefix: bool,
+}
+
+/// Canonical form of a source path for comparison: forward slashes, no `.`
+/// segments, no empty segments, no trailing slash.
+fn normalize_path(path: &str) -> String {
+    path.trim()
+        .replace('\\', "/")
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn path_matches(path: &str, wanted: &str, prefix: bool) -> bool {
+    if !prefix {
+        return path == wanted;
+    }
+    wanted.is_empty()
+        || path == wanted
+        || path
+            .strip_prefix(wanted)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// `GET /items/by-code?file=`: items whose code location points into the
+/// given file (or, with `prefix=true`, directory), ordered by file then line.
+pub async fn by_code(
+    db: TenantStore,
+    query: web::Query<ByCodeQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let wanted = normalize_path(&query.file);
+    let prefix = query.prefix;
+
+    let items = filter::scan_blocking(&db, ItemFilter::default()).await?;
+    let mut located: Vec<_> = items
+        .into_iter()
+        .filter_map(|item| {
+            let location = item.code_location.as_ref()?;
+            let path = normalize_path(&location.file_path);
+            let line = location.line_number;
+            path_matches(&path, &wanted, prefix).then_some((path, line, item))
+        })
+        .collect();
+    located.sort_by(|(a_path, a_line, _), (b_path, b_line, _)| {
+        a_path.cmp(b_path).then(a_line.cmp(b_line))
+    });
+
+    let items: Vec<_> = located.into_iter().map(|(_, _, item)| item).collect();
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_relative_and_redundant_segments() {
+        assert_eq!(normalize_path("./src/main.rs"), "src/main.rs");
+        assert_eq!(normalize_path("src//./main.rs"), "src/main.rs");
+        assert_eq!(normalize_path("src\\bin\\"), "src/bin");
+    }
+
+    #[test]
+    fn prefix_matches_whole_directories_only() {
+        assert!(path_matches("src/main.rs", "src", true));
+        assert!(!path_matches("srcfoo/main.rs", "src", true));
+        assert!(!path_matches("src/main.rs", "src", false));
+    }
+}
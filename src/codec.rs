@@ -0,0 +1,173 @@
+//! How records are laid out in the store. Every value written starts with a
+//! one-byte tag naming its format, so a keyspace can hold a mix of encodings
+//! while it is being migrated. Values from before tagging are bare JSON.
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+
+use crate::{
+    access, audit, capture, idempotency, projects, reminders, revisions, store::BatchOp,
+    store::StoreResult, tags, templates, tenant, trash, views, SharedStore,
+};
+
+/// Prefix of JSON records, version 1.
+const JSON_TAG: u8 = 0x01;
+/// Prefix of MessagePack records, version 1.
+const MSGPACK_TAG: u8 = 0x02;
+
+/// Side trees holding serialized records, as opposed to raw bytes such as
+/// attachment blobs. Migration rewrites these along with the item keyspace.
+const RECORD_TREES: &[&str] = &[
+    audit::AUDIT_TREE,
+    access::ACCESS_TREE,
+    capture::HASH_TREE,
+    idempotency::KEY_TREE,
+    projects::PROJECT_TREE,
+    reminders::REMINDER_TREE,
+    revisions::REVISION_TREE,
+    tags::META_TREE,
+    templates::TEMPLATE_TREE,
+    trash::TRASH_TREE,
+    views::VIEW_TREE,
+];
+
+/// On-disk format for new writes. Selected with `STORAGE_ENCODING`; reads
+/// accept either regardless.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+#[derive(Debug)]
+pub struct CodecError(String);
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "codec error: {}", self.0)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Serializes `value` in `encoding`, tag byte first.
+pub fn encode<T: Serialize + ?Sized>(encoding: Encoding, value: &T) -> Result<Vec<u8>, CodecError> {
+    let body = match encoding {
+        Encoding::Json => serde_json::to_vec(value).map_err(|e| CodecError(e.to_string()))?,
+        Encoding::MessagePack => {
+            rmp_serde::to_vec_named(value).map_err(|e| CodecError(e.to_string()))?
+        }
+    };
+    let mut bytes = Vec::with_capacity(body.len() + 1);
+    bytes.push(tag(encoding));
+    bytes.extend(body);
+    Ok(bytes)
+}
+
+/// Deserializes a stored value in whichever encoding it was written.
+pub fn decode<T: DeserializeOwned>(raw: &[u8]) -> Result<T, CodecError> {
+    match raw.split_first() {
+        Some((&MSGPACK_TAG, body)) => {
+            rmp_serde::from_slice(body).map_err(|e| CodecError(e.to_string()))
+        }
+        Some((&JSON_TAG, body)) => {
+            serde_json::from_slice(body).map_err(|e| CodecError(e.to_string()))
+        }
+        _ => serde_json::from_slice(raw).map_err(|e| CodecError(e.to_string())),
+    }
+}
+
+fn tag(encoding: Encoding) -> u8 {
+    match encoding {
+        Encoding::Json => JSON_TAG,
+        Encoding::MessagePack => MSGPACK_TAG,
+    }
+}
+
+/// Rewrites every record in `db` that isn't already in `encoding`. Values
+/// that don't decode are left for the integrity scan. Returns how many were
+/// rewritten.
+fn migrate_tree(db: &SharedStore, encoding: Encoding) -> StoreResult<usize> {
+    let mut ops = Vec::new();
+    for entry in db.iter() {
+        let (key, raw) = entry?;
+        if raw.first() == Some(&tag(encoding)) {
+            continue;
+        }
+        let Ok(value) = decode::<serde_json::Value>(&raw) else {
+            continue;
+        };
+        if let Ok(bytes) = encode(encoding, &value) {
+            ops.push(BatchOp::Insert(key, bytes));
+        }
+    }
+    let rewritten = ops.len();
+    db.batch(ops)?;
+    Ok(rewritten)
+}
+
+/// Rewrites the items and record trees of the default keyspace and of every
+/// tenant in `encoding`. Returns how many records were rewritten.
+pub fn migrate(db: &SharedStore, tenant_labels: &[&str], encoding: Encoding) -> StoreResult<usize> {
+    let mut keyspaces = vec![db.clone()];
+    for label in tenant_labels {
+        keyspaces.push(tenant::tenant_store(db, label)?);
+    }
+
+    let mut rewritten = 0;
+    for keyspace in keyspaces {
+        rewritten += migrate_tree(&keyspace, encoding)?;
+        for name in RECORD_TREES {
+            rewritten += migrate_tree(&keyspace.tree(name)?, encoding)?;
+        }
+    }
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    #[test]
+    fn round_trips_in_both_encodings() {
+        let value = json!({"id": "a", "tags": ["x"], "due_date": null, "created_at": 5});
+        for encoding in [Encoding::Json, Encoding::MessagePack] {
+            let bytes = encode(encoding, &value).unwrap();
+            assert_eq!(bytes[0], tag(encoding));
+            assert_eq!(decode::<Value>(&bytes).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn reads_untagged_json() {
+        let decoded: Value = decode(br#"{"id": "legacy"}"#).unwrap();
+        assert_eq!(decoded, json!({"id": "legacy"}));
+    }
+
+    #[test]
+    fn migration_rewrites_legacy_and_json_records() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        db.insert(b"legacy", br#"{"id": "legacy"}"#.to_vec())
+            .unwrap();
+        db.insert(
+            b"tagged",
+            encode(Encoding::Json, &json!({"id": "tagged"})).unwrap(),
+        )
+        .unwrap();
+        db.tree(tags::META_TREE)
+            .unwrap()
+            .insert(b"rust", br#"{"color": "red"}"#.to_vec())
+            .unwrap();
+
+        assert_eq!(migrate(&db, &[], Encoding::MessagePack).unwrap(), 3);
+        for key in [&b"legacy"[..], b"tagged"] {
+            let raw = db.get(key).unwrap().unwrap();
+            assert_eq!(raw[0], MSGPACK_TAG);
+        }
+        assert_eq!(migrate(&db, &[], Encoding::MessagePack).unwrap(), 0);
+    }
+}
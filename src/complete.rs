@@ -0,0 +1,96 @@
+use actix_web::{http::header::LOCATION, web, HttpResponse};
+
+use crate::{
+    clock::SharedClock,
+    error::ApiError,
+    ids::SharedIdGenerator,
+    load_item, recurrence, save_item, save_items,
+    tenant::{Tenant, TenantStore},
+    Item,
+};
+
+/// Hands a repeating item's rule on when a write completes it: clears the
+/// `recurrence` of `item`, changed from `old` and stored at `now`, and
+/// returns a new item for the next occurrence, to be stored with it. Every
+/// write that can complete an item calls this, so a series rolls forward
+/// however its current occurrence is marked done.
+pub fn roll_forward(
+    old: &Item,
+    item: &mut Item,
+    ids: &SharedIdGenerator,
+    now: i64,
+) -> Option<Item> {
+    if old.completed == Some(true) || item.completed != Some(true) || item.recurrence.is_none() {
+        return None;
+    }
+    // The next occurrence starts out as unmarked as this one was.
+    let next = recurrence::next(item, ids.generate(&item.title, now), now).map(|next| Item {
+        completed: old.completed,
+        ..next
+    });
+    item.recurrence = None;
+    next
+}
+
+/// The `Location` header naming the next occurrence [`roll_forward`] made.
+pub fn location(next: &Item) -> (actix_web::http::header::HeaderName, String) {
+    (LOCATION, format!("/items/{}", next.id))
+}
+
+fn set_completed(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    id: &str,
+    completed: bool,
+) -> Result<HttpResponse, ApiError> {
+    let mut item = load_item(&db, id)?;
+    if item.completed != Some(completed) {
+        item.completed = Some(completed);
+        item.touch(clock.now_millis());
+        save_item(&db, &tenant, &item)?;
+    }
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// `POST /items/{id}/complete`: marks the item done and records when.
+/// Completing a completed item changes nothing, so `completed_at` keeps the
+/// first completion time. A repeating item hands its rule on to a new item
+/// for the next occurrence, named by the `Location` header.
+pub async fn complete(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let old = load_item(&db, &path.into_inner())?;
+    if old.completed == Some(true) {
+        return Ok(HttpResponse::Ok().json(old));
+    }
+    let now = clock.now_millis();
+    let mut item = old.clone();
+    item.completed = Some(true);
+    item.touch(now);
+    let next = roll_forward(&old, &mut item, &ids, now);
+
+    let mut changes = vec![(Some(old), item.clone())];
+    changes.extend(next.iter().map(|next| (None, next.clone())));
+    save_items(&db, &tenant, &changes, Vec::new())?;
+    let mut res = HttpResponse::Ok();
+    if let Some(next) = &next {
+        res.insert_header(location(next));
+    }
+    Ok(res.json(item))
+}
+
+/// `POST /items/{id}/uncomplete`: marks the item not done and clears
+/// `completed_at`.
+pub async fn uncomplete(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    set_completed(db, tenant, clock, &path.into_inner(), false)
+}
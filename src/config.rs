@@ -0,0 +1,341 @@
+use std::{collections::HashMap, env, fmt::Debug, fs, str::FromStr};
+
+use crate::{codec::Encoding, ids::IdStrategy, rules, types::ItemType};
+
+/// Where items are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreBackend {
+    /// The sled database on disk.
+    Sled,
+    /// A volatile in-process map; everything is lost on restart.
+    Memory,
+}
+
+/// Size limits every stored item must respect.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_tags: usize,
+    pub max_title_chars: usize,
+    /// Size of an item's content, in bytes of UTF-8.
+    pub max_content_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_tags: 50,
+            max_title_chars: 500,
+            max_content_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// What capture does with a first line carrying more tags than allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagOverflow {
+    /// Fail validation like any other write.
+    Reject,
+    /// Keep the first `max_tags` tags and drop the rest.
+    Truncate,
+}
+
+/// How captured items get their IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureId {
+    /// Minted by `ID_STRATEGY`, like any other new item.
+    Uuid,
+    /// The SHA-256 of the whitespace-normalized capture text, so capturing
+    /// the same text again returns the item it created, as edited since,
+    /// instead of adding a second one. Distinct texts would only share an ID through a SHA-256
+    /// collision.
+    Hash,
+}
+
+/// Where the reminder scheduler sends reminders that come due.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifierKind {
+    /// Print them to stdout.
+    Log,
+    /// POST them as JSON to this `http://` URL.
+    Webhook(String),
+}
+
+/// Runtime settings, read once from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub api_key: String,
+    /// Additional `(label, key)` pairs, each scoped to its own namespace.
+    pub tenant_keys: Vec<(String, String)>,
+    pub store: StoreBackend,
+    /// Format records are written in. Switching to MessagePack rewrites
+    /// existing records at startup.
+    pub storage_encoding: Encoding,
+    /// How IDs for new items are generated.
+    pub id_strategy: IdStrategy,
+    pub limits: Limits,
+    /// Item types accepted besides the built-in note, task and event.
+    pub custom_types: Vec<ItemType>,
+    /// Board columns an item's `status` may name, in order. The last one is
+    /// the done column.
+    pub statuses: Vec<String>,
+    pub capture_tag_overflow: TagOverflow,
+    pub capture_id: CaptureId,
+    /// Type given to captures whose tags don't select one.
+    pub capture_default_type: ItemType,
+    /// Capture tags (lowercase, without `#`) that select an item type.
+    pub capture_type_tags: HashMap<String, ItemType>,
+    /// Return the existing item when the same text is captured twice in a row.
+    pub dedup_capture: bool,
+    /// How long, in seconds, a capture counts as a duplicate of an earlier one.
+    pub dedup_window_secs: u64,
+    /// How long, in seconds, an `Idempotency-Key` replays the item it created.
+    pub idempotency_ttl_secs: u64,
+    /// Largest single attachment upload accepted, in bytes.
+    pub max_attachment_bytes: u64,
+    /// Hold appends to an item for this many milliseconds and write them
+    /// together. Every append is written immediately when unset.
+    pub append_coalesce_ms: Option<u64>,
+    /// Buffered append size, in bytes, that forces an immediate write.
+    pub append_flush_bytes: usize,
+    pub reminder_notifier: NotifierKind,
+    /// How often, in seconds, the scheduler looks for reminders to fire.
+    pub reminder_interval_secs: u64,
+    /// Count reads of single items for the frequently-used view.
+    pub track_access: bool,
+    /// Reject unknown listing query parameters unless a request passes
+    /// `strict=false`.
+    pub strict_query: bool,
+    /// Refuse every request that would change stored data.
+    pub read_only: bool,
+    /// Check every stored record at startup and quarantine unreadable ones.
+    pub scan_on_start: bool,
+    /// HTTP worker threads; actix starts one per core when unset.
+    pub workers: Option<usize>,
+    /// Keep-alive duration for idle connections, in seconds.
+    pub keep_alive_secs: Option<u64>,
+    /// How long a client may take to send request headers, in milliseconds.
+    pub client_timeout_ms: Option<u64>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let config = Config {
+            api_key: env::var("API_KEY").unwrap_or_else(|_| "secret".into()),
+            tenant_keys: env::var("API_KEYS")
+                .map(|raw| parse_tenant_keys(&raw))
+                .unwrap_or_default(),
+            store: match env::var("NEONOTE_STORE").as_deref() {
+                Ok("memory") => StoreBackend::Memory,
+                Ok("sled") | Err(_) => StoreBackend::Sled,
+                Ok(other) => panic!("NEONOTE_STORE must be 'sled' or 'memory', got '{other}'"),
+            },
+            storage_encoding: match env::var("STORAGE_ENCODING").as_deref() {
+                Ok("json") | Err(_) => Encoding::Json,
+                Ok("msgpack") => Encoding::MessagePack,
+                Ok(other) => panic!("STORAGE_ENCODING must be 'json' or 'msgpack', got '{other}'"),
+            },
+            id_strategy: match env::var("ID_STRATEGY").as_deref() {
+                Ok("uuid") | Err(_) => IdStrategy::Uuid,
+                Ok("ulid") => IdStrategy::Ulid,
+                Ok("short-slug") => IdStrategy::ShortSlug,
+                Ok(other) => {
+                    panic!("ID_STRATEGY must be 'uuid', 'ulid' or 'short-slug', got '{other}'")
+                }
+            },
+            limits: Limits {
+                max_tags: env_parse("MAX_TAGS", Limits::default().max_tags),
+                max_title_chars: env_parse("MAX_TITLE_CHARS", Limits::default().max_title_chars),
+                max_content_bytes: env_parse(
+                    "MAX_CONTENT_BYTES",
+                    Limits::default().max_content_bytes,
+                ),
+            },
+            custom_types: env::var("CUSTOM_TYPES")
+                .map(|raw| parse_custom_types(&raw))
+                .unwrap_or_default(),
+            statuses: env::var("STATUSES")
+                .map(|raw| parse_statuses(&raw))
+                .unwrap_or_else(|_| default_statuses()),
+            capture_tag_overflow: match env::var("CAPTURE_TAG_OVERFLOW").as_deref() {
+                Ok("reject") | Err(_) => TagOverflow::Reject,
+                Ok("truncate") => TagOverflow::Truncate,
+                Ok(other) => {
+                    panic!("CAPTURE_TAG_OVERFLOW must be 'reject' or 'truncate', got '{other}'")
+                }
+            },
+            capture_id: match env::var("CAPTURE_ID").as_deref() {
+                Ok("uuid") | Err(_) => CaptureId::Uuid,
+                Ok("hash") => CaptureId::Hash,
+                Ok(other) => panic!("CAPTURE_ID must be 'uuid' or 'hash', got '{other}'"),
+            },
+            capture_default_type: env::var("CAPTURE_DEFAULT_TYPE")
+                .map(ItemType::from)
+                .unwrap_or(ItemType::Note),
+            capture_type_tags: match env::var("CAPTURE_TYPE_MAP") {
+                Ok(path) => load_type_map(&path),
+                Err(_) => default_type_tags(),
+            },
+            dedup_capture: env_flag("DEDUP_CAPTURE"),
+            dedup_window_secs: env_parse("DEDUP_WINDOW_SECS", 300),
+            idempotency_ttl_secs: env_parse("IDEMPOTENCY_TTL_SECS", 24 * 60 * 60),
+            max_attachment_bytes: env_parse("MAX_ATTACHMENT_BYTES", 5 * 1024 * 1024),
+            append_coalesce_ms: env_parse_opt("APPEND_COALESCE_MS"),
+            append_flush_bytes: env_parse("APPEND_FLUSH_BYTES", 64 * 1024),
+            reminder_notifier: match env::var("REMINDER_NOTIFIER").as_deref() {
+                Ok("log") | Err(_) => NotifierKind::Log,
+                Ok("webhook") => NotifierKind::Webhook(env::var("REMINDER_WEBHOOK_URL").expect(
+                    "REMINDER_WEBHOOK_URL must be set when REMINDER_NOTIFIER is 'webhook'",
+                )),
+                Ok(other) => panic!("REMINDER_NOTIFIER must be 'log' or 'webhook', got '{other}'"),
+            },
+            reminder_interval_secs: env_parse("REMINDER_INTERVAL_SECS", 30),
+            track_access: env_flag("TRACK_ACCESS"),
+            strict_query: env_flag("STRICT_QUERY"),
+            read_only: env_flag("READ_ONLY"),
+            scan_on_start: env_flag("SCAN_ON_START"),
+            workers: env_parse_opt("NEONOTE_WORKERS"),
+            keep_alive_secs: env_parse_opt("NEONOTE_KEEP_ALIVE_SECS"),
+            client_timeout_ms: env_parse_opt("NEONOTE_CLIENT_TIMEOUT_MS"),
+        };
+
+        if !config.knows_type(&config.capture_default_type) {
+            panic!(
+                "CAPTURE_DEFAULT_TYPE must be a known item type, got '{}'",
+                config.capture_default_type
+            );
+        }
+        for (tag, item_type) in &config.capture_type_tags {
+            if !config.knows_type(item_type) {
+                panic!("CAPTURE_TYPE_MAP maps '#{tag}' to unknown item type '{item_type}'");
+            }
+        }
+        if config.statuses.is_empty() {
+            panic!("STATUSES must name at least one status");
+        }
+        if config.reminder_interval_secs == 0 {
+            panic!("REMINDER_INTERVAL_SECS must be at least 1");
+        }
+        if config.workers == Some(0) {
+            panic!("NEONOTE_WORKERS must be at least 1");
+        }
+        config
+    }
+
+    /// Whether items may be written with `item_type`.
+    pub fn knows_type(&self, item_type: &ItemType) -> bool {
+        item_type.is_known(&self.custom_types)
+    }
+
+    /// The status of the board's last column, which completed items sit in.
+    pub fn done_status(&self) -> &str {
+        self.statuses.last().map_or("done", String::as_str)
+    }
+
+    /// Every type items may be written with, built-in ones first.
+    pub fn known_types(&self) -> Vec<&str> {
+        rules::TYPE_RULES
+            .iter()
+            .map(|rules| rules.name)
+            .chain(self.custom_types.iter().map(ItemType::as_str))
+            .collect()
+    }
+}
+
+/// The built-in capture vocabulary, used when `CAPTURE_TYPE_MAP` is unset.
+pub fn default_type_tags() -> HashMap<String, ItemType> {
+    [
+        ("todo", ItemType::Task),
+        ("note", ItemType::Note),
+        ("event", ItemType::Event),
+    ]
+    .into_iter()
+    .map(|(tag, item_type)| (tag.to_string(), item_type))
+    .collect()
+}
+
+/// Reads a JSON object of `"tag": "type"` pairs, replacing the defaults.
+fn load_type_map(path: &str) -> HashMap<String, ItemType> {
+    let raw = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read CAPTURE_TYPE_MAP '{path}': {e}"));
+    let map: HashMap<String, String> = serde_json::from_str(&raw).unwrap_or_else(|e| {
+        panic!("CAPTURE_TYPE_MAP '{path}' is not a JSON object of strings: {e}")
+    });
+    map.into_iter()
+        .map(|(tag, item_type)| {
+            let tag = tag.trim().trim_start_matches('#').to_lowercase();
+            (tag, ItemType::from(item_type))
+        })
+        .collect()
+}
+
+/// Parses `CUSTOM_TYPES`, a comma-separated list of extra item type names.
+fn parse_custom_types(raw: &str) -> Vec<ItemType> {
+    let mut types: Vec<ItemType> = Vec::new();
+    for item_type in raw.split(',').map(ItemType::from) {
+        if !item_type.as_str().is_empty() && !types.contains(&item_type) {
+            types.push(item_type);
+        }
+    }
+    types
+}
+
+/// The board columns used when `STATUSES` is unset.
+pub fn default_statuses() -> Vec<String> {
+    ["backlog", "in-progress", "blocked", "done"]
+        .map(String::from)
+        .to_vec()
+}
+
+/// Parses `STATUSES`, a comma-separated list of board columns in order.
+fn parse_statuses(raw: &str) -> Vec<String> {
+    let mut statuses: Vec<String> = Vec::new();
+    for status in raw.split(',').map(|s| s.trim().to_lowercase()) {
+        if !status.is_empty() && !statuses.contains(&status) {
+            statuses.push(status);
+        }
+    }
+    statuses
+}
+
+/// Parses `API_KEYS`, a comma-separated list of `label:key` pairs.
+fn parse_tenant_keys(raw: &str) -> Vec<(String, String)> {
+    let mut keys: Vec<(String, String)> = Vec::new();
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let Some((label, key)) = entry.split_once(':') else {
+            panic!("API_KEYS entries must be 'label:key', got '{entry}'");
+        };
+        let (label, key) = (label.trim(), key.trim());
+        if label.is_empty() || key.is_empty() {
+            panic!("API_KEYS entries need both a label and a key, got '{entry}'");
+        }
+        if keys.iter().any(|(l, _)| l == label) {
+            panic!("API_KEYS label '{label}' is listed more than once");
+        }
+        keys.push((label.to_string(), key.to_string()));
+    }
+    keys
+}
+
+fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|v| v.eq_ignore_ascii_case("true") || v == "1")
+}
+
+fn env_parse<T>(name: &str, default: T) -> T
+where
+    T: FromStr,
+    T::Err: Debug,
+{
+    env_parse_opt(name).unwrap_or(default)
+}
+
+fn env_parse_opt<T>(name: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Debug,
+{
+    let raw = env::var(name).ok()?;
+    Some(
+        raw.parse()
+            .unwrap_or_else(|e| panic!("Invalid value for {name}: {e:?}")),
+    )
+}
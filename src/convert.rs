@@ -0,0 +1,74 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    clock::SharedClock,
+    config::Config,
+    error::ApiError,
+    load_item, save_item,
+    tenant::{Tenant, TenantStore},
+    time,
+    types::ItemType,
+    validation, Item,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ConvertPayload {
+    to: String,
+    #[serde(default, deserialize_with = "time::deserialize_opt_millis")]
+    due_date: Option<i64>,
+    #[serde(default, deserialize_with = "time::deserialize_opt_millis")]
+    start_time: Option<i64>,
+    #[serde(default, deserialize_with = "time::deserialize_opt_millis")]
+    end_time: Option<i64>,
+}
+
+/// Changes `item` to the requested type and reconciles the fields that only
+/// make sense for the old or new type. Returns what is missing on failure.
+fn convert(item: &mut Item, payload: &ConvertPayload) -> Result<(), String> {
+    let to = ItemType::from(payload.to.as_str());
+
+    if to == ItemType::Event {
+        let (Some(start), Some(end)) = (payload.start_time, payload.end_time) else {
+            return Err("Converting to an event requires start_time and end_time".into());
+        };
+        item.start_time = Some(start);
+        item.end_time = Some(end);
+    } else if item.item_type == ItemType::Event {
+        item.start_time = None;
+        item.end_time = None;
+    }
+
+    if to == ItemType::Task {
+        if payload.due_date.is_some() {
+            item.due_date = payload.due_date;
+        }
+        if item.due_date.is_none() {
+            return Err("Converting to a task requires a due_date".into());
+        }
+        if item.completed.is_none() {
+            item.completed = Some(false);
+        }
+    }
+
+    item.item_type = to;
+    Ok(())
+}
+
+pub async fn convert_item(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    payload: web::Json<ConvertPayload>,
+) -> Result<HttpResponse, ApiError> {
+    let mut item = load_item(&db, &path.into_inner())?;
+
+    convert(&mut item, &payload).map_err(ApiError::BadRequest)?;
+    validation::validate_item(&mut item, &config).map_err(ApiError::Invalid)?;
+
+    item.touch(clock.now_millis());
+    save_item(&db, &tenant, &item)?;
+    Ok(HttpResponse::Ok().json(item))
+}
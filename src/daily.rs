@@ -0,0 +1,112 @@
+//! Daily notes: one item per calendar day, made on first use. The item's ID
+//! is derived from the date, so two clients opening the same day at once
+//! both get the one note instead of racing to create two.
+
+use actix_web::{web, HttpResponse};
+use chrono::NaiveDate;
+use serde_json::json;
+
+use crate::{
+    append::{self, AppendPayload},
+    clock::SharedClock,
+    commit_items,
+    config::Config,
+    error::ApiError,
+    load_item, templates,
+    tenant::{Tenant, TenantStore},
+    tz::{self, TzQuery},
+    validation, CreateItemPayload, Item, SharedStore,
+};
+
+/// The template a new daily note starts from, when one is saved under this
+/// name. Otherwise it is a note titled with the date and tagged `daily`.
+pub const TEMPLATE: &str = "daily";
+
+/// The day `raw` names: `today` in the caller's zone, or `YYYY-MM-DD`.
+fn parse_date(raw: &str, query: &TzQuery, now: i64) -> Result<NaiveDate, ApiError> {
+    if raw == "today" {
+        let zone = query.zone().map_err(ApiError::BadRequest)?;
+        return Ok(tz::day_of(now, zone));
+    }
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d").map_err(|_| {
+        ApiError::BadRequest(format!(
+            "Invalid date '{raw}', expected 'today' or YYYY-MM-DD"
+        ))
+    })
+}
+
+fn note_id(date: NaiveDate) -> String {
+    format!("daily-{date}")
+}
+
+/// The daily note for `date`, created from the template if there is none
+/// yet. A read-only instance only finds existing notes.
+fn get_or_create(
+    db: &SharedStore,
+    tenant: &Tenant,
+    config: &Config,
+    date: NaiveDate,
+    now: i64,
+) -> Result<Item, ApiError> {
+    let id = note_id(date);
+    if db.get(id.as_bytes())?.is_some() || config.read_only {
+        return load_item(db, &id);
+    }
+
+    let date = date.to_string();
+    let mut body = templates::expand(db, TEMPLATE, &date)?
+        .unwrap_or_else(|| json!({ "type": "note", "tags": ["daily"] }));
+    if body["title"].is_null() {
+        body["title"] = date.into();
+    }
+    let payload: CreateItemPayload =
+        serde_json::from_value(body).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let mut item = Item::from_payload(id.clone(), now, &payload);
+    validation::validate_item(&mut item, config).map_err(ApiError::Invalid)?;
+
+    // Stored only if absent; whoever loses the race reads the winner's note.
+    if !commit_items(db, tenant, &[(None, item.clone())], Vec::new())? {
+        return load_item(db, &id);
+    }
+    Ok(item)
+}
+
+/// `GET /daily/{date}`: the daily note for `date` or `today`, made if
+/// missing. `?tz=` sets which day today is.
+pub async fn get(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<TzQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let now = clock.now_millis();
+    let date = parse_date(&path.into_inner(), &query, now)?;
+    let db = db.into_inner();
+    let item = web::block(move || get_or_create(&db, &tenant, &config, date, now)).await??;
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// `POST /daily/{date}/append`: appends a line to the day's note, making
+/// the note first if needed.
+pub async fn append(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<TzQuery>,
+    payload: web::Json<AppendPayload>,
+) -> Result<HttpResponse, ApiError> {
+    let now = clock.now_millis();
+    let date = parse_date(&path.into_inner(), &query, now)?;
+    let db = db.into_inner();
+    let text = payload.into_inner().text;
+    let item = web::block(move || {
+        let note = get_or_create(&db, &tenant, &config, date, now)?;
+        append::append_stored(&db, &tenant, &config, &note.id, &text, now)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(item))
+}
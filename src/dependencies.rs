@@ -0,0 +1,105 @@
+//! Blocked-by relationships between items. An item lists the open items it
+//! waits on in `blocked_by`; completing one of them removes it from every
+//! list it is on, so an item is blocked exactly while that list is not empty.
+
+use std::collections::HashSet;
+
+use crate::{
+    codec,
+    filter::{self, ItemFilter},
+    store::StoreResult,
+    validation::FieldError,
+    Item, SharedStore,
+};
+
+/// Checks each of `item`'s blockers against the store: it must be another
+/// item, exist, not be completed, and not itself wait on `item`, directly or
+/// through others.
+pub fn check_blockers(db: &SharedStore, item: &Item) -> StoreResult<Vec<FieldError>> {
+    let mut errors = Vec::new();
+    for blocker_id in &item.blocked_by {
+        if *blocker_id == item.id {
+            errors.push(FieldError::new(
+                "blocked_by",
+                "must not include the item itself",
+            ));
+            continue;
+        }
+        let Some(blocker) = find(db, blocker_id)? else {
+            errors.push(FieldError::new(
+                "blocked_by",
+                format!("'{blocker_id}' is not an item"),
+            ));
+            continue;
+        };
+        if blocker.completed == Some(true) {
+            errors.push(FieldError::new(
+                "blocked_by",
+                format!("'{blocker_id}' is already completed"),
+            ));
+        } else if waits_on(db, &blocker, &item.id)? {
+            errors.push(FieldError::new(
+                "blocked_by",
+                format!("'{blocker_id}' is itself blocked by this item"),
+            ));
+        }
+    }
+    Ok(errors)
+}
+
+fn find(db: &SharedStore, id: &str) -> StoreResult<Option<Item>> {
+    Ok(db
+        .get(id.as_bytes())?
+        .and_then(|raw| codec::decode(&raw).ok()))
+}
+
+/// Whether `item` waits on `target` through any chain of blockers.
+fn waits_on(db: &SharedStore, item: &Item, target: &str) -> StoreResult<bool> {
+    let mut seen = HashSet::new();
+    let mut pending = item.blocked_by.clone();
+    while let Some(id) = pending.pop() {
+        if id == target {
+            return Ok(true);
+        }
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+        if let Some(next) = find(db, &id)? {
+            pending.extend(next.blocked_by);
+        }
+    }
+    Ok(false)
+}
+
+/// The dependents that `changes` unblock: every stored item waiting on an
+/// item the changes complete, with that blocker removed and touched at the
+/// completion time. Items among `changes` themselves are left to them.
+pub fn unblocked(db: &SharedStore, changes: &[(Option<Item>, Item)]) -> Vec<(Option<Item>, Item)> {
+    let completed: Vec<(&str, i64)> = changes
+        .iter()
+        .filter(|(old, new)| {
+            new.completed == Some(true)
+                && old.as_ref().is_some_and(|old| old.completed != Some(true))
+        })
+        .map(|(_, new)| (new.id.as_str(), new.updated_at.unwrap_or(new.created_at)))
+        .collect();
+    if completed.is_empty() {
+        return Vec::new();
+    }
+    let changing: HashSet<&str> = changes.iter().map(|(_, new)| new.id.as_str()).collect();
+    filter::iter(db, ItemFilter::default())
+        .filter(|item| !changing.contains(item.id.as_str()))
+        .filter_map(|old| {
+            let mut item = old.clone();
+            let mut now = None;
+            item.blocked_by.retain(|id| {
+                let done = completed.iter().find(|(done, _)| done == id);
+                now = now.max(done.map(|(_, at)| *at));
+                done.is_none()
+            });
+            let now = now?;
+            item.touch(now);
+            Some((Some(old), item))
+        })
+        .collect()
+}
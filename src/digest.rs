@@ -0,0 +1,197 @@
+use actix_web::{http::header, web, HttpResponse};
+use chrono::{Datelike, NaiveDate, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::{
+    clock::SharedClock,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    tenant::TenantStore,
+    types::ItemType,
+    tz, Item,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct DigestQuery {
+    /// ISO week such as `2025-W27`; the current week when absent.
+    week: Option<String>,
+    /// `json` (default) or `md`.
+    format: Option<String>,
+    /// IANA zone the week's days are counted in; UTC when absent.
+    tz: Option<String>,
+}
+
+/// An ISO week as a half-open millisecond range, from Monday 00:00 in the
+/// requested zone to the next Monday 00:00 there.
+#[derive(Debug, Clone, Copy)]
+struct Week {
+    year: i32,
+    number: u32,
+    start: i64,
+    end: i64,
+    zone: Tz,
+}
+
+impl Week {
+    fn from_monday(monday: NaiveDate, zone: Tz) -> Self {
+        let iso = monday.iso_week();
+        Week {
+            year: iso.year(),
+            number: iso.week(),
+            start: tz::start_of_day(monday, zone),
+            end: tz::start_of_day(monday + chrono::Days::new(7), zone),
+            zone,
+        }
+    }
+
+    /// Parses `YYYY-Www`.
+    fn parse(raw: &str, zone: Tz) -> Option<Self> {
+        let (year, week) = raw.trim().split_once("-W")?;
+        let monday =
+            NaiveDate::from_isoywd_opt(year.parse().ok()?, week.parse().ok()?, Weekday::Mon)?;
+        Some(Week::from_monday(monday, zone))
+    }
+
+    fn containing(millis: i64, zone: Tz) -> Self {
+        let date = tz::day_of(millis, zone);
+        let monday = date - chrono::Days::new(date.weekday().num_days_from_monday() as u64);
+        Week::from_monday(monday, zone)
+    }
+
+    fn end(&self) -> i64 {
+        self.end
+    }
+
+    fn contains(&self, millis: Option<i64>) -> bool {
+        millis.is_some_and(|t| t >= self.start && t < self.end)
+    }
+
+    fn label(&self) -> String {
+        format!("{}-W{:02}", self.year, self.number)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Digest {
+    week: String,
+    start: i64,
+    end: i64,
+    /// Tasks marked complete and last changed during the week.
+    completed_tasks: Vec<Item>,
+    /// Open tasks due by the end of the week, overdue ones included.
+    open_tasks: Vec<Item>,
+    /// Events that started during the week.
+    events: Vec<Item>,
+    notes_created: Vec<Item>,
+    #[serde(skip)]
+    zone: Tz,
+}
+
+impl Digest {
+    fn build(week: Week, items: Vec<Item>) -> Self {
+        let mut digest = Digest {
+            week: week.label(),
+            start: week.start,
+            end: week.end(),
+            completed_tasks: Vec::new(),
+            open_tasks: Vec::new(),
+            events: Vec::new(),
+            notes_created: Vec::new(),
+            zone: week.zone,
+        };
+        for item in items {
+            let done = item.completed == Some(true);
+            match item.item_type {
+                ItemType::Task if done && week.contains(item.completed_at.or(item.updated_at)) => {
+                    digest.completed_tasks.push(item)
+                }
+                ItemType::Task if !done && item.due_date.is_some_and(|due| due < week.end()) => {
+                    digest.open_tasks.push(item)
+                }
+                ItemType::Event if week.contains(item.start_time) => digest.events.push(item),
+                ItemType::Note if week.contains(Some(item.created_at)) => {
+                    digest.notes_created.push(item)
+                }
+                _ => {}
+            }
+        }
+        filter::sort_by_key(&mut digest.open_tasks, |item| item.due_date);
+        filter::sort_by_key(&mut digest.events, |item| item.start_time);
+        filter::sort_by_key(&mut digest.notes_created, |item| item.created_at);
+        digest
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut md = format!("# Weekly digest {}\n", self.week);
+        section(&mut md, "Completed tasks", &self.completed_tasks, |_| None);
+        let day = |millis: i64| tz::day_of(millis, self.zone).format("%Y-%m-%d").to_string();
+        section(&mut md, "Open tasks", &self.open_tasks, |item| {
+            item.due_date.map(|due| format!("due {}", day(due)))
+        });
+        section(&mut md, "Events", &self.events, |item| {
+            item.start_time.map(day)
+        });
+        section(&mut md, "Notes created", &self.notes_created, |_| None);
+        md
+    }
+}
+
+fn section(
+    md: &mut String,
+    heading: &str,
+    items: &[Item],
+    detail: impl Fn(&Item) -> Option<String>,
+) {
+    let _ = write!(md, "\n## {heading}\n\n");
+    if items.is_empty() {
+        md.push_str("_None_\n");
+    }
+    for item in items {
+        match detail(item) {
+            Some(detail) => {
+                let _ = writeln!(md, "- {} ({detail})", item.title);
+            }
+            None => {
+                let _ = writeln!(md, "- {}", item.title);
+            }
+        }
+    }
+}
+
+/// `GET /digest`: a week's completed and outstanding tasks, events and new
+/// notes, as JSON or, with `format=md`, Markdown for pasting into a standup.
+pub async fn digest(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    query: web::Query<DigestQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let zone = tz::parse(query.tz.as_deref()).map_err(ApiError::BadRequest)?;
+    let week = match &query.week {
+        Some(raw) => Week::parse(raw, zone).ok_or_else(|| {
+            ApiError::BadRequest(format!("Invalid week '{raw}', expected e.g. 2025-W27"))
+        })?,
+        None => Week::containing(clock.now_millis(), zone),
+    };
+    let markdown = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("md") => true,
+        Some(other) => {
+            return Err(ApiError::BadRequest(format!(
+                "Invalid format '{other}', expected 'json' or 'md'"
+            )))
+        }
+    };
+
+    let items = filter::scan_blocking(&db, ItemFilter::default()).await?;
+    let digest = Digest::build(week, items);
+
+    Ok(if markdown {
+        HttpResponse::Ok()
+            .insert_header((header::CONTENT_TYPE, "text/markdown; charset=utf-8"))
+            .body(digest.to_markdown())
+    } else {
+        HttpResponse::Ok().json(digest)
+    })
+}
@@ -0,0 +1,169 @@
+//! The error every handler fails with. Each variant has a status and a
+//! stable `code`, and renders as `{"code", "message", "details"}`, so clients
+//! can branch on the code instead of matching message text.
+
+use actix_web::{
+    error::{BlockingError, JsonPayloadError, PathError, QueryPayloadError},
+    http::{header::ETAG, StatusCode},
+    HttpRequest, HttpResponse, ResponseError,
+};
+use serde_json::{json, Value};
+use std::fmt;
+
+use crate::{store::StoreError, validation::FieldError};
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// A malformed request: a bad query parameter, body or header.
+    BadRequest(String),
+    /// No API key, or one that isn't configured.
+    Unauthorized,
+    /// A write sent to a read-only instance.
+    ReadOnly,
+    NotFound(&'static str),
+    /// The write clashes with what is stored, such as an id already in use.
+    Conflict(String),
+    /// `If-Match` named a stale version. Carries the current ETag.
+    PreconditionFailed(String),
+    PayloadTooLarge(String),
+    /// Fields that failed validation, all of them.
+    Invalid(Vec<FieldError>),
+    /// A request over several items was refused; `details` says why for each.
+    Unprocessable {
+        message: String,
+        details: Value,
+    },
+    /// Storage or encoding failed. The message names the step, not the cause.
+    Internal(&'static str),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::ReadOnly => "read_only",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PreconditionFailed(_) => "precondition_failed",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::Invalid(errors) if is_limit(errors) => "limit_exceeded",
+            ApiError::Invalid(_) => "validation_failed",
+            ApiError::Unprocessable { .. } => "unprocessable",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    /// The response body, also used for one failed row of a batch.
+    pub fn body(&self) -> Value {
+        let details = match self {
+            ApiError::Invalid(errors) => json!(errors),
+            ApiError::Unprocessable { details, .. } => details.clone(),
+            _ => Value::Null,
+        };
+        json!({ "code": self.code(), "message": self.to_string(), "details": details })
+    }
+}
+
+/// Whether any of the failures is a configured limit, which is reported as
+/// 400 rather than 422.
+fn is_limit(errors: &[FieldError]) -> bool {
+    errors.iter().any(|e| e.code.is_some())
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Conflict(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::Unprocessable { message, .. } => f.write_str(message),
+            ApiError::NotFound(message) | ApiError::Internal(message) => f.write_str(message),
+            ApiError::Unauthorized => f.write_str("Missing or invalid API key"),
+            ApiError::ReadOnly => f.write_str("This instance is read-only"),
+            ApiError::PreconditionFailed(_) => {
+                f.write_str("Item has changed since that version; fetch it and retry")
+            }
+            ApiError::Invalid(errors) if is_limit(errors) => f.write_str("A limit was exceeded"),
+            ApiError::Invalid(_) => f.write_str("Validation failed"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::ReadOnly => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Invalid(errors) if is_limit(errors) => StatusCode::BAD_REQUEST,
+            ApiError::Invalid(_) | ApiError::Unprocessable { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status_code());
+        if let ApiError::PreconditionFailed(etag) = self {
+            res.insert_header((ETAG, etag.clone()));
+        }
+        res.json(self.body())
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(_: StoreError) -> Self {
+        ApiError::Internal("DB error")
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(_: BlockingError) -> Self {
+        ApiError::Internal("DB error")
+    }
+}
+
+/// Error handler for `web::Json` bodies that fail to parse or are too big.
+pub fn json_error(err: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            ApiError::PayloadTooLarge(err.to_string()).into()
+        }
+        err => ApiError::BadRequest(err.to_string()).into(),
+    }
+}
+
+/// Error handler for path segments that don't parse, such as a revision
+/// number that isn't one. No resource has that path, so it is a 404.
+pub fn path_error(_: PathError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::NotFound("Not found").into()
+}
+
+/// Error handler for `web::Query` parameters that fail to parse.
+pub fn query_error(err: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::BadRequest(err.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    #[actix_web::test]
+    async fn renders_code_message_and_details() {
+        let res = ApiError::NotFound("Item not found").error_response();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(res.into_body()).await.unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({"code": "not_found", "message": "Item not found", "details": null})
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Optimistic concurrency for item writes. Every change bumps an item's
+//! `version`, which responses carry as a strong ETag; a write sent with
+//! `If-Match` only goes ahead if the item is still at that version, so two
+//! clients editing the same item can't silently overwrite each other.
+
+use actix_web::{
+    http::header::{ETAG, IF_MATCH},
+    HttpRequest,
+};
+
+use crate::{
+    changed_meanwhile, commit_items, error::ApiError, load_item, tenant::Tenant, Item, SharedStore,
+};
+
+/// The item's version as an ETag header value.
+pub fn of(item: &Item) -> String {
+    format!("\"{}\"", item.version)
+}
+
+/// The `ETag` header for a response carrying `item`.
+pub fn header(item: &Item) -> (actix_web::http::header::HeaderName, String) {
+    (ETAG, of(item))
+}
+
+/// Whether an `If-Match` value names `version`: `*`, or any entry of the
+/// list, quoted or not, weak or not.
+fn matches(if_match: &str, version: u64) -> bool {
+    let version = version.to_string();
+    if_match.split(',').any(|tag| {
+        let tag = tag.trim();
+        let tag = tag.strip_prefix("W/").unwrap_or(tag);
+        tag == "*" || tag.trim_matches('"') == version
+    })
+}
+
+/// Refuses a write with 412 if the request's `If-Match` names a version
+/// other than `current`'s. Requests without one always proceed.
+pub fn check(req: &HttpRequest, current: &Item) -> Result<(), ApiError> {
+    let Some(if_match) = req.headers().get(IF_MATCH) else {
+        return Ok(());
+    };
+    match if_match.to_str() {
+        Ok(if_match) => require(if_match, current),
+        Err(_) => Err(ApiError::BadRequest("Invalid If-Match header".to_string())),
+    }
+}
+
+/// Refuses with 412 unless `if_match`, an `If-Match` value carried some
+/// other way than the header, names `current`'s version.
+pub fn require(if_match: &str, current: &Item) -> Result<(), ApiError> {
+    match matches(if_match, current.version) {
+        true => Ok(()),
+        false => Err(ApiError::PreconditionFailed(of(current))),
+    }
+}
+
+/// Stores `changes`, the first of which replaces the version [`check`]
+/// passed, only if nothing else has been written to any of them since, so
+/// the `If-Match` comparison holds for the write itself and not just the
+/// read before it.
+pub fn save(
+    req: &HttpRequest,
+    db: &SharedStore,
+    tenant: &Tenant,
+    changes: &[(Option<Item>, Item)],
+) -> Result<(), ApiError> {
+    if commit_items(db, tenant, changes, Vec::new())? {
+        Ok(())
+    } else {
+        Err(overtaken(req, db, &changes[0].1.id))
+    }
+}
+
+/// The error for a write to `id` that another write got to first: 412 with
+/// the current ETag, as [`check`] would have given had it run after that
+/// write, or 409 for a request without `If-Match`.
+pub fn overtaken(req: &HttpRequest, db: &SharedStore, id: &str) -> ApiError {
+    if !req.headers().contains_key(IF_MATCH) {
+        return changed_meanwhile();
+    }
+    match load_item(db, id) {
+        Ok(current) => ApiError::PreconditionFailed(of(&current)),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Encoding, store::MemoryStore};
+    use actix_web::test::TestRequest;
+    use std::sync::Arc;
+
+    #[test]
+    fn matches_listed_and_wildcard_tags() {
+        assert!(matches("\"3\"", 3));
+        assert!(matches("W/\"3\"", 3));
+        assert!(matches("\"1\", \"3\"", 3));
+        assert!(matches("*", 7));
+        assert!(!matches("\"2\"", 3));
+    }
+
+    #[test]
+    fn a_write_overtaken_after_the_check_is_refused() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let tenant = Tenant(None);
+        let item: Item = serde_json::from_value(serde_json::json!({
+            "id": "a", "type": "note", "title": "a", "content": null, "tags": [],
+            "code_location": null, "created_at": 0, "completed": null,
+            "due_date": null, "start_time": null, "end_time": null,
+        }))
+        .unwrap();
+        assert!(commit_items(&db, &tenant, &[(None, item.clone())], Vec::new()).unwrap());
+
+        // Both requests read version 1 and pass `check`; the first to write wins.
+        let loaded = load_item(&db, "a").unwrap();
+        let mut first = loaded.clone();
+        first.title = "first".to_string();
+        first.touch(1);
+        let mut second = loaded.clone();
+        second.title = "second".to_string();
+        second.touch(2);
+        let req = TestRequest::default()
+            .insert_header((IF_MATCH, of(&loaded)))
+            .to_http_request();
+        save(&req, &db, &tenant, &[(Some(loaded.clone()), first.clone())]).unwrap();
+
+        let overtaken = [(Some(loaded), second)];
+        match save(&req, &db, &tenant, &overtaken) {
+            Err(ApiError::PreconditionFailed(tag)) => assert_eq!(tag, of(&first)),
+            other => panic!("expected 412, got {other:?}"),
+        }
+        let unconditional = TestRequest::default().to_http_request();
+        assert!(matches!(
+            save(&unconditional, &db, &tenant, &overtaken),
+            Err(ApiError::Conflict(_))
+        ));
+        assert_eq!(load_item(&db, "a").unwrap().title, "first");
+    }
+}
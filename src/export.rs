@@ -0,0 +1,88 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    clock::SharedClock,
+    codec,
+    error::ApiError,
+    filter::{self, ItemFilter, Page},
+    stream,
+    tenant::TenantStore,
+    time::{TimeFormat, TimeView},
+    Item,
+};
+
+const DEFAULT_PAGE_LIMIT: usize = 100;
+const MAX_PAGE_LIMIT: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct CursorQuery {
+    /// ID of the last item of the previous page; the first page when absent.
+    after: Option<String>,
+    limit: Option<usize>,
+    #[serde(default)]
+    time: TimeFormat,
+}
+
+#[derive(Serialize)]
+struct CursorPage {
+    items: Vec<TimeView<Item>>,
+    /// Pass as `after` to fetch the next page; `null` on the last one.
+    next_after: Option<String>,
+}
+
+/// `GET /items/export/ndjson`: every matching item as one JSON document per
+/// line, streamed straight off the store. Takes the listing's filter and
+/// paging parameters.
+pub async fn ndjson(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let filter =
+        ItemFilter::from_query(&query, clock.now_millis()).map_err(ApiError::BadRequest)?;
+    let page = Page::from_query(&query).map_err(ApiError::BadRequest)?;
+
+    Ok(stream::ndjson(page.apply(filter::iter(&db, filter))))
+}
+
+/// `GET /items/page`: key-ordered paging with a cursor. Each page starts
+/// with a range scan just past `after`, so deep pages cost the same as the
+/// first rather than skipping everything before them. With ULID ids, key
+/// order is creation order.
+pub async fn cursor_page(
+    db: TenantStore,
+    query: web::Query<CursorQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+
+    let db = db.into_inner();
+    let scanned = web::block(move || {
+        let entries = match &query.after {
+            Some(after) => db.iter_after(after.as_bytes()),
+            None => db.iter(),
+        };
+        // One extra item tells us whether there is a next page.
+        entries
+            .filter_map(|entry| codec::decode::<Item>(&entry.ok()?.1).ok())
+            .take(limit + 1)
+            .collect::<Vec<_>>()
+    })
+    .await;
+    let mut items = scanned?;
+
+    let next_after = (items.len() > limit).then(|| {
+        items.truncate(limit);
+        items[limit - 1].id.clone()
+    });
+    let items = items
+        .into_iter()
+        .map(|item| query.time.view(item))
+        .collect();
+    Ok(HttpResponse::Ok().json(CursorPage { items, next_after }))
+}
@@ -0,0 +1,105 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    clock::SharedClock,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    index,
+    tenant::TenantStore,
+    time::{self, TimeQuery},
+    types::ItemType,
+    tz::{self, TzQuery},
+    Item, SharedStore,
+};
+
+const DEFAULT_RECENT_LIMIT: usize = 20;
+const DEFAULT_DUE_SOON_WINDOW: &str = "1d";
+const MAX_RECENT_LIMIT: usize = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct RecentQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DueSoonQuery {
+    /// How far ahead to look, e.g. `12h`, `2d` or `1w`. One day by default.
+    within: Option<String>,
+}
+
+/// When the item last changed, counting creation as a change.
+fn last_activity(item: &Item) -> i64 {
+    item.created_at.max(item.updated_at.unwrap_or(i64::MIN))
+}
+
+/// Activity feed: the most recently created or edited items of any type.
+pub async fn recent(
+    db: TenantStore,
+    query: web::Query<RecentQuery>,
+    time_query: web::Query<TimeQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_RECENT_LIMIT)
+        .min(MAX_RECENT_LIMIT);
+
+    let mut items = filter::scan_blocking(&db, ItemFilter::default()).await?;
+    filter::sort_by_key(&mut items, |item| std::cmp::Reverse(last_activity(item)));
+    items.truncate(limit);
+
+    Ok(HttpResponse::Ok().json(time_query.time.view(items)))
+}
+
+/// Open tasks due within `from..=to`, read through the due-date index.
+async fn open_tasks_due(
+    db: &SharedStore,
+    from: Option<i64>,
+    to: i64,
+) -> Result<Vec<Item>, ApiError> {
+    let db = db.clone();
+    let mut items = web::block(move || index::due_between(&db, from, to)).await??;
+    items.retain(|item| item.item_type == ItemType::Task && item.completed != Some(true));
+    Ok(items)
+}
+
+/// Open tasks due on a day before today, earliest first. Days are counted in
+/// `?tz=` (UTC by default), so a task due today is never overdue, however
+/// late in the day it was due.
+pub async fn overdue(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    tz_query: web::Query<TzQuery>,
+    time_query: web::Query<TimeQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let zone = tz_query.zone().map_err(ApiError::BadRequest)?;
+    let today_starts = tz::start_of_day(tz::day_of(clock.now_millis(), zone), zone);
+
+    let mut items = open_tasks_due(&db, None, today_starts - 1).await?;
+    filter::sort_by_key(&mut items, |item| item.due_date);
+
+    Ok(HttpResponse::Ok().json(time_query.time.view(items)))
+}
+
+/// Open tasks due between now and `?within=` from now, soonest first. Tasks
+/// already past due are left to [`overdue`].
+pub async fn due_soon(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    query: web::Query<DueSoonQuery>,
+    time_query: web::Query<TimeQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let raw = query.within.as_deref().unwrap_or(DEFAULT_DUE_SOON_WINDOW);
+    let Some(window) = time::parse_duration_millis(raw) else {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid within '{raw}', expected a duration such as 12h, 2d or 1w"
+        )));
+    };
+    let now = clock.now_millis();
+    let until = now.saturating_add(window);
+
+    let mut items = open_tasks_due(&db, Some(now), until).await?;
+    filter::sort_by_key(&mut items, |item| item.due_date);
+
+    Ok(HttpResponse::Ok().json(time_query.time.view(items)))
+}
@@ -0,0 +1,92 @@
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::stream::Keyed;
+
+/// Names an item serializes with, and so the ones `?fields=` can select.
+const ITEM_FIELDS: &[&str] = &[
+    "id",
+    "type",
+    "title",
+    "content",
+    "tags",
+    "code_location",
+    "created_at",
+    "updated_at",
+    "completed",
+    "due_date",
+    "start_time",
+    "end_time",
+    "attachments",
+    "links",
+    "parent_id",
+    "archived",
+    "version",
+    "progress",
+];
+
+/// The fields a response should include, chosen with `?fields=id,title`.
+/// `id` is always kept so projected items can still be addressed.
+#[derive(Debug, Clone, Default)]
+pub struct Fields(Option<Arc<[String]>>);
+
+impl Fields {
+    /// Reads `fields` from `query`. Unknown names are dropped, or rejected
+    /// when `strict` is set.
+    pub fn from_query(query: &HashMap<String, String>, strict: bool) -> Result<Self, String> {
+        let Some(raw) = query.get("fields") else {
+            return Ok(Fields(None));
+        };
+        let mut selected = Vec::new();
+        let mut unknown = Vec::new();
+        for name in raw.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if ITEM_FIELDS.contains(&name) {
+                selected.push(name.to_string());
+            } else {
+                unknown.push(name);
+            }
+        }
+        if strict && !unknown.is_empty() {
+            return Err(format!(
+                "Unknown fields: {}; expected any of: {}",
+                unknown.join(", "),
+                ITEM_FIELDS.join(", ")
+            ));
+        }
+        Ok(Fields(Some(selected.into())))
+    }
+
+    /// Wraps `value` so it serializes with only the selected fields.
+    pub fn view<T>(&self, value: T) -> Projected<T> {
+        Projected {
+            value,
+            fields: self.0.clone(),
+        }
+    }
+}
+
+/// Response view of an item that drops every field not asked for.
+pub struct Projected<T> {
+    value: T,
+    fields: Option<Arc<[String]>>,
+}
+
+impl<T: Serialize> Serialize for Projected<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Some(fields) = &self.fields else {
+            return self.value.serialize(serializer);
+        };
+        let mut value = serde_json::to_value(&self.value).map_err(serde::ser::Error::custom)?;
+        if let Value::Object(map) = &mut value {
+            map.retain(|key, _| key == "id" || fields.iter().any(|f| f == key));
+        }
+        value.serialize(serializer)
+    }
+}
+
+impl<T: Keyed> Keyed for Projected<T> {
+    fn key(&self) -> &str {
+        self.value.key()
+    }
+}
@@ -0,0 +1,524 @@
+use actix_web::{error::BlockingError, web};
+use std::{cmp::Ordering, collections::HashMap, str::FromStr};
+
+use crate::{
+    codec, index, priority,
+    query::{self, Expr},
+    store::KvIter,
+    time, Item, SharedStore,
+};
+
+/// How a list of tags in a filter is matched against an item's tags.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
+pub enum TagsMode {
+    /// The item carries every listed tag.
+    #[default]
+    All,
+    /// The item carries at least one listed tag.
+    Any,
+}
+
+impl FromStr for TagsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "all" => Ok(TagsMode::All),
+            "any" => Ok(TagsMode::Any),
+            other => Err(format!(
+                "Invalid tags_mode '{other}', expected 'any' or 'all'"
+            )),
+        }
+    }
+}
+
+/// An optional field an item can be filtered on for lacking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
+pub enum MissingField {
+    DueDate,
+    /// No content, or content that is only whitespace.
+    Content,
+    /// No tags at all.
+    Tags,
+    CodeLocation,
+}
+
+impl MissingField {
+    fn is_missing(self, item: &Item) -> bool {
+        match self {
+            MissingField::DueDate => item.due_date.is_none(),
+            MissingField::Content => item.content.as_deref().is_none_or(|c| c.trim().is_empty()),
+            MissingField::Tags => item.tags.is_empty(),
+            MissingField::CodeLocation => item.code_location.is_none(),
+        }
+    }
+}
+
+impl FromStr for MissingField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "due_date" => Ok(MissingField::DueDate),
+            "content" => Ok(MissingField::Content),
+            "tags" => Ok(MissingField::Tags),
+            "code_location" => Ok(MissingField::CodeLocation),
+            other => Err(format!(
+                "Invalid missing field '{other}', expected due_date, content, tags or code_location"
+            )),
+        }
+    }
+}
+
+/// Criteria shared by every endpoint that lists items (REST and GraphQL).
+#[derive(Debug, Default, Clone)]
+pub struct ItemFilter {
+    pub item_type: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub tags_mode: TagsMode,
+    /// Items must also carry at least one of these, whatever `tags_mode` is.
+    pub tags_any: Option<Vec<String>>,
+    /// Items carrying any of these are left out.
+    pub without_tags: Vec<String>,
+    /// Treat `/` in tags as a hierarchy, so a filter tag also matches every
+    /// tag nested under it. On for listings unless `hierarchical=false`.
+    pub hierarchical: bool,
+    /// Only items lacking every one of these fields.
+    pub missing: Vec<MissingField>,
+    /// Only items last modified strictly after this time.
+    pub updated_after: Option<i64>,
+    /// Only items last modified strictly before this time.
+    pub updated_before: Option<i64>,
+    /// Only items created strictly after this time.
+    pub created_after: Option<i64>,
+    /// Only items created strictly before this time.
+    pub created_before: Option<i64>,
+    /// Only items due strictly after this time.
+    pub due_after: Option<i64>,
+    /// Only items due strictly before this time.
+    pub due_before: Option<i64>,
+    /// Only items starting within this inclusive range.
+    pub start_between: Option<(i64, i64)>,
+    /// Only items with one of these priorities.
+    pub priority: Option<Vec<u8>>,
+    /// Only items in this project.
+    pub project_id: Option<String>,
+    /// Only items marked done (`true`) or not (`false`).
+    pub completed: Option<bool>,
+    /// Only items that are (`true`) or aren't (`false`) overdue: due before
+    /// `now` and not completed.
+    pub overdue: Option<bool>,
+    /// Only items that are (`true`) or aren't (`false`) waiting on another.
+    pub blocked: Option<bool>,
+    /// The time `overdue` is judged at.
+    pub now: i64,
+    /// A `?q=` expression items must also satisfy.
+    pub expr: Option<Expr>,
+    /// Leave out archived items. Listings set this unless asked for
+    /// `include_archived`; internal scans see everything.
+    pub exclude_archived: bool,
+    /// Leave out items snoozed past `now`. Listings set this unless asked
+    /// for `include_snoozed`.
+    pub exclude_snoozed: bool,
+}
+
+impl ItemFilter {
+    pub fn from_query(query: &HashMap<String, String>, now: i64) -> Result<Self, String> {
+        let (tags, mut without_tags) = tag_terms(query.get("tags"));
+        let (tags_any, more_without) = tag_terms(query.get("tags_any"));
+        without_tags.extend(more_without);
+        Ok(ItemFilter {
+            item_type: query.get("type").map(|s| s.to_lowercase()),
+            tags,
+            tags_mode: query
+                .get("tags_mode")
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or_default(),
+            tags_any,
+            without_tags,
+            hierarchical: parse_param(query, "hierarchical")?.unwrap_or(true),
+            missing: query
+                .get("missing")
+                .map(|raw| raw.split(',').map(str::parse).collect())
+                .transpose()?
+                .unwrap_or_default(),
+            updated_after: parse_time(query, "updated_after")?,
+            updated_before: parse_time(query, "updated_before")?,
+            created_after: parse_time(query, "created_after")?,
+            created_before: parse_time(query, "created_before")?,
+            due_after: parse_time(query, "due_after")?,
+            due_before: parse_time(query, "due_before")?,
+            start_between: parse_range(query, "start_between")?,
+            priority: query
+                .get("priority")
+                .map(|raw| raw.split(',').map(parse_priority).collect())
+                .transpose()?,
+            project_id: query.get("project").cloned(),
+            completed: parse_param(query, "completed")?,
+            overdue: parse_param(query, "overdue")?,
+            blocked: parse_param(query, "blocked")?,
+            now,
+            expr: query.get("q").map(|q| query::parse(q)).transpose()?,
+            exclude_archived: !parse_param(query, "include_archived")?.unwrap_or(false),
+            exclude_snoozed: !parse_param(query, "include_snoozed")?.unwrap_or(false),
+        })
+    }
+
+    pub fn matches(&self, item: &Item) -> bool {
+        let type_match = self
+            .item_type
+            .as_ref()
+            .is_none_or(|t| t == item.item_type.as_str());
+
+        let has_tag = |tag: &String| {
+            item.tags
+                .iter()
+                .any(|t| t == tag || (self.hierarchical && is_nested_under(t, tag)))
+        };
+        let tags_match = self.tags.as_ref().is_none_or(|tags| match self.tags_mode {
+            TagsMode::All => tags.iter().all(has_tag),
+            TagsMode::Any => tags.iter().any(has_tag),
+        }) && self
+            .tags_any
+            .as_ref()
+            .is_none_or(|tags| tags.iter().any(has_tag))
+            && !self.without_tags.iter().any(has_tag);
+
+        let missing_match = self.missing.iter().all(|field| field.is_missing(item));
+
+        // Items stored before updated_at was tracked last changed on creation.
+        let modified = item.updated_at.unwrap_or(item.created_at);
+        let updated_match = between(modified, self.updated_after, self.updated_before);
+        let created_match = between(item.created_at, self.created_after, self.created_before);
+        // Undated items fall outside any due window.
+        let due_match = (self.due_after.is_none() && self.due_before.is_none())
+            || item
+                .due_date
+                .is_some_and(|due| between(due, self.due_after, self.due_before));
+        let start_match = self.start_between.is_none_or(|(from, to)| {
+            item.start_time
+                .is_some_and(|start| (from..=to).contains(&start))
+        });
+
+        let priority_match = self
+            .priority
+            .as_ref()
+            .is_none_or(|wanted| item.priority.is_some_and(|p| wanted.contains(&p)));
+
+        let project_match = self
+            .project_id
+            .as_ref()
+            .is_none_or(|project| item.project_id.as_ref() == Some(project));
+
+        let done = item.completed == Some(true);
+        let completed_match = self.completed.is_none_or(|completed| completed == done);
+        let is_overdue = !done && item.due_date.is_some_and(|due| due < self.now);
+        let overdue_match = self.overdue.is_none_or(|overdue| overdue == is_overdue);
+        let blocked_match = self
+            .blocked
+            .is_none_or(|blocked| blocked != item.blocked_by.is_empty());
+
+        let archived_match = !(self.exclude_archived && item.archived);
+        let snoozed = item.snoozed_until.is_some_and(|until| until > self.now);
+        let snoozed_match = !(self.exclude_snoozed && snoozed);
+
+        type_match
+            && tags_match
+            && missing_match
+            && updated_match
+            && created_match
+            && due_match
+            && start_match
+            && priority_match
+            && project_match
+            && completed_match
+            && overdue_match
+            && blocked_match
+            && self
+                .expr
+                .as_ref()
+                .is_none_or(|expr| expr.matches(item, self.now))
+            && archived_match
+            && snoozed_match
+    }
+}
+
+/// Whether `value` lies strictly between the bounds given.
+fn between(value: i64, after: Option<i64>, before: Option<i64>) -> bool {
+    after.is_none_or(|after| value > after) && before.is_none_or(|before| value < before)
+}
+
+/// Whether `tag` sits below `ancestor` in a `/`-separated tag hierarchy.
+pub fn is_nested_under(tag: &str, ancestor: &str) -> bool {
+    tag.strip_prefix(ancestor)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// The window of matching items a list request asks for.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Page {
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        Ok(Page {
+            offset: parse_param(query, "offset")?.unwrap_or(0),
+            limit: parse_param(query, "limit")?,
+        })
+    }
+
+    /// Applies the window to an iterator of matching items.
+    pub fn apply<I: Iterator>(self, items: I) -> impl Iterator<Item = I::Item> {
+        items
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+    }
+}
+
+/// Field a list can be sorted on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    CreatedAt,
+    DueDate,
+    Title,
+    Priority,
+    Rank,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created_at" => Ok(SortKey::CreatedAt),
+            "due_date" => Ok(SortKey::DueDate),
+            "title" => Ok(SortKey::Title),
+            "priority" => Ok(SortKey::Priority),
+            "rank" => Ok(SortKey::Rank),
+            other => Err(format!(
+                "Invalid sort '{other}', expected 'created_at', 'due_date', 'title', 'priority' \
+                 or 'rank'"
+            )),
+        }
+    }
+}
+
+/// A requested ordering, from `?sort=` and `?order=asc|desc`. Without one,
+/// lists come in key order.
+#[derive(Debug, Clone, Copy)]
+pub struct Sort {
+    key: SortKey,
+    descending: bool,
+}
+
+impl Sort {
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Option<Self>, String> {
+        let descending = match query.get("order").map(String::as_str) {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => {
+                return Err(format!("Invalid order '{other}', expected 'asc' or 'desc'"))
+            }
+        };
+        match query.get("sort") {
+            Some(key) => Ok(Some(Sort {
+                key: key.parse()?,
+                descending,
+            })),
+            None if query.contains_key("order") => Err("order requires sort".to_string()),
+            None => Ok(None),
+        }
+    }
+
+    /// Sorts `items`, ties broken by id so pages stay put between requests.
+    /// Items without a due date, priority or rank come last in either
+    /// direction.
+    pub fn apply(self, items: &mut [Item]) {
+        items.sort_by(|a, b| self.compare(a, b).then_with(|| a.id.cmp(&b.id)));
+    }
+
+    fn compare(self, a: &Item, b: &Item) -> Ordering {
+        let directed = |ordering: Ordering| {
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        };
+        match self.key {
+            SortKey::CreatedAt => directed(a.created_at.cmp(&b.created_at)),
+            SortKey::Title => directed(a.title.to_lowercase().cmp(&b.title.to_lowercase())),
+            SortKey::DueDate => last_if_none(a.due_date, b.due_date, directed),
+            SortKey::Priority => last_if_none(a.priority, b.priority, directed),
+            SortKey::Rank => last_if_none(a.rank.as_ref(), b.rank.as_ref(), directed),
+        }
+    }
+}
+
+/// Orders two optional values with `directed`, putting `None` after any
+/// value.
+fn last_if_none<T: Ord>(
+    a: Option<T>,
+    b: Option<T>,
+    directed: impl Fn(Ordering) -> Ordering,
+) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => directed(a.cmp(&b)),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+/// For strict requests: fails with the offending keys when `query` has any
+/// parameter not in `known`. `strict` itself is always accepted.
+pub fn reject_unknown_params(
+    query: &HashMap<String, String>,
+    known: &[&str],
+) -> Result<(), String> {
+    let mut unknown: Vec<&str> = query
+        .keys()
+        .map(String::as_str)
+        .filter(|key| *key != "strict" && !known.contains(key))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    unknown.sort_unstable();
+    Err(format!(
+        "Unknown query parameters: {}; expected any of: {}",
+        unknown.join(", "),
+        known.join(", ")
+    ))
+}
+
+/// Whether a request asked for strict parameter checking, falling back to
+/// `default` when it doesn't say.
+pub fn is_strict(query: &HashMap<String, String>, default: bool) -> Result<bool, String> {
+    Ok(parse_param(query, "strict")?.unwrap_or(default))
+}
+
+fn parse_param<T: FromStr>(
+    query: &HashMap<String, String>,
+    key: &str,
+) -> Result<Option<T>, String> {
+    query
+        .get(key)
+        .map(|raw| {
+            raw.parse()
+                .map_err(|_| format!("Invalid value '{raw}' for {key}"))
+        })
+        .transpose()
+}
+
+fn parse_priority(raw: &str) -> Result<u8, String> {
+    priority::parse(raw).ok_or_else(|| {
+        format!(
+            "Invalid priority '{raw}', expected 1 to {lowest} or P1 to P{lowest}",
+            lowest = priority::LOWEST
+        )
+    })
+}
+
+fn parse_time(query: &HashMap<String, String>, key: &str) -> Result<Option<i64>, String> {
+    query
+        .get(key)
+        .map(|raw| {
+            time::parse_millis(raw).ok_or_else(|| {
+                format!("Invalid value '{raw}' for {key}, expected epoch millis or ISO-8601")
+            })
+        })
+        .transpose()
+}
+
+/// A `from,to` pair of times.
+fn parse_range(query: &HashMap<String, String>, key: &str) -> Result<Option<(i64, i64)>, String> {
+    let Some(raw) = query.get(key) else {
+        return Ok(None);
+    };
+    let bounds = raw.split_once(',').and_then(|(from, to)| {
+        Some((
+            time::parse_millis(from.trim())?,
+            time::parse_millis(to.trim())?,
+        ))
+    });
+    match bounds {
+        Some((from, to)) if from <= to => Ok(Some((from, to))),
+        _ => Err(format!(
+            "Invalid value '{raw}' for {key}, expected two ordered times as from,to"
+        )),
+    }
+}
+
+fn split_tags(raw: &str) -> Vec<String> {
+    raw.split(',').map(|tag| tag.trim().to_string()).collect()
+}
+
+/// Splits a tag list into the tags wanted and those excluded with a leading
+/// `-`, as in `tags=urgent,-blocked`. A list of only exclusions wants nothing
+/// in particular.
+fn tag_terms(raw: Option<&String>) -> (Option<Vec<String>>, Vec<String>) {
+    let Some(raw) = raw else {
+        return (None, Vec::new());
+    };
+    let (without, wanted): (Vec<String>, Vec<String>) = split_tags(raw)
+        .into_iter()
+        .partition(|tag| tag.starts_with('-'));
+    let without = without
+        .into_iter()
+        .map(|tag| tag[1..].to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let wanted = (!wanted.is_empty()).then_some(wanted);
+    (wanted, without)
+}
+
+/// Lazily deserializes the items in `db` that satisfy `filter`, in key order.
+/// Records that fail to read or decode are skipped. When the filter names a
+/// type or tags, only the items the indexes list are read.
+pub fn iter(db: &SharedStore, filter: ItemFilter) -> impl Iterator<Item = Item> + Send + 'static {
+    let db = db.clone();
+    let indexed = filter.clone();
+    // Deferred to the first `next`, like the scan it replaces.
+    let entries = std::iter::once(()).flat_map(move |()| -> KvIter {
+        match index::candidates(&db, &indexed) {
+            Ok(Some(ids)) => {
+                let db = db.clone();
+                Box::new(ids.into_iter().filter_map(move |id| {
+                    let raw = db.get(id.as_bytes()).transpose()?;
+                    Some(raw.map(|raw| (id.into_bytes(), raw)))
+                }))
+            }
+            // An unreadable index costs speed, not results. A missing entry
+            // would hide items, which `index::build` repairs.
+            _ => db.iter(),
+        }
+    });
+    entries.filter_map(move |entry| {
+        let (_, val) = entry.ok()?;
+        let item: Item = codec::decode(&val).ok()?;
+        filter.matches(&item).then_some(item)
+    })
+}
+
+/// Collects every item in `db` that satisfies `filter`, in key order.
+pub fn scan(db: &SharedStore, filter: &ItemFilter) -> Vec<Item> {
+    iter(db, filter.clone()).collect()
+}
+
+/// Runs [`scan`] on the blocking thread pool, keeping long scans off the
+/// async worker that is serving other requests.
+pub async fn scan_blocking(
+    db: &SharedStore,
+    filter: ItemFilter,
+) -> Result<Vec<Item>, BlockingError> {
+    let db = db.clone();
+    web::block(move || scan(&db, &filter)).await
+}
+
+/// Sorts `items` by `key`, breaking ties by id so that items sharing a key
+/// come out in the same order on every request and pages never shift.
+pub fn sort_by_key<K: Ord>(items: &mut [Item], key: impl Fn(&Item) -> K) {
+    items.sort_by(|a, b| key(a).cmp(&key(b)).then_with(|| a.id.cmp(&b.id)));
+}
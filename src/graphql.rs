@@ -0,0 +1,147 @@
+use actix_web::web;
+use async_graphql::{Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use std::collections::BTreeSet;
+
+use crate::{
+    clock::SharedClock,
+    codec,
+    filter::{self, ItemFilter, MissingField, TagsMode},
+    tenant::TenantStore,
+    Item, SharedStore,
+};
+
+pub type NeonoteSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+#[derive(InputObject, Default)]
+pub struct ItemFilterInput {
+    #[graphql(name = "type")]
+    item_type: Option<String>,
+    tags: Option<Vec<String>>,
+    tags_mode: Option<TagsMode>,
+    /// Items must also carry at least one of these.
+    tags_any: Option<Vec<String>>,
+    /// Items carrying any of these are left out.
+    without_tags: Option<Vec<String>>,
+    /// Match tags nested under the given ones too; defaults to true.
+    hierarchical: Option<bool>,
+    missing: Option<Vec<MissingField>>,
+    /// Epoch millis; only items last modified after it.
+    updated_after: Option<i64>,
+    /// Epoch millis; only items last modified before it.
+    updated_before: Option<i64>,
+    /// Epoch millis; only items created after it.
+    created_after: Option<i64>,
+    /// Epoch millis; only items created before it.
+    created_before: Option<i64>,
+    /// Epoch millis; only items due after it.
+    due_after: Option<i64>,
+    /// Epoch millis; only items due before it.
+    due_before: Option<i64>,
+    /// Only items with one of these priorities, 1 to 4.
+    priority: Option<Vec<u8>>,
+    /// Only items in this project.
+    project_id: Option<String>,
+    /// Only items marked done, or with false only those that aren't.
+    completed: Option<bool>,
+    /// Only items waiting on another, or with false only those that aren't.
+    blocked: Option<bool>,
+    /// Archived items are left out unless this is true.
+    include_archived: Option<bool>,
+    /// Items snoozed until later are left out unless this is true.
+    include_snoozed: Option<bool>,
+}
+
+impl From<ItemFilterInput> for ItemFilter {
+    fn from(input: ItemFilterInput) -> Self {
+        ItemFilter {
+            item_type: input.item_type.map(|t| t.to_lowercase()),
+            tags: input.tags,
+            tags_mode: input.tags_mode.unwrap_or_default(),
+            tags_any: input.tags_any,
+            without_tags: input.without_tags.unwrap_or_default(),
+            hierarchical: input.hierarchical.unwrap_or(true),
+            missing: input.missing.unwrap_or_default(),
+            updated_after: input.updated_after,
+            updated_before: input.updated_before,
+            created_after: input.created_after,
+            created_before: input.created_before,
+            due_after: input.due_after,
+            due_before: input.due_before,
+            start_between: None,
+            priority: input.priority,
+            project_id: input.project_id,
+            completed: input.completed,
+            overdue: None,
+            blocked: input.blocked,
+            now: 0,
+            expr: None,
+            exclude_archived: !input.include_archived.unwrap_or(false),
+            exclude_snoozed: !input.include_snoozed.unwrap_or(false),
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn items(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<ItemFilterInput>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<Vec<Item>> {
+        let db = ctx.data::<SharedStore>()?;
+        let mut filter: ItemFilter = filter.unwrap_or_default().into();
+        filter.now = ctx.data::<SharedClock>()?.now_millis();
+        let items = filter::scan_blocking(db, filter)
+            .await?
+            .into_iter()
+            .skip(offset.unwrap_or(0))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        Ok(items)
+    }
+
+    async fn item(&self, ctx: &Context<'_>, id: String) -> async_graphql::Result<Option<Item>> {
+        let db = ctx.data::<SharedStore>()?;
+        match db.get(id.as_bytes())? {
+            Some(value) => Ok(Some(codec::decode(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Every distinct tag in use, sorted alphabetically.
+    async fn tags(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<String>> {
+        let db = ctx.data::<SharedStore>()?;
+        let tags: BTreeSet<String> = filter::scan_blocking(db, ItemFilter::default())
+            .await?
+            .into_iter()
+            .flat_map(|item| item.tags)
+            .collect();
+        Ok(tags.into_iter().collect())
+    }
+}
+
+pub fn build_schema(db: SharedStore) -> NeonoteSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(db)
+        .finish()
+}
+
+/// Runs a query against the caller's namespace; the store given to the
+/// schema at build time is only a fallback.
+pub async fn graphql_handler(
+    schema: web::Data<NeonoteSchema>,
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    req: GraphQLRequest,
+) -> GraphQLResponse {
+    let req = req
+        .into_inner()
+        .data(db.into_inner())
+        .data(clock.get_ref().clone());
+    schema.execute(req).await.into()
+}
@@ -0,0 +1,89 @@
+use crate::{
+    config::Config,
+    error::ApiError,
+    load_item,
+    recent::{Claim, RecentKey},
+    store::StoreResult,
+    Item, SharedStore,
+};
+use actix_web::HttpRequest;
+
+pub const KEY_TREE: &str = "idempotency_keys";
+pub const HEADER: &str = "Idempotency-Key";
+
+pub fn key_tree(db: &SharedStore) -> StoreResult<SharedStore> {
+    db.tree(KEY_TREE)
+}
+
+/// The client-supplied idempotency key, if the request carries one.
+pub fn request_key(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(HEADER)?.to_str().ok()?.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// An idempotency key this request holds. Dropping it without
+/// [`HeldKey::record`] releases the key, so a request that failed before
+/// creating anything can be retried.
+pub struct HeldKey {
+    key: RecentKey,
+    recorded: bool,
+}
+
+impl HeldKey {
+    /// Records `item` as what the request created, for retries to replay.
+    pub fn record(mut self, item: &Item) -> Result<(), ApiError> {
+        self.key
+            .record(&item.id, item.created_at)
+            .map_err(|_| ApiError::Internal("Failed to record request key"))?;
+        self.recorded = true;
+        Ok(())
+    }
+}
+
+impl Drop for HeldKey {
+    fn drop(&mut self) {
+        if !self.recorded {
+            // Left in place, the claim still expires with the window.
+            let _ = self.key.release();
+        }
+    }
+}
+
+/// What to do with a request after [`claim`].
+pub enum Claimed {
+    /// Handle it, recording what it creates under the key if it sent one.
+    Fresh(Option<HeldKey>),
+    /// Answer with the item an earlier request with the same key created.
+    Replay(Box<Item>),
+}
+
+/// Claims the request's idempotency key before it is handled, so of two
+/// requests sent with the same key only one creates anything. A key is
+/// scoped to the method and path it was sent to; while the request holding
+/// it runs, others with it get 409.
+pub fn claim(
+    req: &HttpRequest,
+    db: &SharedStore,
+    config: &Config,
+    now: i64,
+) -> Result<Claimed, ApiError> {
+    let Some(key) = request_key(req) else {
+        return Ok(Claimed::Fresh(None));
+    };
+    let key = format!("{} {} {key}", req.method(), req.path());
+    let window_ms = config.idempotency_ttl_secs as i64 * 1000;
+    let key = RecentKey::new(key_tree(db)?, key, window_ms);
+    let claim = key
+        .claim(now, |id| load_item(db, id).is_err())
+        .map_err(|_| ApiError::Internal("Failed to claim request key"))?;
+    match claim {
+        Claim::Won => Ok(Claimed::Fresh(Some(HeldKey {
+            key,
+            recorded: false,
+        }))),
+        Claim::Recorded(id) => Ok(Claimed::Replay(Box::new(load_item(db, &id)?))),
+        Claim::Pending => Err(ApiError::Conflict(format!(
+            "A request with this {HEADER} is still in progress"
+        ))),
+    }
+}
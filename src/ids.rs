@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use ulid::Ulid;
+use uuid::Uuid;
+
+/// Longest title-derived stem used by [`SlugIds`], before the suffix.
+const SLUG_STEM_LEN: usize = 40;
+
+/// How new item IDs are minted. Selected with `ID_STRATEGY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdStrategy {
+    Uuid,
+    Ulid,
+    ShortSlug,
+}
+
+/// Source of IDs for newly created items.
+pub trait IdGenerator: Send + Sync {
+    /// Mints an ID for an item titled `title`, created at `now_millis`.
+    fn generate(&self, title: &str, now_millis: i64) -> String;
+}
+
+pub type SharedIdGenerator = Arc<dyn IdGenerator>;
+
+pub fn generator(strategy: IdStrategy) -> SharedIdGenerator {
+    match strategy {
+        IdStrategy::Uuid => Arc::new(UuidIds),
+        IdStrategy::Ulid => Arc::new(UlidIds),
+        IdStrategy::ShortSlug => Arc::new(SlugIds),
+    }
+}
+
+/// Random, opaque UUID v4s.
+pub struct UuidIds;
+
+impl IdGenerator for UuidIds {
+    fn generate(&self, _title: &str, _now_millis: i64) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// ULIDs: time-ordered, so keys, and therefore default listings, sort by
+/// creation.
+pub struct UlidIds;
+
+impl IdGenerator for UlidIds {
+    fn generate(&self, _title: &str, now_millis: i64) -> String {
+        Ulid::from_parts(now_millis as u64, Uuid::new_v4().as_u128()).to_string()
+    }
+}
+
+/// Readable IDs such as `buy-milk-3f9a1c`: the title slugified, plus a short
+/// random suffix to keep repeated titles apart.
+pub struct SlugIds;
+
+impl IdGenerator for SlugIds {
+    fn generate(&self, title: &str, _now_millis: i64) -> String {
+        let suffix = &Uuid::new_v4().simple().to_string()[..6];
+        match slugify(title) {
+            stem if stem.is_empty() => format!("item-{suffix}"),
+            stem => format!("{stem}-{suffix}"),
+        }
+    }
+}
+
+/// Lowercase ASCII alphanumeric runs of `title` joined by dashes, cut at a
+/// word boundary once it passes [`SLUG_STEM_LEN`].
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if !slug.is_empty() && slug.len() + word.len() >= SLUG_STEM_LEN {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    slug.truncate(SLUG_STEM_LEN);
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ulids_sort_by_creation_time() {
+        let earlier = UlidIds.generate("", 1_000);
+        let later = UlidIds.generate("", 2_000);
+        assert!(earlier < later);
+    }
+
+    #[test]
+    fn slugs_keep_readable_title_words() {
+        let id = SlugIds.generate("Buy milk & eggs!", 0);
+        assert!(id.starts_with("buy-milk-eggs-"), "{id}");
+        assert_eq!(id.len(), "buy-milk-eggs-".len() + 6);
+        assert!(SlugIds.generate("¿¿", 0).starts_with("item-"));
+    }
+}
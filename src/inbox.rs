@@ -0,0 +1,73 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    clock::SharedClock,
+    config::Config,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    load_item, save_item,
+    tenant::{Tenant, TenantStore},
+    time::TimeQuery,
+    types::ItemType,
+    validation, Item,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FilePayload {
+    tags: Vec<String>,
+    #[serde(rename = "type")]
+    item_type: Option<ItemType>,
+}
+
+/// An unprocessed capture: a plain note with no deadline and no tags beyond
+/// the bare `note` marker the capture parser may have added.
+fn is_inbox(item: &Item) -> bool {
+    item.item_type == ItemType::Note
+        && item.due_date.is_none()
+        && item.tags.iter().all(|tag| tag.eq_ignore_ascii_case("note"))
+}
+
+/// Notes still waiting to be filed, oldest first. Archived and snoozed
+/// notes stay out of the way like they do in listings.
+pub async fn list_inbox(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    time_query: web::Query<TimeQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let filter = ItemFilter {
+        exclude_archived: true,
+        exclude_snoozed: true,
+        now: clock.now_millis(),
+        ..ItemFilter::default()
+    };
+    let items = filter::scan_blocking(&db, filter).await?;
+    let mut items: Vec<Item> = items.into_iter().filter(is_inbox).collect();
+    filter::sort_by_key(&mut items, |item| item.created_at);
+
+    Ok(HttpResponse::Ok().json(time_query.time.view(items)))
+}
+
+/// Recategorizes an item in one step, replacing its tags and optionally its
+/// type, which takes it out of the inbox.
+pub async fn file_item(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    payload: web::Json<FilePayload>,
+) -> Result<HttpResponse, ApiError> {
+    let mut item = load_item(&db, &path.into_inner())?;
+
+    let payload = payload.into_inner();
+    item.tags = payload.tags;
+    if let Some(item_type) = payload.item_type {
+        item.item_type = item_type;
+    }
+    validation::validate_item(&mut item, &config).map_err(ApiError::Invalid)?;
+
+    item.touch(clock.now_millis());
+    save_item(&db, &tenant, &item)?;
+    Ok(HttpResponse::Ok().json(item))
+}
@@ -0,0 +1,374 @@
+//! Secondary indexes over the item keyspace, so filtered reads touch only the
+//! items that can match instead of decoding every record. Each index is a
+//! side tree of keys with empty values: the indexed value, then the item id.
+//! Writes that go through [`crate::save_items`] update them in the same batch
+//! as the items, via [`IndexOps`]; the rest call [`reindex`] after their
+//! write. Either way the search index is kept current too, and [`build`]
+//! repairs whatever a failed write left behind at startup.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    codec,
+    filter::{self, ItemFilter, TagsMode},
+    search,
+    store::{BatchOp, StoreResult},
+    tenant, Item, SharedStore,
+};
+
+/// Lowercased type, `\0`, id.
+pub const TYPE_TREE: &str = "idx_type";
+/// Tag, `\0`, id.
+pub const TAG_TREE: &str = "idx_tag";
+/// Due date as eight order-preserving bytes, then id.
+pub const DUE_TREE: &str = "idx_due";
+
+const TREES: [&str; 4] = [TYPE_TREE, TAG_TREE, DUE_TREE, search::INDEX_TREE];
+
+fn text_key(value: &str, id: &str) -> Vec<u8> {
+    [value.as_bytes(), &[0], id.as_bytes()].concat()
+}
+
+/// Big-endian with the sign bit flipped, so byte order is numeric order.
+fn due_bytes(due: i64) -> [u8; 8] {
+    ((due as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+/// The keys `item` holds in each of [`TREES`].
+fn keys(item: &Item) -> [BTreeSet<Vec<u8>>; 4] {
+    let types = BTreeSet::from([text_key(item.item_type.as_str(), &item.id)]);
+    let tags = item
+        .tags
+        .iter()
+        .map(|tag| text_key(tag, &item.id))
+        .collect();
+    let due = item
+        .due_date
+        .map(|due| [&due_bytes(due)[..], item.id.as_bytes()].concat())
+        .into_iter()
+        .collect();
+    [types, tags, due, search::keys(item)]
+}
+
+/// The index updates of one or more writes, per tree, to be applied in the
+/// same batch as the writes themselves.
+#[derive(Default)]
+pub struct IndexOps([Vec<BatchOp>; 4]);
+
+impl IndexOps {
+    /// Adds the updates for a write that changed `old` into `new`; `None` on
+    /// either side for a create or a delete.
+    pub fn add(&mut self, old: Option<&Item>, new: Option<&Item>) {
+        let old_keys = old.map(keys).unwrap_or_default();
+        let new_keys = new.map(keys).unwrap_or_default();
+        for ((ops, before), after) in self.0.iter_mut().zip(&old_keys).zip(&new_keys) {
+            ops.extend(
+                before
+                    .difference(after)
+                    .map(|key| BatchOp::Remove(key.clone())),
+            );
+            ops.extend(
+                after
+                    .difference(before)
+                    .map(|key| BatchOp::Insert(key.clone(), Vec::new())),
+            );
+        }
+    }
+
+    /// The updates as side trees for [`crate::store::Store::batch_if`].
+    pub fn into_sides(self) -> Vec<(&'static str, Vec<BatchOp>)> {
+        TREES
+            .into_iter()
+            .zip(self.0)
+            .filter(|(_, ops)| !ops.is_empty())
+            .collect()
+    }
+}
+
+/// Brings every index in line with a write that changed `old` into `new`,
+/// for writes that can't carry [`IndexOps`] in their own batch.
+pub fn reindex(db: &SharedStore, old: Option<&Item>, new: Option<&Item>) -> StoreResult<()> {
+    let mut ops = IndexOps::default();
+    ops.add(old, new);
+    for (tree, ops) in ops.into_sides() {
+        db.tree(tree)?.batch(ops)?;
+    }
+    Ok(())
+}
+
+fn stored_keys(tree: &SharedStore) -> StoreResult<BTreeSet<Vec<u8>>> {
+    tree.iter().map(|entry| Ok(entry?.0)).collect()
+}
+
+/// Checks the indexes of the default keyspace and of every tenant against
+/// the items stored, as after an upgrade or a write whose [`reindex`]
+/// failed, adding missing entries and dropping stale ones. Returns how many
+/// items had entries missing.
+pub fn build(db: &SharedStore, tenant_labels: &[&str]) -> StoreResult<usize> {
+    let mut keyspaces = vec![db.clone()];
+    for label in tenant_labels {
+        keyspaces.push(tenant::tenant_store(db, label)?);
+    }
+
+    let mut indexed = 0;
+    for keyspace in keyspaces {
+        let mut stored = Vec::with_capacity(TREES.len());
+        for tree in TREES {
+            stored.push(stored_keys(&keyspace.tree(tree)?)?);
+        }
+        let mut expected: [BTreeSet<Vec<u8>>; 4] = Default::default();
+        for item in filter::iter(&keyspace, ItemFilter::default()) {
+            let keys = keys(&item);
+            if keys
+                .iter()
+                .zip(&stored)
+                .any(|(keys, stored)| !keys.is_subset(stored))
+            {
+                indexed += 1;
+            }
+            for (expected, keys) in expected.iter_mut().zip(keys) {
+                expected.extend(keys);
+            }
+        }
+        for ((tree, expected), stored) in TREES.into_iter().zip(expected).zip(stored) {
+            let mut ops: Vec<BatchOp> = stored
+                .difference(&expected)
+                .map(|key| BatchOp::Remove(key.clone()))
+                .collect();
+            ops.extend(
+                expected
+                    .difference(&stored)
+                    .map(|key| BatchOp::Insert(key.clone(), Vec::new())),
+            );
+            if !ops.is_empty() {
+                keyspace.tree(tree)?.batch(ops)?;
+            }
+        }
+    }
+    Ok(indexed)
+}
+
+/// IDs in `tree` under keys starting with `prefix`, where the id follows the
+/// first `\0`.
+fn ids_under(tree: &SharedStore, prefix: &[u8]) -> StoreResult<BTreeSet<String>> {
+    let mut ids = BTreeSet::new();
+    for entry in tree.iter_after(prefix) {
+        let (key, _) = entry?;
+        if !key.starts_with(prefix) {
+            break;
+        }
+        if let Some(split) = key.iter().position(|&b| b == 0) {
+            ids.insert(String::from_utf8_lossy(&key[split + 1..]).into_owned());
+        }
+    }
+    Ok(ids)
+}
+
+/// IDs of the items carrying `tag`, or with `hierarchical` any tag nested
+/// under it.
+fn tagged(tags: &SharedStore, tag: &str, hierarchical: bool) -> StoreResult<BTreeSet<String>> {
+    let mut ids = ids_under(tags, &text_key(tag, ""))?;
+    if hierarchical {
+        ids.extend(ids_under(tags, format!("{tag}/").as_bytes())?);
+    }
+    Ok(ids)
+}
+
+/// IDs of the items carrying at least one of `wanted`.
+fn tagged_any(
+    tags: &SharedStore,
+    wanted: &[String],
+    hierarchical: bool,
+) -> StoreResult<BTreeSet<String>> {
+    let mut ids = BTreeSet::new();
+    for tag in wanted {
+        ids.extend(tagged(tags, tag, hierarchical)?);
+    }
+    Ok(ids)
+}
+
+/// IDs, in key order, of every item that could satisfy `filter` as far as
+/// the indexes can tell; `None` when it names nothing indexed and only a
+/// full scan will do. Candidates still need [`ItemFilter::matches`].
+pub fn candidates(db: &SharedStore, filter: &ItemFilter) -> StoreResult<Option<BTreeSet<String>>> {
+    let mut found: Option<BTreeSet<String>> = None;
+    let mut narrow = |ids: BTreeSet<String>| {
+        found = Some(match found.take() {
+            Some(found) => found.intersection(&ids).cloned().collect(),
+            None => ids,
+        });
+    };
+
+    if let Some(item_type) = &filter.item_type {
+        narrow(ids_under(&db.tree(TYPE_TREE)?, &text_key(item_type, ""))?);
+    }
+    if let Some(wanted) = &filter.tags {
+        let tags = db.tree(TAG_TREE)?;
+        match filter.tags_mode {
+            TagsMode::All => {
+                for tag in wanted {
+                    narrow(tagged(&tags, tag, filter.hierarchical)?);
+                }
+            }
+            TagsMode::Any => narrow(tagged_any(&tags, wanted, filter.hierarchical)?),
+        }
+    }
+    if let Some(wanted) = &filter.tags_any {
+        narrow(tagged_any(
+            &db.tree(TAG_TREE)?,
+            wanted,
+            filter.hierarchical,
+        )?);
+    }
+    // The filter's due bounds are exclusive and the range read here isn't;
+    // matching drops the items due exactly on one.
+    if filter.due_after.is_some() || filter.due_before.is_some() {
+        narrow(
+            due_ids(db, filter.due_after, filter.due_before)?
+                .into_iter()
+                .collect(),
+        );
+    }
+    if filter.overdue == Some(true) {
+        narrow(
+            due_ids(db, None, Some(filter.now - 1))?
+                .into_iter()
+                .collect(),
+        );
+    }
+    Ok(found)
+}
+
+/// IDs of the items due within `from..=to`, soonest first, ties in id order.
+/// Open ends are unbounded.
+fn due_ids(db: &SharedStore, from: Option<i64>, to: Option<i64>) -> StoreResult<Vec<String>> {
+    let due = db.tree(DUE_TREE)?;
+    let entries = match from {
+        // Keys at `from` extend its eight bytes with an id, so they all sort
+        // after the bare prefix.
+        Some(from) => due.iter_after(&due_bytes(from)),
+        None => due.iter(),
+    };
+    let end = to.map(due_bytes);
+
+    let mut ids = Vec::new();
+    for entry in entries {
+        let (key, _) = entry?;
+        let (at, id) = key.split_at(8.min(key.len()));
+        if end.is_some_and(|end| at > &end[..]) {
+            break;
+        }
+        ids.push(String::from_utf8_lossy(id).into_owned());
+    }
+    Ok(ids)
+}
+
+/// Items due within `from..=to`, soonest first, ties in id order.
+pub fn due_between(db: &SharedStore, from: Option<i64>, to: i64) -> StoreResult<Vec<Item>> {
+    let mut items = Vec::new();
+    for id in due_ids(db, from, Some(to))? {
+        if let Some(raw) = db.get(id.as_bytes())? {
+            if let Ok(item) = codec::decode::<Item>(&raw) {
+                items.push(item);
+            }
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Encoding, store::MemoryStore};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn item(id: &str, item_type: &str, tags: &[&str], due: Option<i64>) -> Item {
+        serde_json::from_value(json!({
+            "id": id, "type": item_type, "title": id, "content": null, "tags": tags,
+            "code_location": null, "created_at": 0, "completed": null,
+            "due_date": due, "start_time": null, "end_time": null,
+        }))
+        .unwrap()
+    }
+
+    fn ids(found: Option<BTreeSet<String>>) -> Vec<String> {
+        found.unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn builds_then_follows_writes() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let a = item("a", "Task", &["work/urgent"], Some(20));
+        for stored in [&a, &item("b", "note", &["work"], Some(10))] {
+            let bytes = codec::encode(db.encoding(), stored).unwrap();
+            db.insert(stored.id.as_bytes(), bytes).unwrap();
+        }
+        assert_eq!(build(&db, &[]).unwrap(), 2);
+        assert_eq!(build(&db, &[]).unwrap(), 0);
+
+        let by_type = ItemFilter {
+            item_type: Some("task".into()),
+            ..ItemFilter::default()
+        };
+        assert_eq!(ids(candidates(&db, &by_type).unwrap()), ["a"]);
+        let nested = ItemFilter {
+            tags: Some(vec!["work".into()]),
+            hierarchical: true,
+            ..ItemFilter::default()
+        };
+        assert_eq!(ids(candidates(&db, &nested).unwrap()), ["a", "b"]);
+        assert!(candidates(&db, &ItemFilter::default()).unwrap().is_none());
+        let due: Vec<String> = due_between(&db, Some(10), 15)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.id)
+            .collect();
+        assert_eq!(due, ["b"]);
+
+        let moved = item("a", "note", &[], None);
+        reindex(&db, Some(&a), Some(&moved)).unwrap();
+        assert_eq!(
+            ids(candidates(&db, &by_type).unwrap()),
+            Vec::<String>::new()
+        );
+        assert!(db
+            .tree(DUE_TREE)
+            .unwrap()
+            .iter()
+            .all(|e| !e.unwrap().0.ends_with(b"a")));
+    }
+
+    #[test]
+    fn build_repairs_stale_entries() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let a = item("a", "task", &[], None);
+        let bytes = codec::encode(db.encoding(), &a).unwrap();
+        db.insert(b"a", bytes).unwrap();
+        build(&db, &[]).unwrap();
+
+        // As if the item became a note but reindexing it failed.
+        let moved = item("a", "note", &[], None);
+        let bytes = codec::encode(db.encoding(), &moved).unwrap();
+        db.insert(b"a", bytes).unwrap();
+        assert_eq!(build(&db, &[]).unwrap(), 1);
+        let by_type = |item_type: &str| ItemFilter {
+            item_type: Some(item_type.into()),
+            ..ItemFilter::default()
+        };
+        assert_eq!(ids(candidates(&db, &by_type("note")).unwrap()), ["a"]);
+        assert!(ids(candidates(&db, &by_type("task")).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn due_bytes_sort_numerically() {
+        let mut values = [5_i64, -3, 0, i64::MIN, i64::MAX, -1];
+        let mut encoded: Vec<[u8; 8]> = values.iter().map(|&v| due_bytes(v)).collect();
+        values.sort();
+        encoded.sort();
+        assert_eq!(
+            encoded,
+            values.iter().map(|&v| due_bytes(v)).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{
+    codec,
+    store::{BatchOp, StoreResult},
+    tenant, Item, SharedStore,
+};
+
+/// Where records that no longer deserialize are moved, keyed as before.
+pub const CORRUPT_TREE: &str = "corrupt";
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub good: usize,
+    pub bad: usize,
+}
+
+/// Checks that every record in the item keyspace, and in each tenant's,
+/// deserializes as an [`Item`] and quarantines the ones that don't, so
+/// handlers only ever see clean data. Bad records are copied to the
+/// keyspace's [`CORRUPT_TREE`] before being removed.
+pub fn scan_and_repair(db: &SharedStore, tenant_labels: &[&str]) -> StoreResult<ScanReport> {
+    let mut keyspaces = vec![db.clone()];
+    for label in tenant_labels {
+        keyspaces.push(tenant::tenant_store(db, label)?);
+    }
+
+    let mut report = ScanReport::default();
+    for keyspace in keyspaces {
+        scan_keyspace(&keyspace, &mut report)?;
+    }
+    Ok(report)
+}
+
+fn scan_keyspace(db: &SharedStore, report: &mut ScanReport) -> StoreResult<()> {
+    let corrupt = db.tree(CORRUPT_TREE)?;
+    let mut removals = Vec::new();
+
+    for entry in db.iter() {
+        let (key, value) = entry?;
+        if codec::decode::<Item>(&value).is_ok() {
+            report.good += 1;
+            continue;
+        }
+        eprintln!(
+            "Quarantining corrupt record {}",
+            String::from_utf8_lossy(&key)
+        );
+        corrupt.insert(&key, value)?;
+        removals.push(BatchOp::Remove(key));
+        report.bad += 1;
+    }
+
+    db.batch(removals)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Encoding, store::MemoryStore};
+    use std::sync::Arc;
+
+    #[test]
+    fn quarantines_records_that_are_not_items() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let good = serde_json::json!({
+            "id": "good", "type": "note", "title": "ok", "content": null, "tags": [],
+            "code_location": null, "created_at": 0, "completed": null,
+            "due_date": null, "start_time": null, "end_time": null,
+        });
+        db.insert(b"good", serde_json::to_vec(&good).unwrap())
+            .unwrap();
+        db.insert(b"bad", b"{\"id\": \"ba".to_vec()).unwrap();
+        let work = tenant::tenant_store(&db, "work").unwrap();
+        work.insert(b"worse", b"[]".to_vec()).unwrap();
+
+        let report = scan_and_repair(&db, &["work"]).unwrap();
+        assert_eq!((report.good, report.bad), (1, 2));
+        assert!(db.get(b"good").unwrap().is_some());
+        assert!(db.get(b"bad").unwrap().is_none());
+        let corrupt = db.tree(CORRUPT_TREE).unwrap();
+        assert_eq!(corrupt.get(b"bad").unwrap().unwrap(), b"{\"id\": \"ba");
+        assert!(work.get(b"worse").unwrap().is_none());
+        let corrupt = work.tree(CORRUPT_TREE).unwrap();
+        assert_eq!(corrupt.get(b"worse").unwrap().unwrap(), b"[]");
+    }
+}
@@ -0,0 +1,77 @@
+use actix_web::HttpResponse;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::{
+    audit::{self, Action},
+    codec,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    store::{BatchOp, StoreResult},
+    tenant::{Tenant, TenantStore},
+    SharedStore,
+};
+
+/// Response header on `DELETE /items/{id}` counting the items whose links to
+/// the deleted item were removed.
+pub const CLEANED_HEADER: &str = "X-Links-Cleaned";
+
+/// Removes `target` from the `links` and `blocked_by` of every item that
+/// points at it, in one batch, logging each change as an update at `now`.
+/// Returns how many items were changed.
+pub fn strip_links(
+    db: &SharedStore,
+    tenant: &Tenant,
+    target: &str,
+    now: i64,
+) -> StoreResult<usize> {
+    let mut entries = Vec::new();
+    let ops: Vec<BatchOp> = filter::iter(db, ItemFilter::default())
+        .filter(|item| {
+            item.links.iter().any(|link| link == target)
+                || item.blocked_by.iter().any(|id| id == target)
+        })
+        .filter_map(|mut item| {
+            item.links.retain(|link| link != target);
+            item.blocked_by.retain(|id| id != target);
+            item.touch(now);
+            let bytes = codec::encode(db.encoding(), &item).ok()?;
+            entries.push(audit::Entry::new(tenant, Action::Update, &item.id, now));
+            Some(BatchOp::Insert(item.id.into_bytes(), bytes))
+        })
+        .collect();
+    let cleaned = ops.len();
+    audit::commit(db, ops, &entries)?;
+    Ok(cleaned)
+}
+
+#[derive(Debug, Serialize)]
+struct DanglingLinks {
+    id: String,
+    dangling: Vec<String>,
+}
+
+/// `GET /admin/orphan-links`: every item that links to an ID no longer in the
+/// store, with the missing targets. Reports only; nothing is changed.
+pub async fn orphan_check(db: TenantStore) -> Result<HttpResponse, ApiError> {
+    let items = filter::scan_blocking(&db, ItemFilter::default()).await?;
+
+    let ids: HashSet<&str> = items.iter().map(|item| item.id.as_str()).collect();
+    let orphans: Vec<DanglingLinks> = items
+        .iter()
+        .filter_map(|item| {
+            let dangling: Vec<String> = item
+                .links
+                .iter()
+                .filter(|link| !ids.contains(link.as_str()))
+                .cloned()
+                .collect();
+            (!dangling.is_empty()).then(|| DanglingLinks {
+                id: item.id.clone(),
+                dangling,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(orphans))
+}
@@ -1,68 +1,241 @@
 use actix_web::{
-    body::{BoxBody, EitherBody},
-    dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    web, App, Error, HttpResponse, HttpServer, Responder,
+    body::{BoxBody, EitherBody, MessageBody},
+    dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    middleware, web, App, Error, HttpMessage, HttpRequest, HttpResponse, HttpServer, ResponseError,
+    Route,
 };
+use async_graphql::{ComplexObject, SimpleObject};
 use futures_util::future::{ok, LocalBoxFuture, Ready};
 use serde::{Deserialize, Serialize};
-use sled::Db;
 use std::{
-    env,
+    path::Path,
     rc::Rc,
     sync::Arc,
     task::{Context, Poll},
-    time::SystemTime,
+    time::Duration,
 };
-use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+mod access;
+mod admin;
+mod agenda;
+mod append;
+mod archive;
+mod attachments;
+mod audit;
+mod batch;
+mod board;
+mod bulk;
+mod calendar;
+mod capture;
+mod clock;
+mod code;
+mod codec;
+mod complete;
+mod config;
+mod convert;
+mod daily;
+mod dependencies;
+mod digest;
+mod error;
+mod etag;
+mod export;
+mod feeds;
+mod fields;
+mod filter;
+mod graphql;
+mod idempotency;
+mod ids;
+mod inbox;
+mod index;
+mod integrity;
+mod links;
+mod notify;
+mod priority;
+mod progress;
+mod projects;
+mod query;
+mod rank;
+mod read_only;
+mod recent;
+mod recurrence;
+mod reminders;
+mod revisions;
+mod rules;
+mod search;
+mod snooze;
+mod store;
+mod stream;
+mod subtasks;
+mod tags;
+mod templates;
+mod tenant;
+#[cfg(test)]
+mod tests;
+mod time;
+mod trash;
+mod types;
+mod tz;
+mod validation;
+mod views;
+
+use admin::StartupInfo;
+use append::AppendBuffer;
+use attachments::Attachment;
+use audit::Action;
+use clock::{SharedClock, SystemClock};
+use codec::Encoding;
+use config::{CaptureId, Config, StoreBackend, TagOverflow};
+use error::ApiError;
+use fields::Fields;
+use filter::{ItemFilter, Page, Sort};
+use idempotency::Claimed;
+use ids::SharedIdGenerator;
+use index::IndexOps;
+use recent::RecentKey;
+use store::{BatchOp, Check, Guard, MemoryStore, SledStore, Store, StoreResult};
+use stream::Shape;
+use tenant::{Tenant, TenantStore};
+use time::TimeFormat;
+use types::ItemType;
+
+#[derive(Debug, Serialize, Deserialize, Clone, SimpleObject)]
 struct CodeLocation {
     file_path: String,
     line_number: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, SimpleObject)]
+#[graphql(complex)]
 struct Item {
     id: String,
     #[serde(rename = "type")]
-    item_type: String, // e.g., "note", "task", "event", etc.
+    #[graphql(skip)]
+    item_type: ItemType,
     title: String,
     content: Option<String>,
     tags: Vec<String>,
     code_location: Option<CodeLocation>,
     created_at: i64,
+    /// Last modification time; absent on items stored before it was tracked.
+    #[serde(default)]
+    updated_at: Option<i64>,
     completed: Option<bool>,
+    /// When the item was last marked completed; `None` while it isn't.
+    #[serde(default)]
+    completed_at: Option<i64>,
     due_date: Option<i64>,
     start_time: Option<i64>,
     end_time: Option<i64>,
+    /// An RRULE the item repeats by; see [`recurrence`].
+    #[serde(default)]
+    recurrence: Option<String>,
+    /// 1 (most urgent) to 4; see [`priority`].
+    #[serde(default)]
+    priority: Option<u8>,
+    /// The board column the item is in, one of `STATUSES`; see [`board`].
+    #[serde(default)]
+    status: Option<String>,
+    /// Where the user placed the item in a manual ordering; see [`rank`].
+    #[serde(default)]
+    rank: Option<String>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// IDs of related items. Removed automatically when the target is deleted.
+    #[serde(default)]
+    links: Vec<String>,
+    /// The item this one is a subtask of.
+    #[serde(default)]
+    parent_id: Option<String>,
+    /// The project the item belongs to; see [`projects`].
+    #[serde(default)]
+    project_id: Option<String>,
+    /// Open items this one waits on; see [`dependencies`].
+    #[serde(default)]
+    blocked_by: Vec<String>,
+    /// Hidden from listings unless they ask for archived items.
+    #[serde(default)]
+    archived: bool,
+    /// Hidden from listings until this time unless they ask for snoozed
+    /// items.
+    #[serde(default)]
+    snoozed_until: Option<i64>,
+    /// Bumped by every change; sent as the ETag. Items stored before it was
+    /// tracked start at 0.
+    #[serde(default)]
+    version: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CreateItemPayload {
+    /// Client-chosen ID, for deterministic sync. Generated when absent.
+    #[serde(default)]
+    id: Option<String>,
     #[serde(rename = "type")]
-    item_type: String,
+    item_type: ItemType,
     title: String,
     content: Option<String>,
     tags: Option<Vec<String>>,
     code_location: Option<CodeLocation>,
     completed: Option<bool>,
+    #[serde(default, deserialize_with = "time::deserialize_opt_millis")]
     due_date: Option<i64>,
+    #[serde(default, deserialize_with = "time::deserialize_opt_millis")]
     start_time: Option<i64>,
+    #[serde(default, deserialize_with = "time::deserialize_opt_millis")]
     end_time: Option<i64>,
+    recurrence: Option<String>,
+    #[serde(default, deserialize_with = "priority::deserialize_opt")]
+    priority: Option<u8>,
+    status: Option<String>,
+    links: Option<Vec<String>>,
+    parent_id: Option<String>,
+    project_id: Option<String>,
+    blocked_by: Option<Vec<String>>,
 }
 
+/// A partial update. Fields left out are kept; the optional ones can also be
+/// sent as `null` to clear them, which is why they are doubly optional.
 #[derive(Debug, Serialize, Deserialize)]
 struct UpdateItemPayload {
     #[serde(rename = "type")]
-    item_type: Option<String>,
+    item_type: Option<ItemType>,
     title: Option<String>,
-    content: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    content: Option<Option<String>>,
     tags: Option<Vec<String>>,
-    code_location: Option<CodeLocation>,
-    completed: Option<bool>,
-    due_date: Option<i64>,
-    start_time: Option<i64>,
-    end_time: Option<i64>,
+    #[serde(default, deserialize_with = "nullable")]
+    code_location: Option<Option<CodeLocation>>,
+    #[serde(default, deserialize_with = "nullable")]
+    completed: Option<Option<bool>>,
+    #[serde(default, deserialize_with = "time::deserialize_nullable_millis")]
+    due_date: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "time::deserialize_nullable_millis")]
+    start_time: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "time::deserialize_nullable_millis")]
+    end_time: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "nullable")]
+    recurrence: Option<Option<String>>,
+    #[serde(default, deserialize_with = "priority::deserialize_nullable")]
+    priority: Option<Option<u8>>,
+    #[serde(default, deserialize_with = "nullable")]
+    status: Option<Option<String>>,
+    links: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    parent_id: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    project_id: Option<Option<String>>,
+    blocked_by: Option<Vec<String>>,
+}
+
+/// Deserializes a field that is present, even as `null`, to `Some`. Use
+/// together with `#[serde(default)]` so absent fields stay `None`.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,10 +243,164 @@ struct CapturePayload {
     text: String,
 }
 
-type SharedDb = Arc<Db>;
+const DB_PATH: &str = "/usr/src/app/data/notes_db";
+
+type SharedStore = Arc<dyn Store>;
+
+fn load_item(db: &SharedStore, id: &str) -> Result<Item, ApiError> {
+    match db.get(id.as_bytes())? {
+        Some(value) => {
+            codec::decode(&value).map_err(|_| ApiError::Internal("Deserialization failed"))
+        }
+        None => Err(ApiError::NotFound("Item not found")),
+    }
+}
+
+/// Stores a changed item, logging the update in the same batch, keeping the
+/// version it replaces as a revision, and reindexing it.
+fn save_item(db: &SharedStore, tenant: &Tenant, item: &Item) -> Result<(), ApiError> {
+    let old = db
+        .get(item.id.as_bytes())?
+        .and_then(|raw| codec::decode::<Item>(&raw).ok());
+    save_items(db, tenant, &[(old, item.clone())], Vec::new())
+}
+
+/// [`save_item`] for several items at once, each paired with the version it
+/// replaces. All of them, the items they unblock, their audit entries, their
+/// index updates and `sides` are written in one batch. Refused with 409 if
+/// another write got to any of them first.
+fn save_items(
+    db: &SharedStore,
+    tenant: &Tenant,
+    changes: &[(Option<Item>, Item)],
+    sides: Vec<(&str, Vec<BatchOp>)>,
+) -> Result<(), ApiError> {
+    if commit_items(db, tenant, changes, sides)? {
+        Ok(())
+    } else {
+        Err(changed_meanwhile())
+    }
+}
+
+fn changed_meanwhile() -> ApiError {
+    ApiError::Conflict("Item was changed by another request".to_string())
+}
+
+/// A guard check passing only while an item is stored at `version`, or,
+/// for `None`, while nothing is stored under its ID.
+fn still_at(version: Option<u64>) -> Box<Check> {
+    Box::new(move |current| match (current, version) {
+        (None, None) => true,
+        (Some(raw), Some(version)) => {
+            codec::decode::<Item>(raw).is_ok_and(|stored| stored.version == version)
+        }
+        _ => false,
+    })
+}
+
+/// The batch [`save_items`] writes, applied only while every replaced item
+/// is still stored at the version it was read at and every new one's ID is
+/// still free. Returns whether it was applied.
+fn commit_items(
+    db: &SharedStore,
+    tenant: &Tenant,
+    changes: &[(Option<Item>, Item)],
+    mut sides: Vec<(&str, Vec<BatchOp>)>,
+) -> Result<bool, ApiError> {
+    let unblocked = dependencies::unblocked(db, changes);
+    let changes: Vec<_> = changes.iter().chain(&unblocked).collect();
+    let mut ops = Vec::with_capacity(changes.len());
+    let mut entries = Vec::with_capacity(changes.len());
+    let mut index_ops = IndexOps::default();
+    let mut revision_ops = Vec::new();
+    for (old, item) in changes.iter().copied() {
+        let bytes = codec::encode(db.encoding(), item)
+            .map_err(|_| ApiError::Internal("Serialization failed"))?;
+        let changed_at = item.updated_at.unwrap_or(item.created_at);
+        if let Some(old) = old {
+            let op = revisions::op(db, old, changed_at)
+                .map_err(|_| ApiError::Internal("Failed to record revision"))?;
+            revision_ops.push(op);
+        }
+        ops.push(BatchOp::Insert(item.id.clone().into_bytes(), bytes));
+        let action = match old {
+            Some(_) => Action::Update,
+            None => Action::Create,
+        };
+        entries.push(audit::Entry::new(tenant, action, &item.id, changed_at));
+        index_ops.add(old.as_ref(), Some(item));
+    }
+    if !revision_ops.is_empty() {
+        sides.push((revisions::REVISION_TREE, revision_ops));
+    }
+    sides.extend(index_ops.into_sides());
+    let checks: Vec<(&[u8], Box<Check>)> = changes
+        .iter()
+        .map(|(old, item)| {
+            (
+                item.id.as_bytes(),
+                still_at(old.as_ref().map(|old| old.version)),
+            )
+        })
+        .collect();
+    let guards: Vec<Guard> = checks
+        .iter()
+        .map(|(key, check)| (*key, check.as_ref()))
+        .collect();
+    audit::commit_if(db, &guards, ops, sides, &entries)
+        .map_err(|_| ApiError::Internal("Update failed"))
+}
+
+/// How often expired idempotency keys and capture hashes are swept out.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Drops the idempotency keys and capture hashes that have outlived their
+/// windows, in the default keyspace and every tenant's. Returns how many
+/// went.
+fn prune_recent_keys(db: &SharedStore, config: &Config, now: i64) -> StoreResult<usize> {
+    let mut keyspaces = vec![db.clone()];
+    for (label, _) in &config.tenant_keys {
+        keyspaces.push(tenant::tenant_store(db, label)?);
+    }
+    let idempotency_ms = config.idempotency_ttl_secs as i64 * 1000;
+    let dedup_ms = config.dedup_window_secs as i64 * 1000;
+
+    let mut pruned = 0;
+    for keyspace in keyspaces {
+        pruned += recent::prune(&idempotency::key_tree(&keyspace)?, idempotency_ms, now)?;
+        pruned += recent::prune(&capture::hash_tree(&keyspace)?, dedup_ms, now)?;
+    }
+    Ok(pruned)
+}
+
+/// The item an earlier write recorded under `key`, if it still exists.
+fn replayed_item(db: &SharedStore, key: &RecentKey, now: i64) -> Option<Item> {
+    let id = key.lookup(now)?;
+    load_item(db, &id).ok()
+}
+
+/// Records the freshly created `item` under each of `keys`.
+fn record_keys(keys: &[&RecentKey], item: &Item) -> Result<(), ApiError> {
+    for key in keys {
+        key.record(&item.id, item.created_at)
+            .map_err(|_| ApiError::Internal("Failed to record request key"))?;
+    }
+    Ok(())
+}
+
+/// 201 response for a newly stored item, pointing `Location` at it.
+fn created(item: &Item) -> HttpResponse {
+    HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/items/{}", item.id)))
+        .json(item)
+}
+
+/// Paths served without an API key.
+const PUBLIC_PATHS: &[&str] = &["/version"];
 
 struct ApiKeyMiddleware {
     api_key: String,
+    tenant_keys: Rc<Vec<(String, String)>>,
 }
 
 impl<S, B> Transform<S, ServiceRequest> for ApiKeyMiddleware
@@ -91,6 +418,7 @@ where
         ok(ApiKeyMiddlewareMiddleware {
             service: Rc::new(service),
             api_key: self.api_key.clone(),
+            tenant_keys: self.tenant_keys.clone(),
         })
     }
 }
@@ -98,6 +426,20 @@ where
 struct ApiKeyMiddlewareMiddleware<S> {
     service: Rc<S>,
     api_key: String,
+    tenant_keys: Rc<Vec<(String, String)>>,
+}
+
+impl<S> ApiKeyMiddlewareMiddleware<S> {
+    /// Which tenant `key` authenticates as, if it is valid at all.
+    fn tenant_for(&self, key: &str) -> Option<Tenant> {
+        if key == self.api_key {
+            return Some(Tenant(None));
+        }
+        self.tenant_keys
+            .iter()
+            .find(|(_, k)| k == key)
+            .map(|(label, _)| Tenant(Some(label.clone())))
+    }
 }
 
 impl<S, B> Service<ServiceRequest> for ApiKeyMiddlewareMiddleware<S>
@@ -114,187 +456,472 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let api_key = self.api_key.clone();
-
-        if let Some(header_value) = req.headers().get("X-API-Key") {
-            if let Ok(value) = header_value.to_str() {
-                if value == api_key {
-                    let fut = self.service.call(req);
-                    return Box::pin(async move {
-                        let res = fut.await?;
-                        Ok(res.map_into_left_body())
-                    });
-                }
-            }
+        if PUBLIC_PATHS.contains(&req.path()) {
+            let fut = self.service.call(req);
+            return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+        }
+
+        let tenant = req
+            .headers()
+            .get("X-API-Key")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| self.tenant_for(value));
+
+        if let Some(tenant) = tenant {
+            req.extensions_mut().insert(tenant);
+            let fut = self.service.call(req);
+            return Box::pin(async move {
+                let res = fut.await?;
+                Ok(res.map_into_left_body())
+            });
         }
 
         let (req, _) = req.into_parts();
-        let res = HttpResponse::Unauthorized()
-            .body("Missing or invalid API key")
+        let res = ApiError::Unauthorized
+            .error_response()
             .map_into_right_body();
         Box::pin(async move { Ok(ServiceResponse::new(req, res)) })
     }
 }
 
-async fn list_items(db: web::Data<SharedDb>) -> impl Responder {
-    let items: Vec<Item> = db
-        .iter()
-        .filter_map(|item| {
-            if let Ok((_, val)) = item {
-                serde_json::from_slice(&val).ok()
-            } else {
-                None
-            }
-        })
-        .collect();
+async fn get_item(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let strict = filter::is_strict(&query, config.strict_query).map_err(ApiError::BadRequest)?;
+    let time = TimeFormat::from_query(&query).map_err(ApiError::BadRequest)?;
+    let fields = Fields::from_query(&query, strict).map_err(ApiError::BadRequest)?;
+
+    let item = load_item(&db, &path.into_inner())?;
+    if config.track_access {
+        access::record(&db, &item.id, clock.now_millis())
+            .map_err(|_| ApiError::Internal("Failed to record access"))?;
+    }
+    let etag = etag::header(&item);
+    let store = db.into_inner();
+    let item = web::block(move || progress::attach(&store, item)).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag)
+        .json(fields.view(time.view(item))))
+}
+
+#[ComplexObject]
+impl Item {
+    #[graphql(name = "type")]
+    async fn item_type(&self) -> &str {
+        self.item_type.as_str()
+    }
+}
+
+impl stream::Keyed for Item {
+    fn key(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Item {
+    /// Builds a new item from a create payload, before validation.
+    fn from_payload(id: String, created_at: i64, payload: &CreateItemPayload) -> Self {
+        Item {
+            id,
+            item_type: payload.item_type.clone(),
+            title: payload.title.clone(),
+            content: payload.content.clone(),
+            tags: payload.tags.clone().unwrap_or_default(),
+            code_location: payload.code_location.clone(),
+            created_at,
+            updated_at: Some(created_at),
+            completed: payload.completed,
+            completed_at: (payload.completed == Some(true)).then_some(created_at),
+            due_date: payload.due_date,
+            start_time: payload.start_time,
+            end_time: payload.end_time,
+            recurrence: payload.recurrence.clone(),
+            priority: payload.priority,
+            status: payload.status.clone(),
+            rank: None,
+            attachments: Vec::new(),
+            links: payload.links.clone().unwrap_or_default(),
+            parent_id: payload.parent_id.clone(),
+            project_id: payload.project_id.clone(),
+            blocked_by: payload.blocked_by.clone().unwrap_or_default(),
+            archived: false,
+            snoozed_until: None,
+            version: 1,
+        }
+    }
+
+    /// Marks the item as modified at `now`, moving it to its next version.
+    /// `completed_at` follows `completed`: stamped when it becomes true,
+    /// cleared when it no longer is.
+    fn touch(&mut self, now: i64) {
+        self.updated_at = Some(now);
+        self.version += 1;
+        if self.completed != Some(true) {
+            self.completed_at = None;
+        } else if self.completed_at.is_none() {
+            self.completed_at = Some(now);
+        }
+    }
 
-    HttpResponse::Ok().json(items)
+    /// Replaces every client-editable field with the payload's, clearing the
+    /// ones it omits. Identity, creation time and attachments are kept.
+    fn replace_with(&mut self, payload: &CreateItemPayload) {
+        *self = Item {
+            updated_at: self.updated_at,
+            completed_at: self.completed_at,
+            snoozed_until: self.snoozed_until,
+            archived: self.archived,
+            rank: self.rank.take(),
+            version: self.version,
+            attachments: std::mem::take(&mut self.attachments),
+            ..Item::from_payload(self.id.clone(), self.created_at, payload)
+        };
+    }
+
+    /// Merges the fields present in an update payload into this item.
+    fn apply_update(&mut self, payload: &UpdateItemPayload) {
+        if let Some(item_type) = &payload.item_type {
+            self.item_type = item_type.clone();
+        }
+        if let Some(title) = &payload.title {
+            self.title = title.clone();
+        }
+        if let Some(content) = &payload.content {
+            self.content = content.clone();
+        }
+        if let Some(tags) = &payload.tags {
+            self.tags = tags.clone();
+        }
+        if let Some(code_location) = &payload.code_location {
+            self.code_location = code_location.clone();
+        }
+        if let Some(completed) = payload.completed {
+            self.completed = completed;
+        }
+        if let Some(due_date) = payload.due_date {
+            self.due_date = due_date;
+        }
+        if let Some(start_time) = payload.start_time {
+            self.start_time = start_time;
+        }
+        if let Some(end_time) = payload.end_time {
+            self.end_time = end_time;
+        }
+        if let Some(recurrence) = &payload.recurrence {
+            self.recurrence = recurrence.clone();
+        }
+        if let Some(priority) = payload.priority {
+            self.priority = priority;
+        }
+        if let Some(status) = &payload.status {
+            self.status = status.clone();
+        }
+        if let Some(links) = &payload.links {
+            self.links = links.clone();
+        }
+        if let Some(parent_id) = &payload.parent_id {
+            self.parent_id = parent_id.clone();
+        }
+        if let Some(project_id) = &payload.project_id {
+            self.project_id = project_id.clone();
+        }
+        if let Some(blocked_by) = &payload.blocked_by {
+            self.blocked_by = blocked_by.clone();
+        }
+    }
 }
 
-async fn get_item(db: web::Data<SharedDb>, path: web::Path<String>) -> impl Responder {
-    match db.get(path.into_inner()) {
-        Ok(Some(value)) => match serde_json::from_slice::<Item>(&value) {
-            Ok(item) => HttpResponse::Ok().json(item),
-            Err(_) => HttpResponse::InternalServerError().body("Deserialization failed"),
-        },
-        Ok(None) => HttpResponse::NotFound().body("Item not found"),
-        Err(_) => HttpResponse::InternalServerError().body("DB error"),
+/// The ID a new item is stored under: the client's own, once checked, or a
+/// generated one.
+fn new_item_id(
+    payload: &CreateItemPayload,
+    ids: &SharedIdGenerator,
+    now: i64,
+) -> Result<String, ApiError> {
+    match &payload.id {
+        Some(id) => {
+            validation::validate_client_id(id).map_err(ApiError::Invalid)?;
+            Ok(id.clone())
+        }
+        None => Ok(ids.generate(&payload.title, now)),
     }
 }
 
+fn id_taken() -> ApiError {
+    ApiError::Conflict("An item with this id already exists".to_string())
+}
+
 async fn create_item(
-    db: web::Data<SharedDb>,
-    payload: web::Json<CreateItemPayload>,
-) -> impl Responder {
-    let id = Uuid::new_v4().to_string();
-    let created_at = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Time went backward")
-        .as_millis() as i64;
-    
-    let item = Item {
-        id: id.clone(),
-        item_type: payload.item_type.clone(),
-        title: payload.title.clone(),
-        content: payload.content.clone(),
-        tags: payload.tags.clone().unwrap_or_default(),
-        code_location: payload.code_location.clone(),
-        created_at,
-        completed: payload.completed,
-        due_date: payload.due_date,
-        start_time: payload.start_time,
-        end_time: payload.end_time,
+    req: HttpRequest,
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    config: web::Data<Config>,
+    query: web::Query<templates::CreateQuery>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, ApiError> {
+    let created_at = clock.now_millis();
+    let held = match idempotency::claim(&req, &db, &config, created_at)? {
+        Claimed::Replay(item) => return Ok(HttpResponse::Ok().json(item)),
+        Claimed::Fresh(held) => held,
     };
 
-    match serde_json::to_vec(&item) {
-        Ok(bytes) => match db.insert(&id, bytes) {
-            Ok(_) => HttpResponse::Created().json(item),
-            Err(_) => HttpResponse::InternalServerError().body("Failed to insert"),
-        },
-        Err(_) => HttpResponse::InternalServerError().body("Serialization failed"),
+    let payload = templates::prefill(&db, &query, body.into_inner(), created_at)?;
+    let id = new_item_id(&payload, &ids, created_at)?;
+    let mut item = Item::from_payload(id.clone(), created_at, &payload);
+    validation::validate_item(&mut item, &config).map_err(ApiError::Invalid)?;
+    validation::check_references(&db, &item)?;
+
+    // Stored only if the key is still free, so a client-supplied ID never
+    // overwrites an existing item, even when two creates race.
+    let tenant = Tenant::of(&req);
+    if !commit_items(&db, &tenant, &[(None, item.clone())], Vec::new())? {
+        return Err(id_taken());
+    }
+    if let Some(held) = held {
+        held.record(&item)?;
     }
+    Ok(created(&item))
 }
 
+/// Stores `item`, an edit of `loaded` made at `now` that passed
+/// [`etag::check`], with the next occurrence if it completes a repeating
+/// item, and responds with it, its ETag and, for a new occurrence, a
+/// `Location` naming that.
+fn save_edit(
+    req: &HttpRequest,
+    db: &SharedStore,
+    ids: &SharedIdGenerator,
+    loaded: Item,
+    mut item: Item,
+    now: i64,
+) -> Result<HttpResponse, ApiError> {
+    let next = complete::roll_forward(&loaded, &mut item, ids, now);
+    let mut changes = vec![(Some(loaded), item.clone())];
+    changes.extend(next.iter().map(|next| (None, next.clone())));
+    etag::save(req, db, &Tenant::of(req), &changes)?;
+
+    let mut res = HttpResponse::Ok();
+    res.insert_header(etag::header(&item));
+    if let Some(next) = &next {
+        res.insert_header(complete::location(next));
+    }
+    Ok(res.json(item))
+}
+
+/// `PUT /items/{id}`: full replace. The body must be a complete item, as for
+/// create; fields it leaves out are cleared rather than kept.
+async fn replace_item(
+    req: HttpRequest,
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    payload: web::Json<CreateItemPayload>,
+) -> Result<HttpResponse, ApiError> {
+    let loaded = load_item(&db, &path.into_inner())?;
+    etag::check(&req, &loaded)?;
+
+    let mut item = loaded.clone();
+    item.replace_with(&payload);
+    validation::validate_item(&mut item, &config).map_err(ApiError::Invalid)?;
+    validation::check_references(&db, &item)?;
+    let now = clock.now_millis();
+    item.touch(now);
+
+    save_edit(&req, &db, &ids, loaded, item, now)
+}
+
+/// `PATCH /items/{id}`: partial update. Only the fields present in the body
+/// change; everything else keeps its stored value.
 async fn update_item(
-    db: web::Data<SharedDb>,
+    req: HttpRequest,
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    config: web::Data<Config>,
     path: web::Path<String>,
     payload: web::Json<UpdateItemPayload>,
-) -> impl Responder {
-    let id = path.into_inner();
+) -> Result<HttpResponse, ApiError> {
+    let loaded = load_item(&db, &path.into_inner())?;
+    etag::check(&req, &loaded)?;
 
-    match db.get(&id) {
-        Ok(Some(value)) => {
-            let mut item: Item = serde_json::from_slice(&value).unwrap();
+    let mut item = loaded.clone();
+    item.apply_update(&payload);
+    validation::validate_item(&mut item, &config).map_err(ApiError::Invalid)?;
+    validation::check_references(&db, &item)?;
+    let now = clock.now_millis();
+    item.touch(now);
 
-            if let Some(item_type) = &payload.item_type {
-                item.item_type = item_type.clone();
-            }
-            if let Some(title) = &payload.title {
-                item.title = title.clone();
-            }
-            if let Some(content) = &payload.content {
-                item.content = Some(content.clone());
-            }
-            if let Some(tags) = &payload.tags {
-                item.tags = tags.clone();
-            }
-            if let Some(code_location) = &payload.code_location {
-                item.code_location = Some(code_location.clone());
-            }
-            if let Some(completed) = payload.completed {
-                item.completed = Some(completed);
-            }
-            if let Some(due_date) = payload.due_date {
-                item.due_date = Some(due_date);
-            }
-            if let Some(start_time) = payload.start_time {
-                item.start_time = Some(start_time);
-            }
-            if let Some(end_time) = payload.end_time {
-                item.end_time = Some(end_time);
-            }
+    save_edit(&req, &db, &ids, loaded, item, now)
+}
 
-            match serde_json::to_vec(&item) {
-                Ok(bytes) => {
-                    if db.insert(&id, bytes).is_ok() {
-                        HttpResponse::Ok().json(item)
-                    } else {
-                        HttpResponse::InternalServerError().body("Update failed")
-                    }
-                }
-                Err(_) => HttpResponse::InternalServerError().body("Serialization failed"),
-            }
-        }
-        Ok(None) => HttpResponse::NotFound().body("Item not found"),
-        Err(_) => HttpResponse::InternalServerError().body("DB error"),
-    }
+#[derive(Debug, Deserialize)]
+struct DeleteQuery {
+    /// Respond 200 with the removed item instead of 204, for client-side undo.
+    #[serde(default, rename = "return")]
+    return_item: bool,
+    /// What happens to the item's subtasks.
+    #[serde(default)]
+    children: subtasks::ChildPolicy,
 }
 
-async fn delete_item(db: web::Data<SharedDb>, path: web::Path<String>) -> impl Responder {
+/// `DELETE /items/{id}`: moves the item to the trash, from which it can be
+/// restored or purged. Links to it from other items are removed either way;
+/// its subtasks are handled as `?children=` says.
+async fn delete_item(
+    req: HttpRequest,
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+    query: web::Query<DeleteQuery>,
+) -> Result<HttpResponse, ApiError> {
     let id = path.into_inner();
-    match db.remove(&id) {
-        Ok(Some(_)) => HttpResponse::NoContent().finish(),
-        Ok(None) => HttpResponse::NotFound().body("Item not found"),
-        Err(_) => HttpResponse::InternalServerError().body("Delete failed"),
+    let now = clock.now_millis();
+    let value = db
+        .get(id.as_bytes())
+        .map_err(|_| ApiError::Internal("Delete failed"))?
+        .ok_or(ApiError::NotFound("Item not found"))?;
+    let item = codec::decode::<Item>(&value).ok();
+    if let Some(item) = &item {
+        etag::check(&req, item)?;
+        subtasks::before_delete(&db, &tenant, item, query.children, now)?;
     }
+    let removed = match &item {
+        Some(item) => trash::trash(&db, &tenant, item, now),
+        // A record that doesn't decode couldn't be restored, so it goes for good.
+        None => {
+            let entry = audit::Entry::new(&tenant, Action::Delete, &id, now);
+            audit::commit(
+                &db,
+                vec![BatchOp::Remove(id.clone().into_bytes())],
+                &[entry],
+            )
+            .map(|()| true)
+        }
+    };
+    if !removed.map_err(|_| ApiError::Internal("Delete failed"))? {
+        return Err(etag::overtaken(&req, &db, &id));
+    }
+
+    let store = SharedStore::clone(&db);
+    let cleaned = match web::block(move || links::strip_links(&store, &tenant, &id, now)).await {
+        Ok(Ok(cleaned)) => cleaned,
+        _ => return Err(ApiError::Internal("Failed to clean up links")),
+    };
+
+    let cleaned = (links::CLEANED_HEADER, cleaned);
+    Ok(match item {
+        Some(item) if query.return_item => HttpResponse::Ok().insert_header(cleaned).json(item),
+        _ => HttpResponse::NoContent().insert_header(cleaned).finish(),
+    })
 }
 
-async fn capture_item(db: web::Data<SharedDb>, payload: web::Json<CapturePayload>) -> impl Responder {
+async fn capture_item(
+    req: HttpRequest,
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    config: web::Data<Config>,
+    payload: web::Json<CapturePayload>,
+) -> Result<HttpResponse, ApiError> {
+    let created_at = clock.now_millis();
+
+    let held = match idempotency::claim(&req, &db, &config, created_at)? {
+        Claimed::Replay(item) => return Ok(HttpResponse::Ok().json(item)),
+        Claimed::Fresh(held) => held,
+    };
+    let dedup = if config.dedup_capture {
+        let tree = capture::hash_tree(&db)?;
+        let hash = capture::capture_hash(&payload.text);
+        let window_ms = config.dedup_window_secs as i64 * 1000;
+        Some(RecentKey::new(tree, hash, window_ms))
+    } else {
+        None
+    };
+    if let Some(item) = dedup
+        .as_ref()
+        .and_then(|key| replayed_item(&db, key, created_at))
+    {
+        if let Some(held) = held {
+            held.record(&item)?;
+        }
+        return Ok(HttpResponse::Ok().json(item));
+    }
+
     let text = payload.text.clone();
     let mut lines = text.lines();
     let first_line = lines.next().unwrap_or("").to_string();
     let content = Some(lines.collect::<Vec<&str>>().join("\n"));
 
-    let mut item_type = "note".to_string();
+    let mut item_type = config.capture_default_type.clone();
+    let mut priority = None;
     let mut tags: Vec<String> = vec![];
     let mut title_parts = Vec::new();
 
-    for word in first_line.split_whitespace() {
-        if word.starts_with('#') {
-            let tag = word[1..].to_string();
-            // Check for special tags to determine item type
-            if tag.eq_ignore_ascii_case("todo") {
-                item_type = "task".to_string();
-            } else if tag.eq_ignore_ascii_case("note") {
-                item_type = "note".to_string();
-            } else if tag.eq_ignore_ascii_case("event") {
-                item_type = "event".to_string();
+    for token in capture::tokenize(&first_line) {
+        match token {
+            capture::Token::Word(word) => {
+                if let Some(tag) = word.strip_prefix('#') {
+                    let tag = tag.to_string();
+                    // Some tags also select the item type
+                    if let Some(mapped) = config.capture_type_tags.get(&tag.to_lowercase()) {
+                        item_type = mapped.clone();
+                    }
+                    tags.push(tag);
+                } else if let Some(level) = word
+                    .strip_prefix('!')
+                    .and_then(priority::parse)
+                    .filter(|level| (priority::HIGHEST..=priority::LOWEST).contains(level))
+                {
+                    // `!p1` or `!1` sets the priority
+                    priority = Some(level);
+                } else {
+                    title_parts.push(word);
+                }
             }
-            tags.push(tag);
-        } else {
-            title_parts.push(word);
+            capture::Token::Quoted(text) => title_parts.push(text),
+            // No fields are interpreted yet; keep them in the title so
+            // nothing typed is lost.
+            capture::Token::Field { key, value } => title_parts.push(format!("{key}:{value}")),
         }
     }
-    
+
+    if config.capture_tag_overflow == TagOverflow::Truncate {
+        tags = validation::normalize_tags(&tags);
+        tags.truncate(config.limits.max_tags);
+    }
+
     let title = title_parts.join(" ");
 
-    let id = Uuid::new_v4().to_string();
-    let created_at = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Time went backward")
-        .as_millis() as i64;
-    
-    let item = Item {
+    let id = match config.capture_id {
+        CaptureId::Uuid => ids.generate(&title, created_at),
+        CaptureId::Hash => capture::capture_hash(&payload.text),
+    };
+
+    // A content-addressed capture seen before hands back the item it made,
+    // as edited since, rather than writing over those edits.
+    if config.capture_id == CaptureId::Hash {
+        if let Some(existing) = db
+            .get(id.as_bytes())?
+            .and_then(|raw| codec::decode::<Item>(&raw).ok())
+        {
+            record_keys(&dedup.iter().collect::<Vec<_>>(), &existing)?;
+            if let Some(held) = held {
+                held.record(&existing)?;
+            }
+            return Ok(HttpResponse::Ok().json(existing));
+        }
+    }
+
+    let mut item = Item {
         id: id.clone(),
         item_type,
         title: title.trim().to_string(),
@@ -302,83 +929,562 @@ async fn capture_item(db: web::Data<SharedDb>, payload: web::Json<CapturePayload
         tags,
         code_location: None,
         created_at,
+        updated_at: Some(created_at),
         completed: None,
+        completed_at: None,
         due_date: None,
         start_time: None,
         end_time: None,
+        recurrence: None,
+        priority,
+        status: None,
+        rank: None,
+        attachments: Vec::new(),
+        links: Vec::new(),
+        parent_id: None,
+        project_id: None,
+        blocked_by: Vec::new(),
+        archived: false,
+        snoozed_until: None,
+        version: 1,
     };
+    validation::validate_capture(&mut item, &config).map_err(ApiError::Invalid)?;
 
-    match serde_json::to_vec(&item) {
-        Ok(bytes) => match db.insert(&id, bytes) {
-            Ok(_) => HttpResponse::Created().json(item),
-            Err(_) => HttpResponse::InternalServerError().body("Failed to insert item"),
-        },
-        Err(_) => HttpResponse::InternalServerError().body("Serialization failed"),
+    if !commit_items(&db, &tenant, &[(None, item.clone())], Vec::new())? {
+        return Err(id_taken());
+    }
+    record_keys(&dedup.iter().collect::<Vec<_>>(), &item)?;
+    if let Some(held) = held {
+        held.record(&item)?;
     }
+    Ok(created(&item))
 }
 
+/// Query parameters understood by [`get_filtered_items`].
+const LIST_PARAMS: &[&str] = &[
+    "type",
+    "tags",
+    "tags_mode",
+    "tags_any",
+    "hierarchical",
+    "missing",
+    "updated_after",
+    "updated_before",
+    "created_after",
+    "created_before",
+    "due_after",
+    "due_before",
+    "start_between",
+    "priority",
+    "completed",
+    "overdue",
+    "blocked",
+    "project",
+    "q",
+    "include_archived",
+    "include_snoozed",
+    "offset",
+    "limit",
+    "sort",
+    "order",
+    "shape",
+    "envelope",
+    "time",
+    "fields",
+];
+
 async fn get_filtered_items(
-    db: web::Data<SharedDb>,
+    db: TenantStore,
+    config: web::Data<Config>,
+    clock: web::Data<SharedClock>,
     info: web::Query<std::collections::HashMap<String, String>>,
-) -> impl Responder {
-    let filter_type = info.get("type").map(|s| s.to_lowercase());
-    let filter_tags: Option<Vec<String>> = info.get("tags").map(|s| {
-        s.split(',')
-            .map(|tag| tag.trim().to_string())
-            .collect()
-    });
+) -> Result<HttpResponse, ApiError> {
+    let strict = filter::is_strict(&info, config.strict_query).map_err(ApiError::BadRequest)?;
+    if strict {
+        filter::reject_unknown_params(&info, LIST_PARAMS).map_err(ApiError::BadRequest)?;
+    }
 
-    let items: Vec<Item> = db
-        .iter()
-        .filter_map(|item| {
-            if let Ok((_, val)) = item {
-                let item_data: Item = serde_json::from_slice(&val).ok()?;
-
-                let type_match = filter_type.as_ref().map_or(true, |t| {
-                    t == &item_data.item_type
-                });
-                
-                let tags_match = filter_tags.as_ref().map_or(true, |tags| {
-                    tags.iter().all(|tag| item_data.tags.contains(tag))
-                });
-                
-                if type_match && tags_match {
-                    return Some(item_data);
-                }
-            }
-            None
-        })
-        .collect();
+    let (filter, page, sort, shape, time, fields) =
+        ItemFilter::from_query(&info, clock.now_millis())
+            .and_then(|filter| {
+                let page = Page::from_query(&info)?;
+                let sort = Sort::from_query(&info)?;
+                let shape = Shape::from_query(&info)?;
+                let time = TimeFormat::from_query(&info)?;
+                let fields = Fields::from_query(&info, strict)?;
+                Ok((filter, page, sort, shape, time, fields))
+            })
+            .map_err(ApiError::BadRequest)?;
+    // Key order streams straight from the store; any other order has to see
+    // every match before the first can be sent.
+    let items: Box<dyn Iterator<Item = Item> + Send> = match sort {
+        Some(sort) => {
+            let mut items = filter::scan_blocking(&db, filter).await?;
+            sort.apply(&mut items);
+            Box::new(items.into_iter())
+        }
+        None => Box::new(filter::iter(&db, filter)),
+    };
+    let items = items.map(move |item| fields.view(time.view(item)));
+
+    if info.get("envelope").is_some_and(|v| v == "true") {
+        return Ok(stream::json_envelope(items, page, shape));
+    }
+    Ok(match shape {
+        Shape::Array => stream::json_array(page.apply(items)),
+        Shape::Map => stream::json_map(page.apply(items)),
+    })
+}
 
-    HttpResponse::Ok().json(items)
+async fn get_type_schema(path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let rules =
+        rules::rules_for(&path.into_inner()).ok_or(ApiError::NotFound("Unknown item type"))?;
+    Ok(HttpResponse::Ok().json(rules::json_schema(rules)))
+}
+
+/// Shared handles mounted into every worker's `App`.
+#[derive(Clone)]
+struct AppState {
+    db: web::Data<SharedStore>,
+    schema: web::Data<graphql::NeonoteSchema>,
+    startup: web::Data<StartupInfo>,
+    config: web::Data<Config>,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    appends: web::Data<AppendBuffer>,
+}
+
+impl AppState {
+    fn new(db: SharedStore, startup: StartupInfo, config: Config, clock: SharedClock) -> Self {
+        AppState {
+            schema: web::Data::new(graphql::build_schema(db.clone())),
+            db: web::Data::new(db),
+            startup: web::Data::new(startup),
+            ids: web::Data::new(ids::generator(config.id_strategy)),
+            appends: web::Data::new(AppendBuffer::new(&config, clock.clone())),
+            config: web::Data::new(config),
+            clock: web::Data::new(clock),
+        }
+    }
+}
+
+/// Fallback for a resource hit with a method it doesn't route, advertising
+/// the ones it does.
+fn method_not_allowed(allow: &'static str) -> Route {
+    web::route().to(move || async move {
+        HttpResponse::MethodNotAllowed()
+            .insert_header((header::ALLOW, allow))
+            .finish()
+    })
+}
+
+/// Assembles the application: shared state, API-key check and every route.
+fn build_app(
+    state: &AppState,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(state.db.clone())
+        .app_data(state.schema.clone())
+        .app_data(state.startup.clone())
+        .app_data(state.config.clone())
+        .app_data(state.clock.clone())
+        .app_data(state.ids.clone())
+        .app_data(state.appends.clone())
+        .app_data(web::JsonConfig::default().error_handler(error::json_error))
+        .app_data(web::QueryConfig::default().error_handler(error::query_error))
+        .app_data(web::PathConfig::default().error_handler(error::path_error))
+        .wrap(middleware::Condition::new(
+            state.config.read_only,
+            middleware::from_fn(read_only::reject_writes),
+        ))
+        .wrap(ApiKeyMiddleware {
+            api_key: state.config.api_key.clone(),
+            tenant_keys: Rc::new(state.config.tenant_keys.clone()),
+        })
+        .service(
+            web::scope("/items")
+                .service(
+                    web::resource("/capture")
+                        .route(web::post().to(capture_item))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("")
+                        .route(web::get().to(get_filtered_items))
+                        .route(web::post().to(create_item))
+                        .default_service(method_not_allowed("GET, POST")),
+                )
+                .service(
+                    web::resource("/batch")
+                        .route(web::post().to(batch::create_batch))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/bulk-update")
+                        .route(web::post().to(bulk::bulk_update))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/get-many")
+                        .route(web::post().to(batch::get_many))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/by-code")
+                        .route(web::get().to(code::by_code))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/frequent")
+                        .route(web::get().to(access::frequent))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/inbox")
+                        .route(web::get().to(inbox::list_inbox))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/autocomplete")
+                        .route(web::get().to(search::autocomplete))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/search")
+                        .route(web::get().to(search::search))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/due-soon")
+                        .route(web::get().to(feeds::due_soon))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/overdue")
+                        .route(web::get().to(feeds::overdue))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/recent")
+                        .route(web::get().to(feeds::recent))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/trash")
+                        .route(web::get().to(trash::list))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/page")
+                        .route(web::get().to(export::cursor_page))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/export/ndjson")
+                        .route(web::get().to(export::ndjson))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/validate")
+                        .route(web::post().to(validation::validate_payload))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}")
+                        .route(web::get().to(get_item))
+                        .route(web::put().to(replace_item))
+                        .route(web::patch().to(update_item))
+                        .route(web::delete().to(delete_item))
+                        .default_service(method_not_allowed("GET, PUT, PATCH, DELETE")),
+                )
+                .service(
+                    web::resource("/{id}/append")
+                        .route(web::post().to(append::append_content))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/restore")
+                        .route(web::post().to(trash::restore))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/purge")
+                        .route(web::delete().to(trash::purge))
+                        .default_service(method_not_allowed("DELETE")),
+                )
+                .service(
+                    web::resource("/{id}/archive")
+                        .route(web::post().to(archive::archive))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/unarchive")
+                        .route(web::post().to(archive::unarchive))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/snooze")
+                        .route(web::post().to(snooze::snooze))
+                        .route(web::delete().to(snooze::wake))
+                        .default_service(method_not_allowed("POST, DELETE")),
+                )
+                .service(
+                    web::resource("/{id}/children")
+                        .route(web::get().to(subtasks::list))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/{id}/move")
+                        .route(web::post().to(rank::move_item))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/complete")
+                        .route(web::post().to(complete::complete))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/uncomplete")
+                        .route(web::post().to(complete::uncomplete))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/occurrences")
+                        .route(web::get().to(recurrence::occurrences))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/{id}/revisions")
+                        .route(web::get().to(revisions::list))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/{id}/revisions/{n}")
+                        .route(web::get().to(revisions::get))
+                        .default_service(method_not_allowed("GET")),
+                )
+                .service(
+                    web::resource("/{id}/revert/{n}")
+                        .route(web::post().to(revisions::revert))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/file")
+                        .route(web::post().to(inbox::file_item))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/convert")
+                        .route(web::post().to(convert::convert_item))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/attachments")
+                        .route(web::post().to(attachments::upload_attachments))
+                        .default_service(method_not_allowed("POST")),
+                )
+                .service(
+                    web::resource("/{id}/attachments/{aid}")
+                        .route(web::get().to(attachments::download_attachment))
+                        .route(web::delete().to(attachments::delete_attachment))
+                        .default_service(method_not_allowed("GET, DELETE")),
+                )
+                .service(
+                    web::resource("/{id}/reminders")
+                        .route(web::get().to(reminders::list))
+                        .route(web::post().to(reminders::create))
+                        .default_service(method_not_allowed("GET, POST")),
+                )
+                .service(
+                    web::resource("/{id}/reminders/{rid}")
+                        .route(web::get().to(reminders::get))
+                        .route(web::put().to(reminders::replace))
+                        .route(web::delete().to(reminders::delete))
+                        .default_service(method_not_allowed("GET, PUT, DELETE")),
+                ),
+        )
+        .service(
+            web::scope("/tags")
+                .route("", web::get().to(tags::list_tags))
+                .route("/tree", web::get().to(tags::tag_tree))
+                .route("/rename", web::post().to(tags::rename_tag))
+                .route("/merge", web::post().to(tags::merge_tags))
+                .route("/{name}", web::delete().to(tags::delete_tag))
+                .route("/{name}/meta", web::get().to(tags::get_tag_meta))
+                .route("/{name}/meta", web::put().to(tags::put_tag_meta)),
+        )
+        .service(
+            web::resource("/templates/{name}")
+                .route(web::get().to(templates::get_template))
+                .route(web::put().to(templates::put_template))
+                .default_service(method_not_allowed("GET, PUT")),
+        )
+        .service(
+            web::scope("/views")
+                .route("", web::get().to(views::list))
+                .route("", web::post().to(views::save))
+                .route("/{name}", web::get().to(views::get))
+                .route("/{name}", web::delete().to(views::delete))
+                .route("/{name}/items", web::get().to(views::items)),
+        )
+        .service(
+            web::scope("/projects")
+                .route("", web::get().to(projects::list))
+                .route("", web::post().to(projects::create))
+                .route("/{id}", web::get().to(projects::get))
+                .route("/{id}", web::put().to(projects::replace))
+                .route("/{id}", web::delete().to(projects::delete))
+                .route("/{id}/items", web::get().to(projects::items))
+                .route("/{id}/stats", web::get().to(projects::stats)),
+        )
+        .route("/types", web::get().to(types::list_types))
+        .service(
+            web::scope("/daily")
+                .route("/{date}", web::get().to(daily::get))
+                .route("/{date}/append", web::post().to(daily::append)),
+        )
+        .route("/digest", web::get().to(digest::digest))
+        .route("/board", web::get().to(board::board))
+        .route("/agenda", web::get().to(agenda::agenda))
+        .route("/calendar/{year}/{month}", web::get().to(calendar::month))
+        .route("/version", web::get().to(admin::version))
+        .route("/admin/info", web::get().to(admin::info))
+        .route("/admin/orphan-links", web::get().to(links::orphan_check))
+        .route("/admin/audit", web::get().to(audit::list))
+        .route("/graphql", web::post().to(graphql::graphql_handler))
+        .route("/schema/{type}", web::get().to(get_type_schema))
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let api_key = env::var("API_KEY").unwrap_or_else(|_| "secret".into());
-    let db = sled::open("/usr/src/app/data/notes_db").expect("Failed to open sled database");
-    let shared_db = web::Data::new(Arc::new(db));
+    let config = Config::from_env();
+    let (db, startup): (SharedStore, _) = match config.store {
+        StoreBackend::Sled => {
+            let path = Path::new(DB_PATH);
+            let startup = StartupInfo {
+                fresh: !path.exists(),
+            };
+            let db = sled::open(path).expect("Failed to open sled database");
+            (
+                Arc::new(SledStore::new(db, config.storage_encoding)),
+                startup,
+            )
+        }
+        StoreBackend::Memory => (
+            Arc::new(MemoryStore::new(config.storage_encoding)),
+            StartupInfo { fresh: true },
+        ),
+    };
+
+    if config.storage_encoding == Encoding::MessagePack {
+        let labels: Vec<&str> = config.tenant_keys.iter().map(|(l, _)| l.as_str()).collect();
+        let rewritten = codec::migrate(&db, &labels, config.storage_encoding)
+            .expect("Storage encoding migration failed");
+        println!("Storage encoding: rewrote {rewritten} records as MessagePack");
+    }
+
+    let labels: Vec<&str> = config.tenant_keys.iter().map(|(l, _)| l.as_str()).collect();
+    let indexed = index::build(&db, &labels).expect("Building the indexes failed");
+    if indexed > 0 {
+        println!("Indexes: indexed {indexed} existing items");
+    }
+    let normalized = types::normalize(&db, &labels, &config.custom_types)
+        .expect("Normalizing item types failed");
+    if normalized.rewritten > 0 {
+        println!("Types: normalized {} items", normalized.rewritten);
+    }
+    if !normalized.unknown.is_empty() {
+        let unknown: Vec<_> = normalized.unknown.into_iter().collect();
+        println!(
+            "Types: items have unknown types {}; add them to CUSTOM_TYPES or convert them",
+            unknown.join(", ")
+        );
+    }
+
+    if config.scan_on_start {
+        let report =
+            integrity::scan_and_repair(&db, &labels).expect("Startup integrity scan failed");
+        println!(
+            "Integrity scan: {} good, {} quarantined to '{}'",
+            report.good,
+            report.bad,
+            integrity::CORRUPT_TREE
+        );
+    }
+
+    let clock: SharedClock = Arc::new(SystemClock::new());
+    let pruned = prune_recent_keys(&db, &config, clock.now_millis())
+        .expect("Pruning expired idempotency keys failed");
+    if pruned > 0 {
+        println!("Idempotency: dropped {pruned} expired keys");
+    }
+    let (sweep_db, sweep_config, sweep_clock) = (db.clone(), config.clone(), clock.clone());
+    actix_web::rt::spawn(async move {
+        let mut ticks = actix_web::rt::time::interval(PRUNE_INTERVAL);
+        loop {
+            ticks.tick().await;
+            let (db, config, now) = (
+                sweep_db.clone(),
+                sweep_config.clone(),
+                sweep_clock.now_millis(),
+            );
+            // A failed sweep leaves expired keys for the next one; lookups ignore them anyway.
+            let _ = web::block(move || prune_recent_keys(&db, &config, now)).await;
+        }
+    });
+
+    // A read-only instance leaves reminders to the one that takes writes.
+    if !config.read_only {
+        let notifier = notify::from_config(&config.reminder_notifier);
+        let labels: Vec<String> = config.tenant_keys.iter().map(|(l, _)| l.clone()).collect();
+        let interval = Duration::from_secs(config.reminder_interval_secs);
+        let (remind_db, remind_clock) = (db.clone(), clock.clone());
+        actix_web::rt::spawn(async move {
+            let mut ticks = actix_web::rt::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let (db, labels, notifier, now) = (
+                    remind_db.clone(),
+                    labels.clone(),
+                    notifier.clone(),
+                    remind_clock.now_millis(),
+                );
+                // Unsent reminders stay armed, so a failed pass is retried on the next tick.
+                let _ =
+                    web::block(move || reminders::fire_all(&db, &labels, &*notifier, now)).await;
+            }
+        });
+    }
 
     println!("Server running at http://localhost:8080");
 
-    HttpServer::new(move || {
-        App::new()
-            .app_data(shared_db.clone())
-            .wrap(ApiKeyMiddleware {
-                api_key: api_key.clone(),
-            })
-            .service(
-                web::scope("/items")
-                    .route("/capture", web::post().to(capture_item))
-                    .route("", web::get().to(get_filtered_items))
-                    .route("", web::post().to(create_item))
-                    .route("/{id}", web::get().to(get_item))
-                    .route("/{id}", web::put().to(update_item))
-                    .route("/{id}", web::delete().to(delete_item)),
-            )
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
-}
+    let workers = config.workers;
+    let keep_alive = config.keep_alive_secs.map(Duration::from_secs);
+    let client_timeout = config.client_timeout_ms.map(Duration::from_millis);
 
+    let state = AppState::new(db, startup, config, clock);
+    let appends = state.appends.clone();
+    let mut server = HttpServer::new(move || build_app(&state));
+
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+    if let Some(keep_alive) = keep_alive {
+        server = server.keep_alive(keep_alive);
+    }
+    if let Some(client_timeout) = client_timeout {
+        server = server.client_request_timeout(client_timeout);
+    }
+
+    server.bind(("0.0.0.0", 8080))?.run().await?;
+    appends.flush_all();
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! Where fired reminders go. The scheduler only knows the [`Notifier`]
+//! trait; `REMINDER_NOTIFIER` picks the implementation at startup, and a new
+//! channel, such as a push service, is one more implementation.
+
+use serde::Serialize;
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::config::NotifierKind;
+
+/// A reminder that came due.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notice {
+    /// The API key label the item belongs to; `None` for the default key.
+    pub tenant: Option<String>,
+    pub item_id: String,
+    pub title: String,
+    pub reminder_id: String,
+    /// When the reminder was set to fire, which may be a little before now.
+    pub fire_at: i64,
+    pub due_date: Option<i64>,
+    pub start_time: Option<i64>,
+}
+
+pub trait Notifier: Send + Sync {
+    /// Delivers `notice`. A failed delivery is retried on the next tick.
+    fn notify(&self, notice: &Notice) -> Result<(), String>;
+}
+
+pub type SharedNotifier = Arc<dyn Notifier>;
+
+/// Writes each notice to stdout.
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify(&self, notice: &Notice) -> Result<(), String> {
+        println!(
+            "Reminder {}: '{}' ({})",
+            notice.reminder_id, notice.title, notice.item_id
+        );
+        Ok(())
+    }
+}
+
+/// POSTs each notice as JSON to a plain `http://` URL and expects a 2xx.
+pub struct WebhookNotifier {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl WebhookNotifier {
+    /// Parses `http://host[:port][/path]`. TLS isn't supported.
+    pub fn new(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("webhook URL '{url}' must start with http://"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("webhook URL '{url}' has an invalid port"))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("webhook URL '{url}' has no host"));
+        }
+        Ok(WebhookNotifier {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, notice: &Notice) -> Result<(), String> {
+        let body = serde_json::to_string(notice).map_err(|e| e.to_string())?;
+        let mut stream =
+            TcpStream::connect((self.host.as_str(), self.port)).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(WEBHOOK_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(WEBHOOK_TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.path,
+            self.host,
+            body.len()
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| e.to_string())?;
+
+        let mut status_line = [0; 12];
+        stream
+            .read_exact(&mut status_line)
+            .map_err(|e| e.to_string())?;
+        // "HTTP/1.1 204"
+        match &status_line[9..10] {
+            b"2" => Ok(()),
+            _ => Err(format!(
+                "webhook answered {}",
+                String::from_utf8_lossy(&status_line[9..])
+            )),
+        }
+    }
+}
+
+/// The notifier `kind` configures.
+pub fn from_config(kind: &NotifierKind) -> SharedNotifier {
+    match kind {
+        NotifierKind::Log => Arc::new(LogNotifier),
+        NotifierKind::Webhook(url) => Arc::new(
+            WebhookNotifier::new(url).unwrap_or_else(|e| panic!("REMINDER_WEBHOOK_URL: {e}")),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_webhook_urls() {
+        let hook = WebhookNotifier::new("http://hooks.local:8081/remind").unwrap();
+        assert_eq!(
+            (hook.host.as_str(), hook.port, hook.path.as_str()),
+            ("hooks.local", 8081, "/remind")
+        );
+        let hook = WebhookNotifier::new("http://hooks.local").unwrap();
+        assert_eq!((hook.port, hook.path.as_str()), (80, "/"));
+        assert!(WebhookNotifier::new("https://hooks.local").is_err());
+    }
+}
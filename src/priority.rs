@@ -0,0 +1,64 @@
+//! Item priority, from 1 (most urgent) to [`LOWEST`]. Clients may send it
+//! as a number or as `P1` to `P4`; it is stored and returned as the number.
+
+use serde::{Deserialize, Deserializer};
+
+pub const HIGHEST: u8 = 1;
+pub const LOWEST: u8 = 4;
+
+/// Parses `2`, `P2` or `p2`. The range isn't checked here; validation does
+/// that, so an out-of-range number is reported against the field.
+pub fn parse(raw: &str) -> Option<u8> {
+    let raw = raw.trim();
+    let digits = raw
+        .strip_prefix('P')
+        .or_else(|| raw.strip_prefix('p'))
+        .unwrap_or(raw);
+    digits.parse().ok()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrLabel {
+    Number(u8),
+    Label(String),
+}
+
+/// Deserializes an optional priority given as a number or a `P1`-style
+/// label. Use together with `#[serde(default)]` so absent fields stay `None`.
+pub fn deserialize_opt<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<NumberOrLabel>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(NumberOrLabel::Number(priority)) => Ok(Some(priority)),
+        Some(NumberOrLabel::Label(raw)) => parse(&raw).map(Some).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid priority '{raw}', expected 1 to {LOWEST} or P1 to P{LOWEST}"
+            ))
+        }),
+    }
+}
+
+/// Like `deserialize_opt`, but a present `null` is `Some(None)`, so an
+/// update can tell clearing the priority from leaving it alone.
+pub fn deserialize_nullable<'de, D>(deserializer: D) -> Result<Option<Option<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_opt(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_and_labels() {
+        assert_eq!(parse("2"), Some(2));
+        assert_eq!(parse("P1"), Some(1));
+        assert_eq!(parse(" p4 "), Some(4));
+        assert_eq!(parse("high"), None);
+    }
+}
@@ -0,0 +1,50 @@
+use serde::Serialize;
+
+use crate::{
+    filter::{self, ItemFilter},
+    stream::Keyed,
+    Item, SharedStore,
+};
+
+/// How many of an item's direct children are completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Progress over the direct children of `parent_id`, or `None` when it has
+/// none. Derived on every read; nothing is stored.
+fn of_children(db: &SharedStore, parent_id: &str) -> Option<Progress> {
+    let mut progress = Progress { done: 0, total: 0 };
+    for child in filter::iter(db, ItemFilter::default())
+        .filter(|item| item.parent_id.as_deref() == Some(parent_id))
+    {
+        progress.total += 1;
+        if child.completed == Some(true) {
+            progress.done += 1;
+        }
+    }
+    (progress.total > 0).then_some(progress)
+}
+
+/// An item with its derived progress alongside the stored fields.
+#[derive(Serialize)]
+pub struct WithProgress<T> {
+    #[serde(flatten)]
+    item: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<Progress>,
+}
+
+impl<T: Keyed> Keyed for WithProgress<T> {
+    fn key(&self) -> &str {
+        self.item.key()
+    }
+}
+
+/// Pairs `item` with the progress of its children in `db`.
+pub fn attach(db: &SharedStore, item: Item) -> WithProgress<Item> {
+    let progress = of_children(db, &item.id);
+    WithProgress { item, progress }
+}
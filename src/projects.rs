@@ -0,0 +1,231 @@
+//! Projects: named groups of items. An item belongs to at most one, named
+//! by its `project_id`, which is checked against this tree on every write.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    clock::SharedClock,
+    codec,
+    error::ApiError,
+    filter::{self, ItemFilter, Page, Sort},
+    ids::SharedIdGenerator,
+    save_items,
+    store::StoreResult,
+    tenant::{Tenant, TenantStore},
+    time::TimeFormat,
+    validation::FieldError,
+    Item, SharedStore,
+};
+
+/// Projects, keyed by ID.
+pub const PROJECT_TREE: &str = "projects";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectPayload {
+    name: String,
+    description: Option<String>,
+}
+
+impl ProjectPayload {
+    fn check(&self) -> Result<(), ApiError> {
+        if self.name.trim().is_empty() {
+            return Err(ApiError::Invalid(vec![FieldError::new(
+                "name",
+                "must not be empty",
+            )]));
+        }
+        Ok(())
+    }
+}
+
+/// How a project's items stand.
+#[derive(Debug, Default, Serialize)]
+struct Stats {
+    total: usize,
+    open: usize,
+    completed: usize,
+    /// Open items due before now.
+    overdue: usize,
+    by_type: BTreeMap<String, usize>,
+}
+
+fn find(db: &SharedStore, id: &str) -> StoreResult<Option<Project>> {
+    Ok(db
+        .tree(PROJECT_TREE)?
+        .get(id.as_bytes())?
+        .and_then(|raw| codec::decode(&raw).ok()))
+}
+
+fn load(db: &SharedStore, id: &str) -> Result<Project, ApiError> {
+    find(db, id)?.ok_or(ApiError::NotFound("Project not found"))
+}
+
+fn store(db: &SharedStore, project: &Project) -> Result<(), ApiError> {
+    let bytes = codec::encode(db.encoding(), project)
+        .map_err(|_| ApiError::Internal("Serialization failed"))?;
+    db.tree(PROJECT_TREE)
+        .and_then(|tree| tree.insert(project.id.as_bytes(), bytes))
+        .map_err(|_| ApiError::Internal("Failed to store project"))?;
+    Ok(())
+}
+
+/// Checks that `item`'s project exists.
+pub fn check_project(db: &SharedStore, item: &Item) -> StoreResult<Vec<FieldError>> {
+    match &item.project_id {
+        Some(id) if find(db, id)?.is_none() => Ok(vec![FieldError::new(
+            "project_id",
+            format!("'{id}' is not a project"),
+        )]),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// `GET /projects`: every project, by ID.
+pub async fn list(db: TenantStore) -> Result<HttpResponse, ApiError> {
+    let projects: Vec<Project> = db
+        .tree(PROJECT_TREE)?
+        .iter()
+        .filter_map(|entry| codec::decode(&entry.ok()?.1).ok())
+        .collect();
+    Ok(HttpResponse::Ok().json(projects))
+}
+
+pub async fn create(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    ids: web::Data<SharedIdGenerator>,
+    payload: web::Json<ProjectPayload>,
+) -> Result<HttpResponse, ApiError> {
+    payload.check()?;
+    let now = clock.now_millis();
+    let project = Project {
+        id: ids.generate(&payload.name, now),
+        name: payload.name.trim().to_string(),
+        description: payload.description.clone(),
+        created_at: now,
+        updated_at: now,
+    };
+    store(&db, &project)?;
+    Ok(HttpResponse::Created().json(project))
+}
+
+pub async fn get(db: TenantStore, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(load(&db, &path.into_inner())?))
+}
+
+/// `PUT /projects/{id}`: replaces the name and description.
+pub async fn replace(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+    payload: web::Json<ProjectPayload>,
+) -> Result<HttpResponse, ApiError> {
+    payload.check()?;
+    let project = Project {
+        name: payload.name.trim().to_string(),
+        description: payload.description.clone(),
+        updated_at: clock.now_millis(),
+        ..load(&db, &path.into_inner())?
+    };
+    store(&db, &project)?;
+    Ok(HttpResponse::Ok().json(project))
+}
+
+/// `DELETE /projects/{id}`: removes the project. Its items stay, with their
+/// `project_id` cleared.
+pub async fn delete(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let project = load(&db, &path.into_inner())?;
+    let now = clock.now_millis();
+    let filter = ItemFilter {
+        project_id: Some(project.id.clone()),
+        ..ItemFilter::default()
+    };
+    let changes: Vec<(Option<Item>, Item)> = filter::scan_blocking(&db, filter)
+        .await?
+        .into_iter()
+        .map(|old| {
+            let mut item = old.clone();
+            item.project_id = None;
+            item.touch(now);
+            (Some(old), item)
+        })
+        .collect();
+    save_items(&db, &tenant, &changes, Vec::new())?;
+    db.tree(PROJECT_TREE)?.remove(project.id.as_bytes())?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// `GET /projects/{id}/items`: the project's items. Takes the listing's
+/// parameters, which narrow, sort and page them.
+pub async fn items(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let project = load(&db, &path.into_inner())?;
+    let (mut filter, page, sort, time) = ItemFilter::from_query(&query, clock.now_millis())
+        .and_then(|filter| {
+            let page = Page::from_query(&query)?;
+            let sort = Sort::from_query(&query)?;
+            let time = TimeFormat::from_query(&query)?;
+            Ok((filter, page, sort, time))
+        })
+        .map_err(ApiError::BadRequest)?;
+    filter.project_id = Some(project.id);
+
+    let mut items = filter::scan_blocking(&db, filter).await?;
+    if let Some(sort) = sort {
+        sort.apply(&mut items);
+    }
+    let items: Vec<_> = page
+        .apply(items.into_iter())
+        .map(|item| time.view(item))
+        .collect();
+    Ok(HttpResponse::Ok().json(items))
+}
+
+/// `GET /projects/{id}/stats`: counts over the project's items, archived
+/// ones included.
+pub async fn stats(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let project = load(&db, &path.into_inner())?;
+    let now = clock.now_millis();
+    let filter = ItemFilter {
+        project_id: Some(project.id),
+        ..ItemFilter::default()
+    };
+    let mut stats = Stats::default();
+    for item in filter::scan_blocking(&db, filter).await? {
+        stats.total += 1;
+        if item.completed == Some(true) {
+            stats.completed += 1;
+        } else {
+            stats.open += 1;
+            if item.due_date.is_some_and(|due| due < now) {
+                stats.overdue += 1;
+            }
+        }
+        *stats.by_type.entry(item.item_type.to_string()).or_default() += 1;
+    }
+    Ok(HttpResponse::Ok().json(stats))
+}
@@ -0,0 +1,420 @@
+//! The `?q=` filter language, e.g.
+//! `type:task AND tag:work AND due<2025-07-01 AND NOT completed`.
+//!
+//! Terms are `field:value` matches, `field<value` style comparisons on the
+//! time fields (`due`, `created`, `updated`, `start`; `<`, `<=`, `>`, `>=`,
+//! values in epoch millis, ISO-8601, or relative as `now`, `now+2d` or
+//! `now-12h`), the flags `completed`, `archived` and
+//! `overdue`, or bare words searched for in the text like `/items/search`
+//! does. Terms combine with `AND`, `OR`, `NOT` (or a leading `-`) and
+//! parentheses; adjacent terms are ANDed, and AND binds tighter than OR.
+//! Values containing spaces go in double quotes.
+
+use crate::{filter, search, time, Item};
+
+/// A parsed expression, evaluated with [`Expr::matches`].
+#[derive(Debug, Clone)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone)]
+pub enum Term {
+    Type(String),
+    /// Matches the tag and every tag nested under it.
+    Tag(String),
+    Id(String),
+    /// Case-insensitive substring of the title.
+    Title(String),
+    /// Case-insensitive substring of the content.
+    Content(String),
+    /// Word prefixes anywhere in the title, content or tags.
+    Text(Vec<String>),
+    Time(TimeField, Cmp, Instant),
+    Completed,
+    Archived,
+    /// Due before now and not completed.
+    Overdue,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TimeField {
+    Due,
+    Created,
+    Updated,
+    Start,
+}
+
+/// A time in an expression, fixed or relative to when it is evaluated, so a
+/// stored expression like `due<now+1d` keeps meaning "in the next day".
+#[derive(Debug, Clone, Copy)]
+pub enum Instant {
+    At(i64),
+    FromNow(i64),
+}
+
+impl Instant {
+    fn parse(raw: &str) -> Option<Instant> {
+        let Some(offset) = raw.strip_prefix("now") else {
+            return time::parse_millis(raw).map(Instant::At);
+        };
+        let offset = match offset.split_at_checked(1) {
+            None => 0,
+            Some(("+", duration)) => time::parse_duration_millis(duration)?,
+            Some(("-", duration)) => -time::parse_duration_millis(duration)?,
+            Some(_) => return None,
+        };
+        Some(Instant::FromNow(offset))
+    }
+
+    fn at(self, now: i64) -> i64 {
+        match self {
+            Instant::At(at) => at,
+            Instant::FromNow(offset) => now.saturating_add(offset),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Expr {
+    /// Whether `item` satisfies the expression, with `overdue` and relative
+    /// times judged at `now`.
+    pub fn matches(&self, item: &Item, now: i64) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(item, now) && b.matches(item, now),
+            Expr::Or(a, b) => a.matches(item, now) || b.matches(item, now),
+            Expr::Not(expr) => !expr.matches(item, now),
+            Expr::Term(term) => term.matches(item, now),
+        }
+    }
+}
+
+impl Term {
+    fn matches(&self, item: &Item, now: i64) -> bool {
+        let contains = |text: &str, wanted: &str| text.to_lowercase().contains(wanted);
+        match self {
+            Term::Type(wanted) => item.item_type.as_str().eq_ignore_ascii_case(wanted),
+            Term::Tag(wanted) => item
+                .tags
+                .iter()
+                .any(|tag| tag == wanted || filter::is_nested_under(tag, wanted)),
+            Term::Id(wanted) => item.id == *wanted,
+            Term::Title(wanted) => contains(&item.title, wanted),
+            Term::Content(wanted) => item
+                .content
+                .as_deref()
+                .is_some_and(|content| contains(content, wanted)),
+            Term::Text(terms) => search::text_matches(item, terms),
+            Term::Time(field, cmp, at) => {
+                let at = at.at(now);
+                let value = match field {
+                    TimeField::Due => item.due_date,
+                    TimeField::Created => Some(item.created_at),
+                    TimeField::Updated => Some(item.updated_at.unwrap_or(item.created_at)),
+                    TimeField::Start => item.start_time,
+                };
+                value.is_some_and(|value| match cmp {
+                    Cmp::Lt => value < at,
+                    Cmp::Le => value <= at,
+                    Cmp::Gt => value > at,
+                    Cmp::Ge => value >= at,
+                })
+            }
+            Term::Completed => item.completed == Some(true),
+            Term::Archived => item.archived,
+            Term::Overdue => {
+                item.completed != Some(true) && item.due_date.is_some_and(|due| due < now)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    /// A term or keyword. `quoted` is where the first quoted part starts:
+    /// operators and keywords are only recognised before it, so `"AND"` and
+    /// `title:"a:b"` mean what they say.
+    Word {
+        text: String,
+        quoted: Option<usize>,
+    },
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            _ => {
+                let mut text = String::new();
+                let mut quoted = None;
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c != '"' {
+                        text.push(c);
+                        continue;
+                    }
+                    quoted.get_or_insert(text.len());
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => text.push(c),
+                            None => return Err("Unclosed quote in q".to_string()),
+                        }
+                    }
+                }
+                tokens.push(Token::Word { text, quoted });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word { text, quoted: None }) if text == keyword)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            self.at += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        loop {
+            if self.keyword("AND") {
+                self.at += 1;
+            } else if self.keyword("OR") || matches!(self.peek(), None | Some(Token::Close)) {
+                return Ok(expr);
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("NOT") {
+            self.at += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.at);
+        self.at += 1;
+        match token {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.peek() {
+                    Some(Token::Close) => {
+                        self.at += 1;
+                        Ok(expr)
+                    }
+                    _ => Err("Missing ) in q".to_string()),
+                }
+            }
+            Some(Token::Word { text, quoted }) => {
+                if let Some(rest) = text
+                    .strip_prefix('-')
+                    .filter(|rest| !rest.is_empty() && *quoted != Some(0))
+                {
+                    let quoted = quoted.map(|at| at.saturating_sub(1));
+                    return Ok(Expr::Not(Box::new(Expr::Term(term(rest, quoted)?))));
+                }
+                Ok(Expr::Term(term(text, *quoted)?))
+            }
+            Some(Token::Close) => Err("Unexpected ) in q".to_string()),
+            None => Err("q ends where a term was expected".to_string()),
+        }
+    }
+}
+
+/// Comparison operators, longest first so `<=` isn't read as `<`.
+const OPERATORS: [(&str, Option<Cmp>); 5] = [
+    ("<=", Some(Cmp::Le)),
+    (">=", Some(Cmp::Ge)),
+    ("<", Some(Cmp::Lt)),
+    (">", Some(Cmp::Gt)),
+    (":", None),
+];
+
+fn term(text: &str, quoted: Option<usize>) -> Result<Term, String> {
+    // The first operator character splits the field from the value.
+    let split = text[..quoted.unwrap_or(text.len())]
+        .find([':', '<', '>'])
+        .and_then(|at| {
+            let (field, rest) = text.split_at(at);
+            OPERATORS
+                .iter()
+                .find_map(|(op, cmp)| rest.strip_prefix(op).map(|value| (field, *cmp, value)))
+        });
+    let Some((field, cmp, value)) = split else {
+        return Ok(match text {
+            "completed" if quoted.is_none() => Term::Completed,
+            "archived" if quoted.is_none() => Term::Archived,
+            "overdue" if quoted.is_none() => Term::Overdue,
+            _ => Term::Text(search::terms(text)),
+        });
+    };
+
+    let time_field = match field {
+        "due" => Some(TimeField::Due),
+        "created" => Some(TimeField::Created),
+        "updated" => Some(TimeField::Updated),
+        "start" => Some(TimeField::Start),
+        _ => None,
+    };
+    match (time_field, cmp) {
+        (Some(time_field), Some(cmp)) => {
+            let at = Instant::parse(value).ok_or_else(|| {
+                format!(
+                    "Invalid time '{value}' for {field} in q, expected epoch millis, ISO-8601 or now±duration"
+                )
+            })?;
+            return Ok(Term::Time(time_field, cmp, at));
+        }
+        (Some(_), None) => {
+            return Err(format!("{field} in q takes <, <=, > or >=, not :"));
+        }
+        (None, Some(_)) => {
+            return Err(format!("{field} in q can't be compared; use {field}:value"));
+        }
+        (None, None) => {}
+    }
+
+    let value = value.to_string();
+    match field {
+        "type" => Ok(Term::Type(value)),
+        "tag" => Ok(Term::Tag(value.trim_start_matches('#').to_string())),
+        "id" => Ok(Term::Id(value)),
+        "title" => Ok(Term::Title(value.to_lowercase())),
+        "content" => Ok(Term::Content(value.to_lowercase())),
+        _ => Err(format!(
+            "Unknown field '{field}' in q, expected type, tag, id, title, content, due, created, updated or start"
+        )),
+    }
+}
+
+/// Parses a `?q=` expression.
+pub fn parse(input: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: lex(input)?,
+        at: 0,
+    };
+    if parser.peek().is_none() {
+        return Err("Empty q".to_string());
+    }
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(_) => Err("Unexpected ) in q".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(value: serde_json::Value) -> Item {
+        let mut fields = json!({
+            "id": "a", "type": "task", "title": "Write report", "content": null, "tags": [],
+            "code_location": null, "created_at": 100, "completed": null,
+            "due_date": null, "start_time": null, "end_time": null,
+        });
+        fields
+            .as_object_mut()
+            .unwrap()
+            .extend(value.as_object().unwrap().clone());
+        serde_json::from_value(fields).unwrap()
+    }
+
+    fn matches(q: &str, item: &Item) -> bool {
+        parse(q).unwrap().matches(item, 1_000)
+    }
+
+    #[test]
+    fn combines_terms() {
+        let task = item(json!({"tags": ["work/reports"], "due_date": 500}));
+        assert!(matches(
+            "type:task AND tag:work AND due<1970-01-02 AND NOT completed",
+            &task
+        ));
+        assert!(matches("type:TASK tag:work overdue", &task));
+        assert!(!matches("type:note OR -tag:work", &task));
+        assert!(matches(
+            "(type:note OR title:\"write rep\") due>=500",
+            &task
+        ));
+        assert!(matches("report", &task));
+        assert!(!matches("start>0", &task));
+        assert!(matches("due<now due>now-1s", &task));
+        assert!(!matches("due>now+1d", &task));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let task = item(json!({}));
+        assert!(matches("type:task OR type:note AND completed", &task));
+        assert!(!matches("(type:task OR type:note) AND completed", &task));
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for q in [
+            "",
+            "(type:task",
+            "type:task)",
+            "due:2025-01-01",
+            "title<x",
+            "colour:red",
+            "due<soon",
+            "due<now+",
+            "due<now*2d",
+            "NOT",
+            "\"open",
+        ] {
+            assert!(parse(q).is_err(), "{q}");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Manual ordering. An item's `rank` is a string of base-36 digits that
+//! sorts as the user arranged items; `POST /items/{id}/move` gives the item
+//! a rank between two neighbours, so nothing else has to be renumbered.
+//! Any subset of items, such as one board column, keeps the relative order
+//! of the whole.
+
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    clock::SharedClock,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    load_item, save_items,
+    tenant::{Tenant, TenantStore},
+    validation::FieldError,
+    Item,
+};
+
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const BASE: u8 = DIGITS.len() as u8;
+
+fn digit(c: u8) -> u8 {
+    DIGITS.iter().position(|&d| d == c).unwrap_or(0) as u8
+}
+
+/// A rank strictly between `lo` and `hi`, with `None` meaning unbounded on
+/// that side. Ranks never end in `0`, so there is always room below one.
+pub fn between(lo: Option<&str>, hi: Option<&str>) -> String {
+    let lo = lo.unwrap_or("").as_bytes();
+    let mut hi = hi.map(str::as_bytes);
+    let mut rank = Vec::new();
+    for i in 0.. {
+        let l = lo.get(i).map_or(0, |&c| digit(c));
+        let h = hi.and_then(|hi| hi.get(i)).map_or(BASE, |&c| digit(c));
+        if h > l + 1 {
+            rank.push(DIGITS[usize::from((l + h) / 2)]);
+            break;
+        }
+        rank.push(DIGITS[usize::from(l)]);
+        // Once below `hi`'s digit, any longer suffix stays below it.
+        if h > l {
+            hi = None;
+        }
+    }
+    String::from_utf8(rank).unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MovePayload {
+    /// Place the item just before this one.
+    before: Option<String>,
+    /// Or just after this one.
+    after: Option<String>,
+}
+
+/// `POST /items/{id}/move`: ranks the item just before or after another.
+/// An unranked neighbour is first ranked after every ranked item, so moving
+/// next to it works on a list nobody has arranged yet.
+pub async fn move_item(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+    payload: web::Json<MovePayload>,
+) -> Result<HttpResponse, ApiError> {
+    let (target, after) = match (&payload.before, &payload.after) {
+        (Some(id), None) => (id, false),
+        (None, Some(id)) => (id, true),
+        _ => {
+            return Err(ApiError::Invalid(vec![FieldError::new(
+                "before",
+                "give either before or after, not both",
+            )]))
+        }
+    };
+    let old = load_item(&db, &path.into_inner())?;
+    if *target == old.id {
+        return Err(ApiError::Invalid(vec![FieldError::new(
+            if after { "after" } else { "before" },
+            "must name another item",
+        )]));
+    }
+    let now = clock.now_millis();
+    let mut neighbour = load_item(&db, target)?;
+
+    let mut ranked: Vec<Item> = filter::scan_blocking(&db, ItemFilter::default())
+        .await?
+        .into_iter()
+        .filter(|item| item.rank.is_some() && item.id != old.id)
+        .collect();
+    filter::sort_by_key(&mut ranked, |item| item.rank.clone());
+    let mut changes = Vec::new();
+    if neighbour.rank.is_none() {
+        let last = ranked.last().and_then(|item| item.rank.as_deref());
+        let previous = neighbour.clone();
+        neighbour.rank = Some(between(last, None));
+        neighbour.touch(now);
+        changes.push((Some(previous), neighbour.clone()));
+        ranked.push(neighbour.clone());
+    }
+
+    let position = ranked
+        .iter()
+        .position(|item| item.id == neighbour.id)
+        .unwrap_or_default();
+    let (lo, hi) = if after {
+        (Some(position), Some(position + 1))
+    } else {
+        (position.checked_sub(1), Some(position))
+    };
+    let rank_at = |i: Option<usize>| i.and_then(|i| ranked.get(i)?.rank.as_deref());
+    let mut item = old.clone();
+    item.rank = Some(between(rank_at(lo), rank_at(hi)));
+    item.touch(now);
+    changes.push((Some(old), item.clone()));
+
+    save_items(&db, &tenant, &changes, Vec::new())?;
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_fall_strictly_between_their_bounds() {
+        assert_eq!(between(None, None), "i");
+        for (lo, hi) in [
+            (None, Some("1")),
+            (Some("i"), None),
+            (Some("i"), Some("j")),
+            (Some("iz"), Some("j")),
+            (Some("a"), Some("a1")),
+            (Some("zz"), None),
+        ] {
+            let rank = between(lo, hi);
+            assert!(lo.is_none_or(|lo| lo < rank.as_str()), "{lo:?} < {rank}");
+            assert!(hi.is_none_or(|hi| rank.as_str() < hi), "{rank} < {hi:?}");
+            assert!(!rank.ends_with('0'));
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    Error, ResponseError,
+};
+
+use crate::error::ApiError;
+
+/// POST routes that only read, and so stay open in read-only mode.
+const READ_ONLY_POSTS: &[&str] = &["/graphql", "/items/get-many", "/items/validate"];
+
+fn is_write(req: &ServiceRequest) -> bool {
+    match *req.method() {
+        Method::GET | Method::HEAD | Method::OPTIONS => false,
+        Method::POST => !READ_ONLY_POSTS.contains(&req.path()),
+        _ => true,
+    }
+}
+
+/// Middleware for `READ_ONLY=true`: refuses every mutating request with 403
+/// before it reaches a handler.
+pub async fn reject_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if is_write(&req) {
+        let res = ApiError::ReadOnly.error_response();
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
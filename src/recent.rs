@@ -0,0 +1,163 @@
+//! Short-lived `key -> item id` mappings, used to collapse repeated writes
+//! (duplicate captures, retried requests) onto the item first created.
+
+use crate::{
+    codec,
+    store::{BatchOp, StoreResult},
+    SharedStore,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// `None` while the request that claimed the key is still running.
+    #[serde(default)]
+    id: Option<String>,
+    recorded_at: i64,
+}
+
+/// What [`RecentKey::claim`] found.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Claim {
+    /// The key is now held by this request, which should record the item it
+    /// creates or [`RecentKey::release`] the key if it creates none.
+    Won,
+    /// An earlier request created this item under the key.
+    Recorded(String),
+    /// An earlier request holds the key and hasn't finished.
+    Pending,
+}
+
+/// One key in a recent-writes index, checked before an item is created and
+/// recorded once it has been stored.
+pub struct RecentKey {
+    tree: SharedStore,
+    key: String,
+    window_ms: i64,
+}
+
+impl RecentKey {
+    pub fn new(tree: SharedStore, key: String, window_ms: i64) -> Self {
+        RecentKey {
+            tree,
+            key,
+            window_ms,
+        }
+    }
+
+    /// Returns the id recorded under this key, if it was recorded within the
+    /// window ending at `now`. Older entries are treated as absent.
+    pub fn lookup(&self, now: i64) -> Option<String> {
+        let raw = self.tree.get(self.key.as_bytes()).ok()??;
+        self.live(&raw, now)?.id
+    }
+
+    /// The entry `raw` holds, unless it is unreadable or older than the
+    /// window ending at `now`.
+    fn live(&self, raw: &[u8], now: i64) -> Option<Entry> {
+        let entry: Entry = codec::decode(raw).ok()?;
+        (now - entry.recorded_at <= self.window_ms).then_some(entry)
+    }
+
+    fn encode(&self, id: Option<&str>, now: i64) -> Vec<u8> {
+        let entry = Entry {
+            id: id.map(str::to_string),
+            recorded_at: now,
+        };
+        codec::encode(self.tree.encoding(), &entry).expect("recent entry serializes")
+    }
+
+    /// Takes the key for a request arriving at `now`, unless an earlier one
+    /// holds it or recorded an item under it within the window. `gone` says
+    /// whether a recorded item has since been deleted, which frees the key.
+    /// The key is taken with a compare-and-swap, so of two requests racing
+    /// for it exactly one wins.
+    pub fn claim(&self, now: i64, gone: impl Fn(&str) -> bool) -> StoreResult<Claim> {
+        let key = self.key.as_bytes();
+        loop {
+            let current = self.tree.get(key)?;
+            match current.as_deref().and_then(|raw| self.live(raw, now)) {
+                Some(Entry { id: None, .. }) => return Ok(Claim::Pending),
+                Some(Entry { id: Some(id), .. }) if !gone(&id) => return Ok(Claim::Recorded(id)),
+                _ => {}
+            }
+            let unchanged = move |stored: Option<&[u8]>| stored == current.as_deref();
+            let claim = vec![BatchOp::Insert(key.to_vec(), self.encode(None, now))];
+            if self
+                .tree
+                .batch_if(&[(key, &unchanged)], claim, Vec::new())?
+            {
+                return Ok(Claim::Won);
+            }
+        }
+    }
+
+    /// Gives up a key this request [`claim`](Self::claim)ed without creating
+    /// anything, so a retry can run.
+    pub fn release(&self) -> StoreResult<()> {
+        self.tree.remove(self.key.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn record(&self, id: &str, now: i64) -> StoreResult<()> {
+        self.tree
+            .insert(self.key.as_bytes(), self.encode(Some(id), now))?;
+        Ok(())
+    }
+}
+
+/// Drops the entries of `tree` recorded more than `window_ms` before `now`,
+/// which [`RecentKey::lookup`] already treats as absent. Returns how many
+/// were dropped.
+pub fn prune(tree: &SharedStore, window_ms: i64, now: i64) -> StoreResult<usize> {
+    let mut ops = Vec::new();
+    for entry in tree.iter() {
+        let (key, raw) = entry?;
+        let expired =
+            codec::decode::<Entry>(&raw).map_or(true, |entry| now - entry.recorded_at > window_ms);
+        if expired {
+            ops.push(BatchOp::Remove(key));
+        }
+    }
+    let pruned = ops.len();
+    tree.batch(ops)?;
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Encoding, store::MemoryStore};
+    use std::sync::Arc;
+
+    #[test]
+    fn prune_drops_only_expired_entries() {
+        let tree: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let old = RecentKey::new(tree.clone(), "old".into(), 100);
+        let fresh = RecentKey::new(tree.clone(), "fresh".into(), 100);
+        old.record("a", 0).unwrap();
+        fresh.record("b", 150).unwrap();
+
+        assert_eq!(prune(&tree, 100, 200).unwrap(), 1);
+        assert_eq!(old.lookup(200), None);
+        assert_eq!(fresh.lookup(200).as_deref(), Some("b"));
+        assert!(tree.get(b"old").unwrap().is_none());
+    }
+
+    #[test]
+    fn only_one_request_claims_a_key() {
+        let tree: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let key = RecentKey::new(tree.clone(), "k".into(), 100);
+        let kept = |_: &str| false;
+
+        assert_eq!(key.claim(0, kept).unwrap(), Claim::Won);
+        assert_eq!(key.claim(10, kept).unwrap(), Claim::Pending);
+        key.record("a", 20).unwrap();
+        assert_eq!(key.claim(30, kept).unwrap(), Claim::Recorded("a".into()));
+        assert_eq!(key.claim(30, |_| true).unwrap(), Claim::Won);
+        key.release().unwrap();
+        assert_eq!(key.claim(40, kept).unwrap(), Claim::Won);
+        // A claim abandoned by a crashed request expires with the window.
+        assert_eq!(key.claim(200, kept).unwrap(), Claim::Won);
+    }
+}
@@ -0,0 +1,504 @@
+//! Repeating items. An item's `recurrence` is an RFC 5545 RRULE such as
+//! `FREQ=WEEKLY;BYDAY=MO,WE`, expanded from its due date (or, for events,
+//! its start time) in UTC. Completing a repeating task through
+//! `POST /items/{id}/complete` spawns the next occurrence as a new item,
+//! which carries the rule on.
+//!
+//! Supported parts are `FREQ` (daily, weekly, monthly or yearly),
+//! `INTERVAL`, `COUNT`, `UNTIL`, `BYDAY` with weekly rules and `BYMONTHDAY`
+//! with monthly ones. Dates a rule names that a month lacks, such as the
+//! 31st, are skipped rather than moved.
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use crate::{error::ApiError, load_item, tenant::TenantStore, time, validation::MAX_MILLIS, Item};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    freq: Freq,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<i64>,
+    by_day: Vec<Weekday>,
+    /// Days of the month; negative ones count back from the last.
+    by_month_day: Vec<i32>,
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("MO", Weekday::Mon),
+    ("TU", Weekday::Tue),
+    ("WE", Weekday::Wed),
+    ("TH", Weekday::Thu),
+    ("FR", Weekday::Fri),
+    ("SA", Weekday::Sat),
+    ("SU", Weekday::Sun),
+];
+
+/// Parses an RRULE `UNTIL`: a date, or a UTC date-time.
+fn parse_until(raw: &str) -> Option<i64> {
+    if let Ok(dt) = NaiveDateTime::parse_from_str(raw, "%Y%m%dT%H%M%SZ") {
+        return Some(dt.and_utc().timestamp_millis());
+    }
+    NaiveDate::parse_from_str(raw, "%Y%m%d")
+        .ok()
+        .and_then(|date| date.and_hms_milli_opt(23, 59, 59, 999))
+        .map(|dt| dt.and_utc().timestamp_millis())
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, String> {
+        let raw = raw.trim();
+        let raw = raw
+            .strip_prefix("RRULE:")
+            .or_else(|| raw.strip_prefix("rrule:"))
+            .unwrap_or(raw);
+        let mut freq = None;
+        let mut rule = Rule {
+            freq: Freq::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+        };
+        for part in raw.split(';').filter(|part| !part.is_empty()) {
+            let Some((key, value)) = part.split_once('=') else {
+                return Err(format!("'{part}' is not a KEY=VALUE part"));
+            };
+            let value = value.trim().to_uppercase();
+            match key.trim().to_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.as_str() {
+                        "DAILY" => Freq::Daily,
+                        "WEEKLY" => Freq::Weekly,
+                        "MONTHLY" => Freq::Monthly,
+                        "YEARLY" => Freq::Yearly,
+                        other => return Err(format!("FREQ={other} is not supported")),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or("INTERVAL must be a positive number")?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or("COUNT must be a positive number")?,
+                    )
+                }
+                "UNTIL" => {
+                    rule.until = Some(parse_until(&value).ok_or(
+                        "UNTIL must be a date (YYYYMMDD) or a UTC time (YYYYMMDDTHHMMSSZ)",
+                    )?)
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let weekday = WEEKDAYS
+                            .iter()
+                            .find(|(code, _)| *code == day.trim())
+                            .map(|(_, weekday)| *weekday)
+                            .ok_or_else(|| format!("BYDAY '{day}' is not a weekday code"))?;
+                        rule.by_day.push(weekday);
+                    }
+                }
+                "BYMONTHDAY" => {
+                    for day in value.split(',') {
+                        let day: i32 = day
+                            .trim()
+                            .parse()
+                            .ok()
+                            .filter(|day: &i32| (1..=31).contains(&day.abs()))
+                            .ok_or_else(|| format!("BYMONTHDAY '{day}' is not a day of a month"))?;
+                        rule.by_month_day.push(day);
+                    }
+                }
+                other => return Err(format!("RRULE part {other} is not supported")),
+            }
+        }
+        rule.freq = freq.ok_or("FREQ is required")?;
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err("COUNT and UNTIL can't both be given".to_string());
+        }
+        if !rule.by_day.is_empty() && rule.freq != Freq::Weekly {
+            return Err("BYDAY is only supported with FREQ=WEEKLY".to_string());
+        }
+        if !rule.by_month_day.is_empty() && rule.freq != Freq::Monthly {
+            return Err("BYMONTHDAY is only supported with FREQ=MONTHLY".to_string());
+        }
+        rule.by_day
+            .sort_by_key(|weekday| weekday.num_days_from_monday());
+        rule.by_day.dedup();
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let freq = match self.freq {
+            Freq::Daily => "DAILY",
+            Freq::Weekly => "WEEKLY",
+            Freq::Monthly => "MONTHLY",
+            Freq::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={freq}")?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if let Some(until) = self.until.and_then(DateTime::from_timestamp_millis) {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self
+                .by_day
+                .iter()
+                .filter_map(|day| WEEKDAYS.iter().find(|(_, w)| w == day))
+                .map(|(code, _)| *code)
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if !self.by_month_day.is_empty() {
+            let days: Vec<String> = self.by_month_day.iter().map(i32::to_string).collect();
+            write!(f, ";BYMONTHDAY={}", days.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+fn last_day_of_month(year: i32, month: u32) -> u32 {
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|first| first.checked_add_months(Months::new(1)))
+        .and_then(|next| next.pred_opt())
+        .map_or(28, |last| last.day())
+}
+
+impl Rule {
+    /// Midnight on the first day of the `k`th period after `start`'s. No
+    /// occurrence in that period is earlier.
+    fn period_start(&self, start: NaiveDateTime, k: u64) -> Option<NaiveDateTime> {
+        let steps = k.checked_mul(self.interval as u64)?;
+        let date = start.date();
+        let date = match self.freq {
+            Freq::Daily => date.checked_add_days(Days::new(steps))?,
+            Freq::Weekly => {
+                let monday = date - Days::new(date.weekday().num_days_from_monday() as u64);
+                monday.checked_add_days(Days::new(steps.checked_mul(7)?))?
+            }
+            Freq::Monthly => date
+                .with_day(1)?
+                .checked_add_months(Months::new(u32::try_from(steps).ok()?))?,
+            Freq::Yearly => {
+                NaiveDate::from_ymd_opt(date.year().checked_add(i32::try_from(steps).ok()?)?, 1, 1)?
+            }
+        };
+        date.and_hms_opt(0, 0, 0)
+    }
+
+    /// The occurrences in the `k`th period after `start`'s, in order.
+    fn period(&self, start: NaiveDateTime, k: u64) -> Vec<NaiveDateTime> {
+        let Some(first) = self.period_start(start, k) else {
+            return Vec::new();
+        };
+        let at = |date: NaiveDate| date.and_time(start.time());
+        let (date, time_date) = (first.date(), start.date());
+        match self.freq {
+            Freq::Daily => vec![at(date)],
+            Freq::Weekly if self.by_day.is_empty() => {
+                let offset = time_date.weekday().num_days_from_monday() as u64;
+                vec![at(date + Days::new(offset))]
+            }
+            Freq::Weekly => self
+                .by_day
+                .iter()
+                .map(|day| at(date + Days::new(day.num_days_from_monday() as u64)))
+                .collect(),
+            Freq::Monthly => {
+                let last = last_day_of_month(date.year(), date.month()) as i32;
+                let mut days: Vec<i32> = if self.by_month_day.is_empty() {
+                    vec![time_date.day() as i32]
+                } else {
+                    self.by_month_day
+                        .iter()
+                        .map(|&day| if day < 0 { last + 1 + day } else { day })
+                        .collect()
+                };
+                days.sort_unstable();
+                days.dedup();
+                days.into_iter()
+                    .filter_map(|day| date.with_day(u32::try_from(day).ok()?))
+                    .map(at)
+                    .collect()
+            }
+            Freq::Yearly => {
+                NaiveDate::from_ymd_opt(date.year(), time_date.month(), time_date.day())
+                    .map(at)
+                    .into_iter()
+                    .collect()
+            }
+        }
+    }
+
+    /// How many whole periods of a series starting at `start` can be
+    /// skipped before reaching `from`, erring one period early.
+    fn periods_before(&self, start: NaiveDateTime, from: i64) -> u64 {
+        let Some(from) = DateTime::from_timestamp_millis(from).map(|dt| dt.date_naive()) else {
+            return 0;
+        };
+        let date = start.date();
+        let elapsed = match self.freq {
+            Freq::Daily => (from - date).num_days(),
+            Freq::Weekly => (from - date).num_days() / 7,
+            Freq::Monthly => {
+                i64::from(from.year() - date.year()) * 12 + i64::from(from.month())
+                    - i64::from(date.month())
+            }
+            Freq::Yearly => i64::from(from.year() - date.year()),
+        };
+        u64::try_from(elapsed / i64::from(self.interval) - 1).unwrap_or(0)
+    }
+
+    /// Up to `limit` occurrences of the series starting at `start`, none
+    /// later than `until`. `start` itself counts only if the rule selects it.
+    pub fn occurrences(&self, start: i64, until: i64, limit: usize) -> Vec<i64> {
+        self.occurrences_between(start, start, until, limit)
+    }
+
+    /// [`Rule::occurrences`] from `from` on. Without a `COUNT`, which has to
+    /// be counted from the start, the periods before `from` are skipped
+    /// unexpanded, so a long-running series costs no more than the window.
+    pub fn occurrences_between(&self, start: i64, from: i64, until: i64, limit: usize) -> Vec<i64> {
+        let Some(dtstart) = DateTime::from_timestamp_millis(start).map(|dt| dt.naive_utc()) else {
+            return Vec::new();
+        };
+        let until = self
+            .until
+            .map_or(until, |own| own.min(until))
+            .min(MAX_MILLIS);
+        let mut remaining = self.count.map_or(usize::MAX, |count| count as usize);
+        let first = match self.count {
+            Some(_) => 0,
+            None => self.periods_before(dtstart, from),
+        };
+
+        let mut found = Vec::new();
+        for k in first.. {
+            let Some(period_start) = self.period_start(dtstart, k) else {
+                break;
+            };
+            if period_start.and_utc().timestamp_millis() > until {
+                break;
+            }
+            for occurrence in self.period(dtstart, k) {
+                let millis = occurrence.and_utc().timestamp_millis();
+                if millis < start {
+                    continue;
+                }
+                if millis > until || found.len() == limit || remaining == 0 {
+                    return found;
+                }
+                remaining -= 1;
+                if millis >= from {
+                    found.push(millis);
+                }
+            }
+        }
+        found
+    }
+}
+
+/// The time an item's occurrences are counted from.
+pub fn anchor(item: &Item) -> Option<i64> {
+    item.due_date.or(item.start_time)
+}
+
+/// The item for the occurrence after `item`'s, with `id` and created at
+/// `now`, or `None` if the series ends with `item`. Its times move by the
+/// same amount as the anchor, and its rule's `COUNT`, if any, is one less.
+pub fn next(item: &Item, id: String, now: i64) -> Option<Item> {
+    let rule: Rule = item.recurrence.as_deref()?.parse().ok()?;
+    let from = anchor(item)?;
+    if rule.count == Some(1) {
+        return None;
+    }
+    let following = rule
+        .occurrences(from, MAX_MILLIS, 2)
+        .into_iter()
+        .find(|&at| at > from)?;
+    let shift = following - from;
+    let rule = Rule {
+        count: rule.count.map(|count| count - 1),
+        ..rule
+    };
+    Some(Item {
+        id,
+        created_at: now,
+        updated_at: Some(now),
+        completed: item.completed.map(|_| false),
+        completed_at: None,
+        due_date: item.due_date.map(|due| due + shift),
+        start_time: item.start_time.map(|start| start + shift),
+        end_time: item.end_time.map(|end| end + shift),
+        attachments: Vec::new(),
+        archived: false,
+        snoozed_until: None,
+        recurrence: Some(rule.to_string()),
+        // Sharing the original's rank would leave nothing between the two.
+        rank: None,
+        version: 1,
+        ..item.clone()
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OccurrencesQuery {
+    /// Last time to expand to, as epoch millis or ISO 8601.
+    until: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct Occurrence {
+    start: i64,
+    /// Set for items with both a start and an end time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<i64>,
+}
+
+/// Most occurrences one request expands.
+const MAX_OCCURRENCES: usize = 1000;
+
+/// `GET /items/{id}/occurrences?until=`: when a repeating item next comes
+/// round, starting with its own occurrence, up to `until`. At most `limit`
+/// are returned, 100 by default. Items without a rule have just the one.
+pub async fn occurrences(
+    db: TenantStore,
+    path: web::Path<String>,
+    query: web::Query<OccurrencesQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let until = time::parse_millis(&query.until)
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid until '{}'", query.until)))?;
+    let limit = query.limit.unwrap_or(100).min(MAX_OCCURRENCES);
+    let item = load_item(&db, &path.into_inner())?;
+
+    let Some(start) = anchor(&item) else {
+        return Ok(HttpResponse::Ok().json(Vec::<Occurrence>::new()));
+    };
+    let starts = match item.recurrence.as_deref().map(str::parse::<Rule>) {
+        Some(Ok(rule)) => rule.occurrences(start, until, limit),
+        Some(Err(_)) => return Err(ApiError::Internal("Stored recurrence is invalid")),
+        None if start <= until && limit > 0 => vec![start],
+        None => Vec::new(),
+    };
+    let length = item.start_time.zip(item.end_time).map(|(s, e)| e - s);
+    let occurrences: Vec<Occurrence> = starts
+        .into_iter()
+        .map(|start| Occurrence {
+            start,
+            end: length.map(|length| start + length),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(occurrences))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(raw: &str) -> i64 {
+        time::parse_iso_millis(raw).unwrap()
+    }
+
+    fn expand(rule: &str, start: &str, until: &str) -> Vec<String> {
+        let rule: Rule = rule.parse().unwrap();
+        rule.occurrences(at(start), at(until), 100)
+            .into_iter()
+            .map(|millis| time::millis_to_iso(millis).unwrap()[..10].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn expands_weekly_by_day() {
+        // 2025-06-16 is a Monday.
+        assert_eq!(
+            expand(
+                "FREQ=WEEKLY;BYDAY=WE,MO",
+                "2025-06-17T09:00:00Z",
+                "2025-06-30T23:00:00Z"
+            ),
+            ["2025-06-18", "2025-06-23", "2025-06-25", "2025-06-30"]
+        );
+    }
+
+    #[test]
+    fn monthly_skips_short_months_and_honours_count() {
+        assert_eq!(
+            expand("FREQ=MONTHLY;COUNT=3", "2025-01-31", "2026-01-01"),
+            ["2025-01-31", "2025-03-31", "2025-05-31"]
+        );
+        assert_eq!(
+            expand(
+                "RRULE:FREQ=MONTHLY;BYMONTHDAY=-1",
+                "2025-02-01",
+                "2025-04-30"
+            ),
+            ["2025-02-28", "2025-03-31", "2025-04-30"]
+        );
+    }
+
+    #[test]
+    fn skips_ahead_to_the_window() {
+        let start = at("2000-01-03T09:00:00Z");
+        let (from, until) = (at("2025-06-16T00:00:00Z"), at("2025-06-30T00:00:00Z"));
+        for raw in [
+            "FREQ=DAILY;INTERVAL=3",
+            "FREQ=WEEKLY;BYDAY=MO,FR",
+            "FREQ=MONTHLY;BYMONTHDAY=-1,16",
+            "FREQ=YEARLY",
+            "FREQ=DAILY;COUNT=9400",
+        ] {
+            let rule: Rule = raw.parse().unwrap();
+            let mut all = rule.occurrences(start, until, usize::MAX);
+            all.retain(|&at| at >= from);
+            assert_eq!(
+                rule.occurrences_between(start, from, until, 100),
+                all,
+                "{raw}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_rules() {
+        assert!("FREQ=HOURLY".parse::<Rule>().is_err());
+        assert!("FREQ=DAILY;BYDAY=MO".parse::<Rule>().is_err());
+        assert!("INTERVAL=2".parse::<Rule>().is_err());
+        assert_eq!(
+            "freq=weekly;interval=2;byday=fr"
+                .parse::<Rule>()
+                .unwrap()
+                .to_string(),
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=FR"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! Reminders on items. Each fires at a fixed time, or a set time before the
+//! item's due date (for events, its start time), through the configured
+//! [`Notifier`]. A background task in the server checks for due reminders
+//! every `REMINDER_INTERVAL_SECS`.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    codec,
+    error::ApiError,
+    load_item,
+    notify::{Notice, Notifier},
+    recurrence,
+    store::{BatchOp, StoreResult},
+    tenant::{self, TenantStore},
+    time,
+    validation::FieldError,
+    Item, SharedStore,
+};
+
+/// Reminders, keyed by `{item id}\0{reminder id}`.
+pub const REMINDER_TREE: &str = "reminders";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reminder {
+    pub id: String,
+    /// Fire at this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    at: Option<i64>,
+    /// Or this many milliseconds before the item's due date or start time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    before: Option<i64>,
+    /// The fire time this reminder last fired for. Moving the item's due
+    /// date gives a relative reminder a new fire time, which arms it again.
+    #[serde(default)]
+    fired_for: Option<i64>,
+}
+
+impl Reminder {
+    /// When the reminder fires for `item`, if it can: a relative reminder
+    /// on an item without a due date or start time never does.
+    fn fire_at(&self, item: &Item) -> Option<i64> {
+        match (self.at, self.before) {
+            (Some(at), _) => Some(at),
+            (None, Some(before)) => recurrence::anchor(item).map(|anchor| anchor - before),
+            (None, None) => None,
+        }
+    }
+}
+
+/// A reminder as the API returns it, with its current fire time.
+#[derive(Debug, Serialize)]
+struct View {
+    #[serde(flatten)]
+    reminder: Reminder,
+    fire_at: Option<i64>,
+}
+
+impl View {
+    fn of(reminder: Reminder, item: &Item) -> Self {
+        View {
+            fire_at: reminder.fire_at(item),
+            reminder,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReminderPayload {
+    /// A time, as epoch millis or ISO 8601.
+    #[serde(default, deserialize_with = "time::deserialize_opt_millis")]
+    at: Option<i64>,
+    /// A duration such as `15m` or `1d` before the item's due date or start
+    /// time.
+    before: Option<String>,
+}
+
+impl ReminderPayload {
+    /// Checks that exactly one of `at` and `before` is given, and well formed.
+    fn into_reminder(self, id: String) -> Result<Reminder, ApiError> {
+        let before = match self.before.as_deref() {
+            None => None,
+            Some(raw) => Some(time::parse_duration_millis(raw).ok_or_else(|| {
+                ApiError::Invalid(vec![FieldError::new(
+                    "before",
+                    format!("'{raw}' is not a duration such as 15m, 2h or 1d"),
+                )])
+            })?),
+        };
+        if self.at.is_some() == before.is_some() {
+            return Err(ApiError::Invalid(vec![FieldError::new(
+                "at",
+                "give either at or before, not both",
+            )]));
+        }
+        Ok(Reminder {
+            id,
+            at: self.at,
+            before,
+            fired_for: None,
+        })
+    }
+}
+
+fn prefix(item_id: &str) -> Vec<u8> {
+    [item_id.as_bytes(), &[0]].concat()
+}
+
+fn key(item_id: &str, reminder_id: &str) -> Vec<u8> {
+    [prefix(item_id), reminder_id.as_bytes().to_vec()].concat()
+}
+
+fn reminders(db: &SharedStore, item_id: &str) -> StoreResult<Vec<Reminder>> {
+    let prefix = prefix(item_id);
+    let mut reminders = Vec::new();
+    for entry in db.tree(REMINDER_TREE)?.iter_after(&prefix) {
+        let (key, raw) = entry?;
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        if let Ok(reminder) = codec::decode(&raw) {
+            reminders.push(reminder);
+        }
+    }
+    Ok(reminders)
+}
+
+fn store(db: &SharedStore, item_id: &str, reminder: &Reminder) -> Result<(), ApiError> {
+    let bytes = codec::encode(db.encoding(), reminder)
+        .map_err(|_| ApiError::Internal("Serialization failed"))?;
+    db.tree(REMINDER_TREE)
+        .and_then(|tree| tree.insert(&key(item_id, &reminder.id), bytes))
+        .map_err(|_| ApiError::Internal("Failed to store reminder"))?;
+    Ok(())
+}
+
+fn find(db: &SharedStore, item_id: &str, reminder_id: &str) -> Result<Reminder, ApiError> {
+    let raw = db
+        .tree(REMINDER_TREE)?
+        .get(&key(item_id, reminder_id))?
+        .ok_or(ApiError::NotFound("Reminder not found"))?;
+    codec::decode(&raw).map_err(|_| ApiError::Internal("Deserialization failed"))
+}
+
+/// Drops the reminders of a deleted item.
+pub fn forget(db: &SharedStore, item_id: &str) -> StoreResult<()> {
+    let ops = reminders(db, item_id)?
+        .into_iter()
+        .map(|reminder| BatchOp::Remove(key(item_id, &reminder.id)))
+        .collect();
+    db.tree(REMINDER_TREE)?.batch(ops)
+}
+
+/// `GET /items/{id}/reminders`: the item's reminders.
+pub async fn list(db: TenantStore, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let item = load_item(&db, &path.into_inner())?;
+    let views: Vec<View> = reminders(&db, &item.id)?
+        .into_iter()
+        .map(|reminder| View::of(reminder, &item))
+        .collect();
+    Ok(HttpResponse::Ok().json(views))
+}
+
+/// `POST /items/{id}/reminders`: adds a reminder, given `at` or `before`.
+pub async fn create(
+    db: TenantStore,
+    path: web::Path<String>,
+    payload: web::Json<ReminderPayload>,
+) -> Result<HttpResponse, ApiError> {
+    let item = load_item(&db, &path.into_inner())?;
+    let reminder = payload
+        .into_inner()
+        .into_reminder(Uuid::new_v4().to_string())?;
+    store(&db, &item.id, &reminder)?;
+    Ok(HttpResponse::Created().json(View::of(reminder, &item)))
+}
+
+pub async fn get(
+    db: TenantStore,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, ApiError> {
+    let (item_id, reminder_id) = path.into_inner();
+    let item = load_item(&db, &item_id)?;
+    let reminder = find(&db, &item_id, &reminder_id)?;
+    Ok(HttpResponse::Ok().json(View::of(reminder, &item)))
+}
+
+/// `PUT /items/{id}/reminders/{rid}`: replaces a reminder's time, which
+/// arms it again.
+pub async fn replace(
+    db: TenantStore,
+    path: web::Path<(String, String)>,
+    payload: web::Json<ReminderPayload>,
+) -> Result<HttpResponse, ApiError> {
+    let (item_id, reminder_id) = path.into_inner();
+    let item = load_item(&db, &item_id)?;
+    find(&db, &item_id, &reminder_id)?;
+    let reminder = payload.into_inner().into_reminder(reminder_id)?;
+    store(&db, &item_id, &reminder)?;
+    Ok(HttpResponse::Ok().json(View::of(reminder, &item)))
+}
+
+pub async fn delete(
+    db: TenantStore,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, ApiError> {
+    let (item_id, reminder_id) = path.into_inner();
+    db.tree(REMINDER_TREE)?
+        .remove(&key(&item_id, &reminder_id))?
+        .ok_or(ApiError::NotFound("Reminder not found"))?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Sends every reminder in `db` that has come due by `now` and hasn't fired
+/// for its current fire time, then marks it fired. Reminders on missing or
+/// completed items are left alone, and ones whose delivery fails stay armed
+/// for the next call. Returns how many fired.
+fn fire_due(
+    db: &SharedStore,
+    tenant: Option<&str>,
+    notifier: &dyn Notifier,
+    now: i64,
+) -> StoreResult<usize> {
+    let tree = db.tree(REMINDER_TREE)?;
+    let mut fired = Vec::new();
+    for entry in tree.iter() {
+        let (key, raw) = entry?;
+        let Ok(mut reminder) = codec::decode::<Reminder>(&raw) else {
+            continue;
+        };
+        let Some(item_id) = key.split(|&b| b == 0).next() else {
+            continue;
+        };
+        let Some(item) = db
+            .get(item_id)?
+            .and_then(|raw| codec::decode::<Item>(&raw).ok())
+        else {
+            continue;
+        };
+        let Some(fire_at) = reminder.fire_at(&item) else {
+            continue;
+        };
+        if fire_at > now || reminder.fired_for == Some(fire_at) || item.completed == Some(true) {
+            continue;
+        }
+        let notice = Notice {
+            tenant: tenant.map(str::to_string),
+            item_id: item.id.clone(),
+            title: item.title.clone(),
+            reminder_id: reminder.id.clone(),
+            fire_at,
+            due_date: item.due_date,
+            start_time: item.start_time,
+        };
+        if let Err(e) = notifier.notify(&notice) {
+            eprintln!("Reminder {} failed to send: {e}", reminder.id);
+            continue;
+        }
+        reminder.fired_for = Some(fire_at);
+        fired.push(BatchOp::Insert(
+            key,
+            codec::encode(db.encoding(), &reminder)?,
+        ));
+    }
+    let count = fired.len();
+    tree.batch(fired)?;
+    Ok(count)
+}
+
+/// [`fire_due`] for the default keyspace and every tenant's.
+pub fn fire_all(
+    db: &SharedStore,
+    tenant_labels: &[String],
+    notifier: &dyn Notifier,
+    now: i64,
+) -> StoreResult<usize> {
+    let mut fired = fire_due(db, None, notifier, now)?;
+    for label in tenant_labels {
+        let keyspace = tenant::tenant_store(db, label)?;
+        fired += fire_due(&keyspace, Some(label), notifier, now)?;
+    }
+    Ok(fired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Encoding, store::MemoryStore};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Notifier for Recorder {
+        fn notify(&self, notice: &Notice) -> Result<(), String> {
+            self.0.lock().unwrap().push(notice.reminder_id.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn fires_each_reminder_once_per_fire_time() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let item = json!({
+            "id": "a", "type": "task", "title": "a", "content": null, "tags": [],
+            "code_location": null, "created_at": 0, "completed": null,
+            "due_date": 10_000, "start_time": null, "end_time": null,
+        });
+        db.insert(b"a", codec::encode(Encoding::Json, &item).unwrap())
+            .unwrap();
+        let remind = |id: &str, at: Option<i64>, before: Option<i64>| Reminder {
+            id: id.to_string(),
+            at,
+            before,
+            fired_for: None,
+        };
+        for reminder in [
+            remind("early", None, Some(5_000)),
+            remind("late", Some(20_000), None),
+        ] {
+            store(&db, "a", &reminder).unwrap();
+        }
+
+        let recorder = Recorder::default();
+        assert_eq!(fire_due(&db, None, &recorder, 6_000).unwrap(), 1);
+        assert_eq!(fire_due(&db, None, &recorder, 7_000).unwrap(), 0);
+        assert_eq!(fire_due(&db, None, &recorder, 20_000).unwrap(), 1);
+        assert_eq!(*recorder.0.lock().unwrap(), ["early", "late"]);
+    }
+}
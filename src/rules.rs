@@ -0,0 +1,139 @@
+use serde_json::{json, Map, Value};
+
+/// Which item fields matter for a given item type. This is the single source
+/// of truth for both write validation and the published JSON schemas.
+#[derive(Debug)]
+pub struct TypeRules {
+    pub name: &'static str,
+    /// Fields a payload of this type must provide.
+    pub required: &'static [&'static str],
+    /// Every field that is meaningful for this type, required or not.
+    pub fields: &'static [&'static str],
+}
+
+const COMMON_FIELDS: &[&str] = &[
+    "type",
+    "title",
+    "content",
+    "tags",
+    "code_location",
+    "priority",
+    "status",
+    "project_id",
+];
+
+pub const TYPE_RULES: &[TypeRules] = &[
+    TypeRules {
+        name: "note",
+        required: &["type", "title"],
+        fields: COMMON_FIELDS,
+    },
+    TypeRules {
+        name: "task",
+        required: &["type", "title", "due_date"],
+        fields: &[
+            "type",
+            "title",
+            "content",
+            "tags",
+            "code_location",
+            "priority",
+            "status",
+            "project_id",
+            "completed",
+            "due_date",
+            "recurrence",
+            "blocked_by",
+        ],
+    },
+    TypeRules {
+        name: "event",
+        required: &["type", "title", "start_time", "end_time"],
+        fields: &[
+            "type",
+            "title",
+            "content",
+            "tags",
+            "code_location",
+            "priority",
+            "status",
+            "project_id",
+            "start_time",
+            "end_time",
+            "recurrence",
+        ],
+    },
+];
+
+pub fn rules_for(item_type: &str) -> Option<&'static TypeRules> {
+    TYPE_RULES
+        .iter()
+        .find(|rules| rules.name.eq_ignore_ascii_case(item_type))
+}
+
+/// Renders the rules for one type as a JSON Schema document.
+pub fn json_schema(rules: &TypeRules) -> Value {
+    let properties: Map<String, Value> = rules
+        .fields
+        .iter()
+        .map(|field| (field.to_string(), field_schema(rules, field)))
+        .collect();
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": rules.name,
+        "type": "object",
+        "properties": properties,
+        "required": rules.required,
+    })
+}
+
+fn field_schema(rules: &TypeRules, field: &str) -> Value {
+    let millis = |description: &str| {
+        json!({
+            "type": ["integer", "null"],
+            "description": format!("{description}, in milliseconds since the Unix epoch"),
+        })
+    };
+
+    match field {
+        "type" => json!({ "const": rules.name }),
+        "title" => json!({ "type": "string", "minLength": 1 }),
+        "content" => json!({ "type": ["string", "null"] }),
+        "tags" => json!({ "type": "array", "items": { "type": "string" } }),
+        "code_location" => json!({
+            "type": ["object", "null"],
+            "properties": {
+                "file_path": { "type": "string" },
+                "line_number": { "type": "integer", "minimum": 1 },
+            },
+            "required": ["file_path", "line_number"],
+        }),
+        "completed" => json!({ "type": ["boolean", "null"] }),
+        "due_date" => millis("When the task is due"),
+        "start_time" => millis("When the event starts"),
+        "end_time" => millis("When the event ends"),
+        "recurrence" => json!({
+            "type": ["string", "null"],
+            "description": "An RFC 5545 RRULE the item repeats by, such as FREQ=WEEKLY;BYDAY=MO",
+        }),
+        "blocked_by" => json!({
+            "type": "array",
+            "items": {"type": "string"},
+            "description": "IDs of open items this one waits on",
+        }),
+        "project_id" => json!({
+            "type": ["string", "null"],
+            "description": "ID of the project the item belongs to",
+        }),
+        "status" => json!({
+            "type": ["string", "null"],
+            "description": "The board column the item is in, one of the configured statuses",
+        }),
+        "priority" => json!({
+            "type": ["integer", "string", "null"],
+            "description": "1 (most urgent) to 4, also accepted as P1 to P4",
+        }),
+        _ => json!({}),
+    }
+}
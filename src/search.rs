@@ -0,0 +1,238 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    clock::SharedClock,
+    codec,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    index,
+    store::StoreResult,
+    stream,
+    tenant::TenantStore,
+    time::TimeFormat,
+    Item, SharedStore,
+};
+
+/// Inverted index from words to the items holding them.
+pub const INDEX_TREE: &str = "search_index";
+
+const DEFAULT_AUTOCOMPLETE_LIMIT: usize = 10;
+const MAX_AUTOCOMPLETE_LIMIT: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct AutocompleteQuery {
+    prefix: String,
+    limit: Option<usize>,
+}
+
+/// Trimmed-down record for type-ahead suggestions.
+#[derive(Debug, Serialize)]
+pub struct Suggestion {
+    id: String,
+    title: String,
+}
+
+pub async fn autocomplete(
+    db: TenantStore,
+    query: web::Query<AutocompleteQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let prefix = query.prefix.to_lowercase();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUTOCOMPLETE_LIMIT)
+        .min(MAX_AUTOCOMPLETE_LIMIT);
+
+    let db = db.into_inner();
+    let suggestions = web::block(move || {
+        filter::iter(&db, ItemFilter::default())
+            .filter(|item| item.title.to_lowercase().starts_with(&prefix))
+            .take(limit)
+            .map(|item| Suggestion {
+                id: item.id,
+                title: item.title,
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(suggestions))
+}
+
+/// Lowercased alphanumeric words of `text`; everything else separates them.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Every word of the item's title, content and tags.
+fn item_words(item: &Item) -> BTreeSet<String> {
+    let content = item.content.as_deref().unwrap_or("");
+    words(&item.title)
+        .chain(words(content))
+        .chain(item.tags.iter().flat_map(|tag| words(tag)))
+        .collect()
+}
+
+/// Search terms; every one must begin some word of the item.
+pub fn terms(q: &str) -> Vec<String> {
+    words(q).collect()
+}
+
+pub fn text_matches(item: &Item, terms: &[String]) -> bool {
+    let words = item_words(item);
+    terms
+        .iter()
+        .all(|term| words.iter().any(|word| word.starts_with(term.as_str())))
+}
+
+/// Index entries are keyed `{word}\0{id}`, so the items holding any word
+/// that starts with a term are one range scan away.
+fn index_key(word: &str, id: &str) -> Vec<u8> {
+    [word.as_bytes(), &[0], id.as_bytes()].concat()
+}
+
+/// The keys `item` holds in the index, kept current by [`crate::index`]
+/// along with the other indexes.
+pub fn keys(item: &Item) -> BTreeSet<Vec<u8>> {
+    item_words(item)
+        .iter()
+        .map(|word| index_key(word, &item.id))
+        .collect()
+}
+
+/// IDs of the items holding a word that starts with `term`, in key order.
+fn ids_with_prefix(index: &SharedStore, term: &str) -> StoreResult<BTreeSet<String>> {
+    let mut ids = BTreeSet::new();
+    for entry in index.iter_after(term.as_bytes()) {
+        let (key, _) = entry?;
+        if !key.starts_with(term.as_bytes()) {
+            break;
+        }
+        if let Some(split) = key.iter().position(|&b| b == 0) {
+            ids.insert(String::from_utf8_lossy(&key[split + 1..]).into_owned());
+        }
+    }
+    Ok(ids)
+}
+
+/// The stored items matching every term and `filter`, looked up through the
+/// indexes. Each candidate is checked against its stored text, so an index
+/// entry left behind by an interrupted write can't produce a false hit.
+fn lookup(db: &SharedStore, terms: &[String], filter: &ItemFilter) -> StoreResult<Vec<Item>> {
+    let index = db.tree(INDEX_TREE)?;
+    // An unreadable filter index only means the terms alone narrow the search.
+    let mut candidates = index::candidates(db, filter).unwrap_or(None);
+    for term in terms {
+        let ids = ids_with_prefix(&index, term)?;
+        candidates = Some(match candidates {
+            Some(found) => found.intersection(&ids).cloned().collect(),
+            None => ids,
+        });
+    }
+
+    let mut items = Vec::new();
+    for id in candidates.unwrap_or_default() {
+        let Some(raw) = db.get(id.as_bytes())? else {
+            continue;
+        };
+        if let Ok(item) = codec::decode::<Item>(&raw) {
+            if text_matches(&item, terms) && filter.matches(&item) {
+                items.push(item);
+            }
+        }
+    }
+    Ok(items)
+}
+
+/// Word-prefix search over titles, content and tags, answered from the
+/// on-disk index. Accepts the same `type`/`tags` parameters as the listing,
+/// which narrow the search through their own indexes first.
+pub async fn search(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let mut query = query.into_inner();
+    // Here `q` is plain search text, not a filter expression.
+    let q = query
+        .remove("q")
+        .ok_or_else(|| ApiError::BadRequest("Missing q parameter".to_string()))?;
+    let terms = terms(&q);
+    let filter =
+        ItemFilter::from_query(&query, clock.now_millis()).map_err(ApiError::BadRequest)?;
+    let time = TimeFormat::from_query(&query).map_err(ApiError::BadRequest)?;
+
+    // With no terms every item matches, and a scan is all the index could do.
+    if terms.is_empty() {
+        let items = filter::iter(&db, filter).map(move |item| time.view(item));
+        return Ok(stream::json_array(items));
+    }
+    let db = db.into_inner();
+    let items = web::block(move || lookup(&db, &terms, &filter)).await??;
+    let items = items.into_iter().map(move |item| time.view(item));
+    Ok(stream::json_array(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Encoding, store::MemoryStore};
+    use std::sync::Arc;
+
+    fn stored(db: &SharedStore, id: &str, title: &str) -> Item {
+        stored_as(db, id, "note", title)
+    }
+
+    fn stored_as(db: &SharedStore, id: &str, item_type: &str, title: &str) -> Item {
+        let item: Item = serde_json::from_value(serde_json::json!({
+            "id": id, "type": item_type, "title": title, "content": null, "tags": [],
+            "code_location": null, "created_at": 0, "completed": null,
+            "due_date": null, "start_time": null, "end_time": null,
+        }))
+        .unwrap();
+        db.insert(id.as_bytes(), codec::encode(db.encoding(), &item).unwrap())
+            .unwrap();
+        item
+    }
+
+    #[test]
+    fn builds_a_missing_index_once() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        stored(&db, "a", "Rust ownership");
+        stored(&db, "b", "Ownership of sled trees");
+
+        assert_eq!(index::build(&db, &[]).unwrap(), 2);
+        assert_eq!(index::build(&db, &[]).unwrap(), 0);
+        let ids = |terms: &[&str]| -> Vec<String> {
+            let terms: Vec<String> = terms.iter().map(|t| t.to_string()).collect();
+            lookup(&db, &terms, &ItemFilter::default())
+                .unwrap()
+                .into_iter()
+                .map(|i| i.id)
+                .collect()
+        };
+        assert_eq!(ids(&["own"]), ["a", "b"]);
+        assert_eq!(ids(&["own", "sled"]), ["b"]);
+        assert_eq!(ids(&["wner"]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn filters_narrow_the_lookup() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        stored(&db, "a", "Rust ownership");
+        stored_as(&db, "b", "task", "Own the release");
+        index::build(&db, &[]).unwrap();
+
+        let filter = ItemFilter {
+            item_type: Some("task".to_string()),
+            ..ItemFilter::default()
+        };
+        let found = lookup(&db, &["own".to_string()], &filter).unwrap();
+        let ids: Vec<&str> = found.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(ids, ["b"]);
+    }
+}
@@ -0,0 +1,83 @@
+//! Snoozing: putting an item out of sight until later without touching its
+//! due date. A snoozed item is left out of listings until `snoozed_until`
+//! passes, unless they ask for `include_snoozed`.
+
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    clock::SharedClock,
+    error::ApiError,
+    load_item, save_item,
+    tenant::{Tenant, TenantStore},
+    time,
+    validation::FieldError,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SnoozePayload {
+    /// How long to snooze for, such as `3h` or `1d`.
+    #[serde(rename = "for")]
+    duration: Option<String>,
+    /// When to wake up, as epoch millis or ISO 8601.
+    #[serde(default, deserialize_with = "time::deserialize_opt_millis")]
+    until: Option<i64>,
+}
+
+impl SnoozePayload {
+    /// The time the snooze ends, which must be after `now`.
+    fn until(&self, now: i64) -> Result<i64, ApiError> {
+        let invalid =
+            |field, message: &str| ApiError::Invalid(vec![FieldError::new(field, message)]);
+        let until = match (&self.duration, self.until) {
+            (Some(raw), None) => time::parse_duration_millis(raw)
+                .and_then(|millis| now.checked_add(millis))
+                .ok_or_else(|| invalid("for", "must be a duration such as 3h or 1d"))?,
+            (None, Some(until)) => until,
+            _ => return Err(invalid("for", "give either for or until, not both")),
+        };
+        if until <= now {
+            return Err(invalid("until", "must be in the future"));
+        }
+        Ok(until)
+    }
+}
+
+fn set_snoozed(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    id: &str,
+    until: Option<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let mut item = load_item(&db, id)?;
+    if item.snoozed_until != until {
+        item.snoozed_until = until;
+        item.touch(clock.now_millis());
+        save_item(&db, &tenant, &item)?;
+    }
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// `POST /items/{id}/snooze`: hides the item from listings `for` a while or
+/// `until` a time, replacing any snooze already set.
+pub async fn snooze(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+    payload: web::Json<SnoozePayload>,
+) -> Result<HttpResponse, ApiError> {
+    let until = payload.until(clock.now_millis())?;
+    set_snoozed(db, tenant, clock, &path.into_inner(), Some(until))
+}
+
+/// `DELETE /items/{id}/snooze`: returns a snoozed item to listings now.
+pub async fn wake(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    set_snoozed(db, tenant, clock, &path.into_inner(), None)
+}
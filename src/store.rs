@@ -0,0 +1,396 @@
+//! Storage backends. Handlers talk to a [`Store`], never to sled directly, so
+//! the same code runs against the on-disk database or an in-memory map.
+
+use crate::codec::{CodecError, Encoding};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Transactional,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Bound,
+    sync::{Arc, Mutex, RwLock},
+};
+
+#[derive(Debug)]
+pub struct StoreError(String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "storage error: {}", self.0)
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<sled::Error> for StoreError {
+    fn from(e: sled::Error) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+impl From<CodecError> for StoreError {
+    fn from(e: CodecError) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+pub type KvPair = (Vec<u8>, Vec<u8>);
+
+/// Key-ordered iterator over a keyspace.
+pub type KvIter = Box<dyn Iterator<Item = StoreResult<KvPair>> + Send>;
+
+/// Computes a key's next value from its current one, for [`Store::update`].
+pub type UpdateFn<'a> = dyn FnMut(Option<&[u8]>) -> Option<Vec<u8>> + 'a;
+
+/// Whether a key's current value still allows a write, for [`Store::batch_if`].
+pub type Check = dyn Fn(Option<&[u8]>) -> bool + Sync;
+
+/// A key of the keyspace a batch is applied to and the check its value must pass.
+pub type Guard<'a> = (&'a [u8], &'a Check);
+
+#[derive(Debug, Clone)]
+pub enum BatchOp {
+    Insert(Vec<u8>, Vec<u8>),
+    Remove(Vec<u8>),
+}
+
+/// An ordered key-value keyspace.
+pub trait Store: Send + Sync {
+    fn get(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>>;
+
+    /// Stores `value` under `key`, returning the previous value.
+    fn insert(&self, key: &[u8], value: Vec<u8>) -> StoreResult<Option<Vec<u8>>>;
+
+    /// Deletes `key`, returning the value it held.
+    fn remove(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>>;
+
+    /// Atomically replaces the value at `key` with `f` of the current one;
+    /// `None` from `f` removes it. Returns the new value. `f` may be called
+    /// more than once if another writer races it.
+    fn update(&self, key: &[u8], f: &mut UpdateFn) -> StoreResult<Option<Vec<u8>>>;
+
+    /// Iterates every entry in ascending key order.
+    fn iter(&self) -> KvIter;
+
+    /// Iterates the entries with keys strictly after `key`, in ascending
+    /// order, without visiting the ones before it.
+    fn iter_after(&self, key: &[u8]) -> KvIter;
+
+    /// Applies all `ops` atomically.
+    fn batch(&self, ops: Vec<BatchOp>) -> StoreResult<()>;
+
+    /// Applies `ops` here and each list in `sides` to the tree of that name
+    /// opened from this store, atomically across all of them, but only if
+    /// every guard's check passes on the current value of its key here. The
+    /// checks run in the same atomic step as the writes, so nothing can
+    /// change a key in between. Returns whether the batch was applied.
+    fn batch_if(
+        &self,
+        guards: &[Guard],
+        ops: Vec<BatchOp>,
+        sides: Vec<(&str, Vec<BatchOp>)>,
+    ) -> StoreResult<bool>;
+
+    /// Opens, creating if needed, a named keyspace in the same backend. Trees
+    /// opened from a tree are namespaced under it, so a store handed to a
+    /// tenant can open its own side trees without colliding with another's.
+    fn tree(&self, name: &str) -> StoreResult<Arc<dyn Store>>;
+
+    /// Format new records should be written in; see [`crate::codec`]. Trees
+    /// share the encoding of the store they were opened from.
+    fn encoding(&self) -> Encoding;
+}
+
+pub struct SledStore {
+    db: sled::Db,
+    tree: sled::Tree,
+    /// Prepended to the names of trees opened from this one.
+    prefix: String,
+    encoding: Encoding,
+}
+
+impl SledStore {
+    /// Wraps the default tree of `db`, writing records in `encoding`.
+    pub fn new(db: sled::Db, encoding: Encoding) -> Self {
+        let tree = (*db).clone();
+        SledStore {
+            db,
+            tree,
+            prefix: String::new(),
+            encoding,
+        }
+    }
+}
+
+impl Store for SledStore {
+    fn get(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>> {
+        Ok(self.tree.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn insert(&self, key: &[u8], value: Vec<u8>) -> StoreResult<Option<Vec<u8>>> {
+        Ok(self.tree.insert(key, value)?.map(|v| v.to_vec()))
+    }
+
+    fn remove(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>> {
+        Ok(self.tree.remove(key)?.map(|v| v.to_vec()))
+    }
+
+    fn update(&self, key: &[u8], f: &mut UpdateFn) -> StoreResult<Option<Vec<u8>>> {
+        Ok(self.tree.update_and_fetch(key, f)?.map(|v| v.to_vec()))
+    }
+
+    fn iter(&self) -> KvIter {
+        Box::new(self.tree.iter().map(|entry| {
+            let (k, v) = entry?;
+            Ok((k.to_vec(), v.to_vec()))
+        }))
+    }
+
+    fn iter_after(&self, key: &[u8]) -> KvIter {
+        let range = (Bound::Excluded(key.to_vec()), Bound::Unbounded);
+        Box::new(self.tree.range(range).map(|entry| {
+            let (k, v) = entry?;
+            Ok((k.to_vec(), v.to_vec()))
+        }))
+    }
+
+    fn batch(&self, ops: Vec<BatchOp>) -> StoreResult<()> {
+        let mut batch = sled::Batch::default();
+        for op in ops {
+            match op {
+                BatchOp::Insert(k, v) => batch.insert(k, v),
+                BatchOp::Remove(k) => batch.remove(k),
+            }
+        }
+        Ok(self.tree.apply_batch(batch)?)
+    }
+
+    fn batch_if(
+        &self,
+        guards: &[Guard],
+        ops: Vec<BatchOp>,
+        sides: Vec<(&str, Vec<BatchOp>)>,
+    ) -> StoreResult<bool> {
+        let mut trees = vec![self.tree.clone()];
+        let mut all_ops = vec![ops];
+        for (name, ops) in sides {
+            trees.push(self.db.open_tree(format!("{}{name}", self.prefix))?);
+            all_ops.push(ops);
+        }
+        let applied = trees.as_slice().transaction(|views| {
+            for (key, check) in guards {
+                if !check(views[0].get(key)?.as_deref()) {
+                    return Err(ConflictableTransactionError::Abort(()));
+                }
+            }
+            for (tree, ops) in views.iter().zip(&all_ops) {
+                for op in ops {
+                    match op {
+                        BatchOp::Insert(k, v) => tree.insert(k.as_slice(), v.as_slice())?,
+                        BatchOp::Remove(k) => tree.remove(k.as_slice())?,
+                    };
+                }
+            }
+            Ok(())
+        });
+        match applied {
+            Ok(()) => Ok(true),
+            Err(TransactionError::Abort(())) => Ok(false),
+            Err(e) => Err(StoreError(format!("{e:?}"))),
+        }
+    }
+
+    fn tree(&self, name: &str) -> StoreResult<Arc<dyn Store>> {
+        let name = format!("{}{name}", self.prefix);
+        Ok(Arc::new(SledStore {
+            db: self.db.clone(),
+            tree: self.db.open_tree(&name)?,
+            prefix: format!("{name}/"),
+            encoding: self.encoding,
+        }))
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+}
+
+/// Volatile store for tests and throwaway instances. Keys stay ordered, like
+/// sled's, so iteration order matches the on-disk backend.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+    trees: Arc<Mutex<HashMap<String, Arc<MemoryStore>>>>,
+    prefix: String,
+    encoding: Encoding,
+}
+
+fn apply(data: &mut BTreeMap<Vec<u8>, Vec<u8>>, ops: Vec<BatchOp>) {
+    for op in ops {
+        match op {
+            BatchOp::Insert(k, v) => data.insert(k, v),
+            BatchOp::Remove(k) => data.remove(&k),
+        };
+    }
+}
+
+impl MemoryStore {
+    fn memory_tree(&self, name: &str) -> Arc<MemoryStore> {
+        let name = format!("{}{name}", self.prefix);
+        let mut trees = self.trees.lock().unwrap();
+        let tree = trees.entry(name.clone()).or_insert_with(|| {
+            Arc::new(MemoryStore {
+                data: RwLock::default(),
+                trees: self.trees.clone(),
+                prefix: format!("{name}/"),
+                encoding: self.encoding,
+            })
+        });
+        tree.clone()
+    }
+
+    pub fn new(encoding: Encoding) -> Self {
+        MemoryStore {
+            encoding,
+            ..Self::default()
+        }
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>> {
+        Ok(self.data.read().unwrap().get(key).cloned())
+    }
+
+    fn insert(&self, key: &[u8], value: Vec<u8>) -> StoreResult<Option<Vec<u8>>> {
+        Ok(self.data.write().unwrap().insert(key.to_vec(), value))
+    }
+
+    fn remove(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>> {
+        Ok(self.data.write().unwrap().remove(key))
+    }
+
+    fn update(&self, key: &[u8], f: &mut UpdateFn) -> StoreResult<Option<Vec<u8>>> {
+        let mut data = self.data.write().unwrap();
+        let next = f(data.get(key).map(Vec::as_slice));
+        match &next {
+            Some(value) => data.insert(key.to_vec(), value.clone()),
+            None => data.remove(key),
+        };
+        Ok(next)
+    }
+
+    fn iter(&self) -> KvIter {
+        let snapshot: Vec<KvPair> = self
+            .data
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Box::new(snapshot.into_iter().map(Ok))
+    }
+
+    fn iter_after(&self, key: &[u8]) -> KvIter {
+        let range = (Bound::Excluded(key.to_vec()), Bound::Unbounded);
+        let snapshot: Vec<KvPair> = self
+            .data
+            .read()
+            .unwrap()
+            .range(range)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Box::new(snapshot.into_iter().map(Ok))
+    }
+
+    fn batch(&self, ops: Vec<BatchOp>) -> StoreResult<()> {
+        apply(&mut self.data.write().unwrap(), ops);
+        Ok(())
+    }
+
+    fn batch_if(
+        &self,
+        guards: &[Guard],
+        ops: Vec<BatchOp>,
+        sides: Vec<(&str, Vec<BatchOp>)>,
+    ) -> StoreResult<bool> {
+        let mut sides: Vec<(Arc<MemoryStore>, Vec<BatchOp>)> = sides
+            .into_iter()
+            .map(|(name, ops)| (self.memory_tree(name), ops))
+            .collect();
+        // Always this tree first, then the side trees by name, so two batches
+        // can't each hold a lock the other needs.
+        sides.sort_by(|(a, _), (b, _)| a.prefix.cmp(&b.prefix));
+        let (trees, side_ops): (Vec<_>, Vec<_>) = sides.into_iter().unzip();
+        let mut data = self.data.write().unwrap();
+        if !guards
+            .iter()
+            .all(|(key, check)| check(data.get(*key).map(Vec::as_slice)))
+        {
+            return Ok(false);
+        }
+        let mut side_data: Vec<_> = trees
+            .iter()
+            .map(|tree| tree.data.write().unwrap())
+            .collect();
+        apply(&mut data, ops);
+        for (data, ops) in side_data.iter_mut().zip(side_ops) {
+            apply(data, ops);
+        }
+        Ok(true)
+    }
+
+    fn tree(&self, name: &str) -> StoreResult<Arc<dyn Store>> {
+        Ok(self.memory_tree(name))
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guarded_batches_only_apply_while_their_checks_pass(store: &dyn Store) {
+        let absent: &Check = &|current| current.is_none();
+        let insert = |value: &[u8]| vec![BatchOp::Insert(b"k".to_vec(), value.to_vec())];
+        let side = || vec![("log", vec![BatchOp::Insert(b"n".to_vec(), Vec::new())])];
+
+        assert!(store
+            .batch_if(&[(b"k", absent)], insert(b"a"), side())
+            .unwrap());
+        assert!(!store
+            .batch_if(&[(b"k", absent)], insert(b"b"), Vec::new())
+            .unwrap());
+        assert_eq!(store.get(b"k").unwrap().unwrap(), b"a");
+
+        let is_a: &Check = &|current| current == Some(b"a".as_slice());
+        let log = store.tree("log").unwrap();
+        assert!(store
+            .batch_if(&[(b"k", is_a)], insert(b"c"), Vec::new())
+            .unwrap());
+        log.remove(b"n").unwrap();
+        assert!(!store
+            .batch_if(&[(b"k", is_a)], insert(b"d"), side())
+            .unwrap());
+        assert_eq!(store.get(b"k").unwrap().unwrap(), b"c");
+        assert!(log.get(b"n").unwrap().is_none());
+    }
+
+    #[test]
+    fn memory_batches_honour_guards() {
+        guarded_batches_only_apply_while_their_checks_pass(&MemoryStore::new(Encoding::Json));
+    }
+
+    #[test]
+    fn sled_batches_honour_guards() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        guarded_batches_only_apply_while_their_checks_pass(&SledStore::new(db, Encoding::Json));
+    }
+}
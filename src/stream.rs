@@ -0,0 +1,219 @@
+use actix_web::{http::header::ContentType, web, web::Bytes, HttpResponse};
+use futures_util::stream;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+use crate::filter::Page;
+
+/// Serialized chunks buffered between the scanning thread and the response.
+const CHANNEL_CAPACITY: usize = 64;
+
+type Chunk = Result<Bytes, serde_json::Error>;
+
+/// Values that can be laid out as entries of a JSON object.
+pub trait Keyed {
+    fn key(&self) -> &str;
+}
+
+/// How a collection is laid out in a JSON body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shape {
+    #[default]
+    Array,
+    /// An object keyed by [`Keyed::key`]. Entries are written in scan order,
+    /// but JSON objects carry no ordering, so clients shouldn't rely on it.
+    Map,
+}
+
+impl Shape {
+    /// Reads `?shape=array|map`, defaulting to an array.
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        match query.get("shape").map(String::as_str) {
+            None | Some("array") => Ok(Shape::Array),
+            Some("map") => Ok(Shape::Map),
+            Some(other) => Err(format!(
+                "Invalid shape '{other}', expected 'array' or 'map'"
+            )),
+        }
+    }
+
+    fn open(self) -> &'static [u8] {
+        match self {
+            Shape::Array => b"[",
+            Shape::Map => b"{",
+        }
+    }
+
+    fn close(self) -> &'static [u8] {
+        match self {
+            Shape::Array => b"]",
+            Shape::Map => b"}",
+        }
+    }
+}
+
+/// Sending half handed to a producer running on the blocking pool.
+struct ChunkSender(mpsc::Sender<Chunk>);
+
+impl ChunkSender {
+    /// Queues a chunk; returns `None` once the client has gone away so the
+    /// producer can stop scanning.
+    fn send(&self, chunk: Chunk) -> Option<()> {
+        self.0.blocking_send(chunk).ok()
+    }
+
+    fn raw(&self, bytes: &'static [u8]) -> Option<()> {
+        self.send(Ok(Bytes::from_static(bytes)))
+    }
+
+    fn value<T: Serialize>(&self, value: &T, leading_comma: bool) -> Option<()> {
+        let mut chunk = if leading_comma {
+            vec![b',']
+        } else {
+            Vec::new()
+        };
+        let chunk = serde_json::to_writer(&mut chunk, value).map(|_| Bytes::from(chunk));
+        self.send(chunk)
+    }
+
+    /// Queues `value` as the next element of a `shape` collection.
+    fn element<T: Serialize + Keyed>(
+        &self,
+        value: &T,
+        shape: Shape,
+        leading_comma: bool,
+    ) -> Option<()> {
+        let mut chunk = if leading_comma {
+            vec![b',']
+        } else {
+            Vec::new()
+        };
+        let written = match shape {
+            Shape::Array => Ok(()),
+            Shape::Map => serde_json::to_writer(&mut chunk, value.key()).map(|_| chunk.push(b':')),
+        };
+        let chunk = written
+            .and_then(|_| serde_json::to_writer(&mut chunk, value))
+            .map(|_| Bytes::from(chunk));
+        self.send(chunk)
+    }
+
+    /// Queues `value` as one newline-terminated JSON line.
+    fn line<T: Serialize>(&self, value: &T) -> Option<()> {
+        let mut chunk = Vec::new();
+        let chunk = serde_json::to_writer(&mut chunk, value).map(|_| {
+            chunk.push(b'\n');
+            Bytes::from(chunk)
+        });
+        self.send(chunk)
+    }
+}
+
+/// Runs `produce` on the blocking thread pool and streams whatever it sends
+/// as the response body.
+///
+/// Walking sled and deserializing records would otherwise stall every other
+/// request on this worker for the duration of the scan.
+fn streaming<F>(content_type: ContentType, produce: F) -> HttpResponse
+where
+    F: FnOnce(&ChunkSender) -> Option<()> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<Chunk>(CHANNEL_CAPACITY);
+
+    // `web::block` spawns eagerly; the scan ends early if the client goes away
+    // and the receiving half is dropped.
+    let _scan = web::block(move || produce(&ChunkSender(tx)));
+
+    let body = stream::unfold(rx, |mut rx| async move {
+        let chunk = rx.recv().await?;
+        Some((chunk, rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .streaming(body)
+}
+
+/// Streams `values` as a single JSON array, serializing one element per chunk
+/// so memory use stays flat no matter how many values the iterator yields.
+pub fn json_array<T, I>(values: I) -> HttpResponse
+where
+    T: Serialize,
+    I: Iterator<Item = T> + Send + 'static,
+{
+    streaming(ContentType::json(), move |tx| {
+        tx.raw(b"[")?;
+        for (i, value) in values.enumerate() {
+            tx.value(&value, i > 0)?;
+        }
+        tx.raw(b"]")
+    })
+}
+
+/// Streams `values` as a single JSON object keyed by each value's key.
+pub fn json_map<T, I>(values: I) -> HttpResponse
+where
+    T: Serialize + Keyed,
+    I: Iterator<Item = T> + Send + 'static,
+{
+    streaming(ContentType::json(), move |tx| {
+        tx.raw(b"{")?;
+        for (i, value) in values.enumerate() {
+            tx.element(&value, Shape::Map, i > 0)?;
+        }
+        tx.raw(b"}")
+    })
+}
+
+/// Streams the `page` window of `values`, laid out as `shape`, wrapped in an
+/// object that also reports how many values matched in total. The items go
+/// out first and the counts trail them, so nothing has to be buffered to know
+/// the total.
+pub fn json_envelope<T, I>(values: I, page: Page, shape: Shape) -> HttpResponse
+where
+    T: Serialize + Keyed,
+    I: Iterator<Item = T> + Send + 'static,
+{
+    streaming(ContentType::json(), move |tx| {
+        tx.raw(b"{\"items\":")?;
+        tx.raw(shape.open())?;
+        let mut total = 0usize;
+        let mut count = 0usize;
+        for (i, value) in values.enumerate() {
+            total += 1;
+            if i >= page.offset && page.limit.is_none_or(|limit| count < limit) {
+                tx.element(&value, shape, count > 0)?;
+                count += 1;
+            }
+        }
+        tx.raw(shape.close())?;
+        tx.raw(b",")?;
+
+        let meta = json!({
+            "total": total,
+            "count": count,
+            "offset": page.offset,
+            "limit": page.limit,
+        });
+        // Splice the counts into the already-open object, dropping their braces.
+        let meta = meta.to_string();
+        tx.send(Ok(Bytes::from(meta[1..].to_string())))
+    })
+}
+
+/// Streams `values` as newline-delimited JSON, one value per line.
+pub fn ndjson<T, I>(values: I) -> HttpResponse
+where
+    T: Serialize,
+    I: Iterator<Item = T> + Send + 'static,
+{
+    let content_type = ContentType("application/x-ndjson".parse().unwrap());
+    streaming(content_type, move |tx| {
+        for value in values {
+            tx.line(&value)?;
+        }
+        Some(())
+    })
+}
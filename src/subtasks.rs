@@ -0,0 +1,180 @@
+//! Subtasks: items whose `parent_id` names another item. A parent's
+//! completion rolls up from its children as [`progress`], and deleting it
+//! can keep, detach or delete them.
+
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    changed_meanwhile, codec,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    links, load_item, progress, save_items,
+    store::StoreResult,
+    tenant::{Tenant, TenantStore},
+    trash,
+    validation::FieldError,
+    Item, SharedStore,
+};
+
+/// What deleting an item does to its subtasks, from `?children=`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChildPolicy {
+    /// Leave them pointing at the deleted item, so restoring it from the
+    /// trash brings the hierarchy back.
+    #[default]
+    Keep,
+    /// Clear their `parent_id`, making them top-level items.
+    Detach,
+    /// Move them, and their subtasks in turn, to the trash too.
+    Delete,
+    /// Refuse to delete an item that has subtasks.
+    Refuse,
+}
+
+fn find(db: &SharedStore, id: &str) -> StoreResult<Option<Item>> {
+    Ok(db
+        .get(id.as_bytes())?
+        .and_then(|raw| codec::decode(&raw).ok()))
+}
+
+/// Checks `item`'s parent against the store: it must exist and must not be
+/// `item` or one of its subtasks, which would make a loop. A parent in the
+/// trash still counts, since [`ChildPolicy::Keep`] leaves its subtasks
+/// pointing at it.
+pub fn check_parent(db: &SharedStore, item: &Item) -> StoreResult<Vec<FieldError>> {
+    let Some(parent_id) = &item.parent_id else {
+        return Ok(Vec::new());
+    };
+    // Naming the item itself is caught by `validate_item`.
+    if *parent_id == item.id {
+        return Ok(Vec::new());
+    }
+    let mut seen = HashSet::new();
+    let mut ancestor = find(db, parent_id)?;
+    if ancestor.is_none() && !trash::contains(db, parent_id)? {
+        let message = format!("'{parent_id}' is not an item");
+        return Ok(vec![FieldError::new("parent_id", message)]);
+    }
+    while let Some(current) = ancestor {
+        if current.id == item.id {
+            let message = format!("'{parent_id}' is a subtask of this item");
+            return Ok(vec![FieldError::new("parent_id", message)]);
+        }
+        ancestor = match current.parent_id {
+            Some(next) if seen.insert(next.clone()) => find(db, &next)?,
+            _ => None,
+        };
+    }
+    Ok(Vec::new())
+}
+
+/// The direct subtasks of `parent_id`, in key order.
+fn children(db: &SharedStore, parent_id: &str) -> Vec<Item> {
+    filter::iter(db, ItemFilter::default())
+        .filter(|item| item.parent_id.as_deref() == Some(parent_id))
+        .collect()
+}
+
+/// Every subtask below `parent_id` at any depth.
+fn descendants(db: &SharedStore, parent_id: &str) -> Vec<Item> {
+    let mut by_parent: HashMap<String, Vec<Item>> = HashMap::new();
+    for item in filter::iter(db, ItemFilter::default()) {
+        if let Some(parent) = item.parent_id.clone() {
+            by_parent.entry(parent).or_default().push(item);
+        }
+    }
+    let mut found = Vec::new();
+    let mut pending = vec![parent_id.to_string()];
+    while let Some(id) = pending.pop() {
+        for child in by_parent.remove(&id).unwrap_or_default() {
+            pending.push(child.id.clone());
+            found.push(child);
+        }
+    }
+    found
+}
+
+/// `GET /items/{id}/children`: the item's direct subtasks, each with the
+/// progress of its own.
+pub async fn list(db: TenantStore, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let parent = load_item(&db, &path.into_inner())?;
+    let store = db.into_inner();
+    let children = web::block(move || {
+        children(&store, &parent.id)
+            .into_iter()
+            .map(|child| progress::attach(&store, child))
+            .collect::<Vec<_>>()
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(children))
+}
+
+/// What deleting an item does to its subtasks under a [`ChildPolicy`].
+#[derive(Debug, Default)]
+pub struct Orphans {
+    /// Children detached, each as stored and with its `parent_id` cleared.
+    pub detached: Vec<(Option<Item>, Item)>,
+    /// Subtasks at any depth moved to the trash with the item.
+    pub deleted: Vec<Item>,
+}
+
+/// Works out what `policy` does to the subtasks of `item` if it is deleted
+/// at `now`, refusing with 409 under [`ChildPolicy::Refuse`] if it has any.
+pub fn orphans(
+    db: &SharedStore,
+    item: &Item,
+    policy: ChildPolicy,
+    now: i64,
+) -> Result<Orphans, ApiError> {
+    let mut orphans = Orphans::default();
+    match policy {
+        ChildPolicy::Keep => {}
+        ChildPolicy::Refuse => match children(db, &item.id).len() {
+            0 => {}
+            count => {
+                return Err(ApiError::Conflict(format!(
+                    "Item has {count} subtasks; delete them first or pass children=detach or children=delete"
+                )))
+            }
+        },
+        ChildPolicy::Detach => {
+            orphans.detached = children(db, &item.id)
+                .into_iter()
+                .map(|old| {
+                    let mut child = old.clone();
+                    child.parent_id = None;
+                    child.touch(now);
+                    (Some(old), child)
+                })
+                .collect();
+        }
+        ChildPolicy::Delete => orphans.deleted = descendants(db, &item.id),
+    }
+    Ok(orphans)
+}
+
+/// Applies `policy` to the subtasks of `item`, which is about to be deleted.
+pub fn before_delete(
+    db: &SharedStore,
+    tenant: &Tenant,
+    item: &Item,
+    policy: ChildPolicy,
+    now: i64,
+) -> Result<(), ApiError> {
+    let Orphans { detached, deleted } = orphans(db, item, policy, now)?;
+    if !detached.is_empty() {
+        save_items(db, tenant, &detached, Vec::new())?;
+    }
+    for child in &deleted {
+        if !trash::trash(db, tenant, child, now)? {
+            return Err(changed_meanwhile());
+        }
+    }
+    for child in &deleted {
+        links::strip_links(db, tenant, &child.id, now)?;
+    }
+    Ok(())
+}
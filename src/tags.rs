@@ -0,0 +1,327 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    clock::SharedClock,
+    codec,
+    error::ApiError,
+    filter::{self, ItemFilter, TagsMode},
+    save_items,
+    store::BatchOp,
+    tenant::{Tenant, TenantStore},
+    validation, Item, SharedStore,
+};
+
+/// Per-tag display metadata, stored independently of the items using the tag.
+pub const META_TREE: &str = "tag_meta";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TagMeta {
+    pub color: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagSummary {
+    name: String,
+    count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<TagMeta>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListTagsQuery {
+    /// Join each tag's stored metadata into the listing.
+    #[serde(default)]
+    meta: bool,
+}
+
+/// Every tag in use with the number of items carrying it, sorted by name.
+pub async fn list_tags(
+    db: TenantStore,
+    query: web::Query<ListTagsQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let items = filter::scan_blocking(&db, ItemFilter::default()).await?;
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for tag in items.into_iter().flat_map(|item| item.tags) {
+        *counts.entry(tag).or_default() += 1;
+    }
+
+    let meta_tree = db.tree(META_TREE)?;
+    let tags: Vec<TagSummary> = counts
+        .into_iter()
+        .map(|(name, count)| {
+            let meta = query
+                .meta
+                .then(|| meta_tree.get(name.as_bytes()).ok().flatten())
+                .flatten()
+                .and_then(|raw| codec::decode(&raw).ok());
+            TagSummary { name, count, meta }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+/// One level of the tag hierarchy, as returned by `GET /tags/tree`.
+#[derive(Debug, Serialize)]
+pub struct TagNode {
+    name: String,
+    /// The full tag this node stands for, e.g. `project/neonote`.
+    path: String,
+    /// Items tagged with exactly this path.
+    count: usize,
+    /// Items tagged with this path or anything nested under it.
+    total: usize,
+    children: Vec<TagNode>,
+}
+
+#[derive(Default)]
+struct NodeCounts {
+    count: usize,
+    total: usize,
+    children: BTreeMap<String, NodeCounts>,
+}
+
+impl NodeCounts {
+    fn at(&mut self, path: &[&str]) -> &mut NodeCounts {
+        path.iter().fold(self, |node, segment| {
+            node.children.entry(segment.to_string()).or_default()
+        })
+    }
+
+    fn into_nodes(self, parent: &str) -> Vec<TagNode> {
+        self.children
+            .into_iter()
+            .map(|(name, counts)| {
+                let path = if parent.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{parent}/{name}")
+                };
+                TagNode {
+                    count: counts.count,
+                    total: counts.total,
+                    children: counts.into_nodes(&path),
+                    name,
+                    path,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Tags arranged by their `/`-separated segments, with item counts at every
+/// level. An item counts once towards each ancestor however many of its tags
+/// sit beneath it.
+pub async fn tag_tree(db: TenantStore) -> Result<HttpResponse, ApiError> {
+    let items = filter::scan_blocking(&db, ItemFilter::default()).await?;
+
+    let mut root = NodeCounts::default();
+    for item in items {
+        let mut ancestors: BTreeSet<Vec<&str>> = BTreeSet::new();
+        for tag in &item.tags {
+            let segments: Vec<&str> = tag.split('/').filter(|s| !s.is_empty()).collect();
+            if segments.is_empty() {
+                continue;
+            }
+            root.at(&segments).count += 1;
+            for depth in 1..=segments.len() {
+                ancestors.insert(segments[..depth].to_vec());
+            }
+        }
+        for path in ancestors {
+            root.at(&path).total += 1;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(root.into_nodes("")))
+}
+
+pub async fn get_tag_meta(
+    db: TenantStore,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let name = path.into_inner();
+    let raw = db
+        .tree(META_TREE)?
+        .get(name.as_bytes())?
+        .ok_or(ApiError::NotFound("No metadata for tag"))?;
+    let meta =
+        codec::decode::<TagMeta>(&raw).map_err(|_| ApiError::Internal("Deserialization failed"))?;
+    Ok(HttpResponse::Ok().json(meta))
+}
+
+pub async fn put_tag_meta(
+    db: TenantStore,
+    path: web::Path<String>,
+    payload: web::Json<TagMeta>,
+) -> Result<HttpResponse, ApiError> {
+    let name = path.into_inner();
+    let meta = payload.into_inner();
+    let bytes = codec::encode(db.encoding(), &meta)
+        .map_err(|_| ApiError::Internal("Serialization failed"))?;
+    db.tree(META_TREE)
+        .and_then(|tree| tree.insert(name.as_bytes(), bytes))
+        .map_err(|_| ApiError::Internal("Failed to store tag metadata"))?;
+    Ok(HttpResponse::Ok().json(meta))
+}
+
+/// Cleans up a tag named in a request the way item tags are cleaned.
+fn clean(tag: &str) -> Option<String> {
+    validation::normalize_tags(&[tag.to_string()]).pop()
+}
+
+#[derive(Debug, Serialize)]
+struct Retagged {
+    /// Items rewritten.
+    updated: usize,
+}
+
+/// Replaces each of `sources` on every item carrying it with `into`, or drops
+/// it when `into` is `None`. The items and the tags' metadata change in one
+/// batch: `into` inherits the first source's metadata if it has none of its
+/// own, and the sources' metadata is removed. 404s when no source is in use
+/// or described.
+fn retag(
+    db: &SharedStore,
+    tenant: &Tenant,
+    now: i64,
+    sources: &[String],
+    into: Option<&str>,
+) -> Result<usize, ApiError> {
+    let filter = ItemFilter {
+        tags: Some(sources.to_vec()),
+        tags_mode: TagsMode::Any,
+        ..ItemFilter::default()
+    };
+    let changes: Vec<(Option<Item>, Item)> = filter::scan(db, &filter)
+        .into_iter()
+        .map(|old| {
+            let mut item = old.clone();
+            let tags: Vec<String> = item
+                .tags
+                .iter()
+                .filter_map(|tag| match sources.contains(tag) {
+                    true => into.map(str::to_string),
+                    false => Some(tag.clone()),
+                })
+                .collect();
+            item.tags = validation::normalize_tags(&tags);
+            item.touch(now);
+            (Some(old), item)
+        })
+        .collect();
+
+    let meta = db.tree(META_TREE)?;
+    let mut meta_ops = Vec::new();
+    let mut inherited = match into {
+        Some(into) => meta.get(into.as_bytes())?.is_some(),
+        None => true,
+    };
+    for source in sources {
+        let Some(raw) = meta.get(source.as_bytes())? else {
+            continue;
+        };
+        if let (Some(into), false) = (into, inherited) {
+            meta_ops.push(BatchOp::Insert(into.as_bytes().to_vec(), raw));
+            inherited = true;
+        }
+        meta_ops.push(BatchOp::Remove(source.as_bytes().to_vec()));
+    }
+
+    if changes.is_empty() && meta_ops.is_empty() {
+        return Err(ApiError::NotFound("Tag not found"));
+    }
+    save_items(db, tenant, &changes, vec![(META_TREE, meta_ops)])?;
+    Ok(changes.len())
+}
+
+fn retagged(updated: usize) -> HttpResponse {
+    HttpResponse::Ok().json(Retagged { updated })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenamePayload {
+    from: String,
+    to: String,
+}
+
+/// `POST /tags/rename`: renames a tag on every item carrying it. Renaming
+/// onto a tag already in use is refused; that's a merge.
+pub async fn rename_tag(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    payload: web::Json<RenamePayload>,
+) -> Result<HttpResponse, ApiError> {
+    let (Some(from), Some(to)) = (clean(&payload.from), clean(&payload.to)) else {
+        return Err(ApiError::BadRequest(
+            "from and to must be non-empty tags".to_string(),
+        ));
+    };
+    if from == to {
+        return Err(ApiError::BadRequest(
+            "from and to are the same tag".to_string(),
+        ));
+    }
+    let taken = ItemFilter {
+        tags: Some(vec![to.clone()]),
+        ..ItemFilter::default()
+    };
+    if filter::iter(&db, taken).next().is_some() {
+        return Err(ApiError::Conflict(format!(
+            "Tag '{to}' is already in use; merge into it instead"
+        )));
+    }
+    let updated = retag(&db, &tenant, clock.now_millis(), &[from], Some(&to))?;
+    Ok(retagged(updated))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergePayload {
+    tags: Vec<String>,
+    into: String,
+}
+
+/// `POST /tags/merge`: replaces every one of `tags` with `into`, which may
+/// already be in use. Items carrying several of them end up with `into` once.
+pub async fn merge_tags(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    payload: web::Json<MergePayload>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(into) = clean(&payload.into) else {
+        return Err(ApiError::BadRequest(
+            "into must be a non-empty tag".to_string(),
+        ));
+    };
+    let sources: Vec<String> = validation::normalize_tags(&payload.tags)
+        .into_iter()
+        .filter(|tag| *tag != into)
+        .collect();
+    if sources.is_empty() {
+        return Err(ApiError::BadRequest(
+            "tags must name at least one other tag".to_string(),
+        ));
+    }
+    let updated = retag(&db, &tenant, clock.now_millis(), &sources, Some(&into))?;
+    Ok(retagged(updated))
+}
+
+/// `DELETE /tags/{name}`: removes the tag from every item and drops its
+/// metadata.
+pub async fn delete_tag(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let name =
+        clean(&path.into_inner()).ok_or_else(|| ApiError::BadRequest("Empty tag".to_string()))?;
+    let updated = retag(&db, &tenant, clock.now_millis(), &[name], None)?;
+    Ok(retagged(updated))
+}
@@ -0,0 +1,109 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{codec, error::ApiError, tenant::TenantStore, tz, CreateItemPayload, SharedStore};
+
+/// Named item templates, keyed by template name.
+pub const TEMPLATE_TREE: &str = "templates";
+
+/// A starting point for new items, applied with `POST /items?template=`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Template {
+    #[serde(rename = "type")]
+    pub item_type: String,
+    /// Title for new items; `{date}` expands to the creation date.
+    pub title_pattern: Option<String>,
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateQuery {
+    /// Name of the template to prefill the item from.
+    template: Option<String>,
+    /// IANA zone `{date}` is expanded in; UTC when absent.
+    tz: Option<String>,
+}
+
+impl Template {
+    /// The template's fields as a create body, with placeholders expanded.
+    fn to_body(&self, date: &str) -> Value {
+        let mut body = json!({ "type": self.item_type, "tags": self.tags });
+        if let Some(pattern) = &self.title_pattern {
+            body["title"] = Value::String(pattern.replace("{date}", date));
+        }
+        if let Some(content) = &self.content {
+            body["content"] = Value::String(content.replace("{date}", date));
+        }
+        body
+    }
+}
+
+fn load(db: &SharedStore, name: &str) -> Result<Option<Template>, ApiError> {
+    let raw = db.tree(TEMPLATE_TREE)?.get(name.as_bytes())?;
+    raw.map(|raw| codec::decode(&raw))
+        .transpose()
+        .map_err(|_| ApiError::Internal("Deserialization failed"))
+}
+
+/// The create body the template `name` gives for `date`, if it exists.
+pub fn expand(db: &SharedStore, name: &str, date: &str) -> Result<Option<Value>, ApiError> {
+    Ok(load(db, name)?.map(|template| template.to_body(date)))
+}
+
+/// Turns a create request body into a payload, first laying it over the
+/// template named in the query, if any. Fields in the body win.
+pub fn prefill(
+    db: &SharedStore,
+    query: &CreateQuery,
+    body: Value,
+    created_at: i64,
+) -> Result<CreateItemPayload, ApiError> {
+    let body = match &query.template {
+        None => body,
+        Some(name) => {
+            let Some(template) = load(db, name)? else {
+                return Err(ApiError::BadRequest(format!("Unknown template '{name}'")));
+            };
+            let zone = tz::parse(query.tz.as_deref()).map_err(ApiError::BadRequest)?;
+            let date = tz::day_of(created_at, zone).format("%Y-%m-%d").to_string();
+            let Value::Object(fields) = body else {
+                return Err(ApiError::BadRequest(
+                    "Body must be a JSON object".to_string(),
+                ));
+            };
+            let mut merged = template.to_body(&date);
+            for (key, value) in fields {
+                merged[key] = value;
+            }
+            merged
+        }
+    };
+    serde_json::from_value(body).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+pub async fn get_template(
+    db: TenantStore,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let template =
+        load(&db, &path.into_inner())?.ok_or(ApiError::NotFound("Template not found"))?;
+    Ok(HttpResponse::Ok().json(template))
+}
+
+pub async fn put_template(
+    db: TenantStore,
+    path: web::Path<String>,
+    payload: web::Json<Template>,
+) -> Result<HttpResponse, ApiError> {
+    let name = path.into_inner();
+    let template = payload.into_inner();
+    let bytes = codec::encode(db.encoding(), &template)
+        .map_err(|_| ApiError::Internal("Serialization failed"))?;
+    db.tree(TEMPLATE_TREE)
+        .and_then(|tree| tree.insert(name.as_bytes(), bytes))
+        .map_err(|_| ApiError::Internal("Failed to store template"))?;
+    Ok(HttpResponse::Ok().json(template))
+}
@@ -0,0 +1,78 @@
+use actix_web::{dev::Payload, web, Error, FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::{ready, Ready};
+use std::ops::Deref;
+
+use crate::{error::ApiError, store::StoreResult, SharedStore};
+
+/// Names of per-tenant trees are this followed by the key's label.
+const TENANT_TREE_PREFIX: &str = "tenant:";
+
+/// Label of the API key a request authenticated with, attached by the API-key
+/// middleware. Requests made with the primary `API_KEY` carry no label.
+#[derive(Debug, Clone, Default)]
+pub struct Tenant(pub Option<String>);
+
+impl Tenant {
+    /// The tenant `req` authenticated as.
+    pub fn of(req: &HttpRequest) -> Tenant {
+        req.extensions()
+            .get::<Tenant>()
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl FromRequest for Tenant {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Tenant::of(req)))
+    }
+}
+
+/// The caller's view of the store. The primary key sees the default keyspace,
+/// exactly as a single-key deployment does; each labelled key gets its own
+/// tree, and every side tree it opens is nested under that.
+pub struct TenantStore(SharedStore);
+
+impl TenantStore {
+    pub fn into_inner(self) -> SharedStore {
+        self.0
+    }
+}
+
+impl Deref for TenantStore {
+    type Target = SharedStore;
+
+    fn deref(&self) -> &SharedStore {
+        &self.0
+    }
+}
+
+impl FromRequest for TenantStore {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(scoped_store(req))
+    }
+}
+
+/// The keyspace holding everything stored by the tenant labelled `label`.
+pub fn tenant_store(db: &SharedStore, label: &str) -> StoreResult<SharedStore> {
+    db.tree(&format!("{TENANT_TREE_PREFIX}{label}"))
+}
+
+fn scoped_store(req: &HttpRequest) -> Result<TenantStore, Error> {
+    let db = req
+        .app_data::<web::Data<SharedStore>>()
+        .ok_or(ApiError::Internal("Store not configured"))?;
+    let label = req.extensions().get::<Tenant>().and_then(|t| t.0.clone());
+    match label {
+        None => Ok(TenantStore(db.get_ref().clone())),
+        Some(label) => tenant_store(db, &label)
+            .map(TenantStore)
+            .map_err(|e| ApiError::from(e).into()),
+    }
+}
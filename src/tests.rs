@@ -0,0 +1,2795 @@
+//! End-to-end tests that mount the full app, middleware included, over an
+//! in-memory store and a fixed clock.
+
+use actix_http::Request;
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::{header, StatusCode},
+    test, Error,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::{
+    admin::StartupInfo,
+    build_app,
+    clock::FakeClock,
+    codec::Encoding,
+    config::{self, CaptureId, Config, Limits, NotifierKind, StoreBackend, TagOverflow},
+    ids::IdStrategy,
+    store::MemoryStore,
+    types::ItemType,
+    AppState,
+};
+
+const API_KEY: &str = "test-key";
+const NOW: i64 = 1_750_000_000_000;
+
+fn test_config() -> Config {
+    Config {
+        api_key: API_KEY.into(),
+        tenant_keys: Vec::new(),
+        store: StoreBackend::Memory,
+        storage_encoding: Encoding::Json,
+        id_strategy: IdStrategy::Uuid,
+        limits: Limits::default(),
+        custom_types: Vec::new(),
+        statuses: config::default_statuses(),
+        capture_tag_overflow: TagOverflow::Reject,
+        capture_id: CaptureId::Uuid,
+        capture_default_type: "note".into(),
+        capture_type_tags: config::default_type_tags(),
+        dedup_capture: false,
+        dedup_window_secs: 300,
+        idempotency_ttl_secs: 24 * 60 * 60,
+        max_attachment_bytes: 5 * 1024 * 1024,
+        append_coalesce_ms: None,
+        append_flush_bytes: 64 * 1024,
+        reminder_notifier: NotifierKind::Log,
+        reminder_interval_secs: 30,
+        track_access: false,
+        strict_query: false,
+        read_only: false,
+        scan_on_start: false,
+        workers: None,
+        keep_alive_secs: None,
+        client_timeout_ms: None,
+    }
+}
+
+async fn app() -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = Error>
+{
+    app_with(test_config()).await
+}
+
+async fn app_with(
+    config: Config,
+) -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = Error> {
+    let state = AppState::new(
+        Arc::new(MemoryStore::new(config.storage_encoding)),
+        StartupInfo { fresh: true },
+        config,
+        Arc::new(FakeClock::new(NOW)),
+    );
+    test::init_service(build_app(&state)).await
+}
+
+fn get(uri: &str) -> test::TestRequest {
+    test::TestRequest::get()
+        .uri(uri)
+        .insert_header(("X-API-Key", API_KEY))
+}
+
+fn post(uri: &str, body: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(uri)
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(body)
+}
+
+/// Sends `req` and returns the status with the body parsed as JSON, or
+/// `Value::Null` when the body is empty or not JSON.
+async fn send<S, B>(app: &S, req: test::TestRequest) -> (StatusCode, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let res = test::call_service(app, req.to_request()).await;
+    let status = res.status();
+    let body = test::read_body(res).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[actix_web::test]
+async fn rejects_requests_without_the_api_key() {
+    let app = app().await;
+
+    let req = test::TestRequest::get().uri("/items");
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri("/items")
+        .insert_header(("X-API-Key", "wrong"));
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = send(&app, get("/items")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([]));
+}
+
+#[actix_web::test]
+async fn item_crud_lifecycle() {
+    let app = app().await;
+
+    let (status, created) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "note", "title": "First", "tags": ["a"]}),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["created_at"], NOW);
+    let id = created["id"].as_str().unwrap().to_string();
+
+    let (status, fetched) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched, created);
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"title": "Renamed"}));
+    let (status, updated) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["title"], "Renamed");
+    assert_eq!(updated["tags"], json!(["a"]));
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"type": "task", "title": "Replaced", "due_date": 1_000}));
+    let (status, replaced) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(replaced["id"], id.as_str());
+    assert_eq!(replaced["type"], "task");
+    assert_eq!(replaced["tags"], json!([]));
+    assert_eq!(replaced["created_at"], NOW);
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"title": "No type"}));
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY));
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn errors_are_json_with_a_code() {
+    let app = app().await;
+
+    let (status, body) = send(&app, get("/items/missing")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        body,
+        json!({"code": "not_found", "message": "Item not found", "details": null})
+    );
+
+    let (status, body) = send(&app, get("/items?sort=nonsense")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "bad_request");
+
+    let (status, body) = send(&app, post("/items", json!({"title": "No type"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "bad_request");
+
+    let req = test::TestRequest::get().uri("/items");
+    let (status, body) = send(&app, req).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+}
+
+#[actix_web::test]
+async fn create_rejects_missing_title() {
+    let app = app().await;
+
+    let (status, body) = send(&app, post("/items", json!({"type": "note", "title": " "}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "validation_failed");
+    assert_eq!(body["details"][0]["field"], "title");
+}
+
+#[actix_web::test]
+async fn create_rejects_unfilterable_tags_and_absurd_times() {
+    let app = app().await;
+
+    let body = json!({"type": "note", "title": "x", "tags": ["ok", "a b", "-x", "c,d"]});
+    let (status, body) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields: Vec<_> = body["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["tags", "tags", "tags"]);
+
+    let body = json!({"type": "task", "title": "x", "due_date": -5});
+    let (status, body) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "due_date");
+
+    let body = json!({"type": "event", "title": "x", "start_time": 10, "end_time": 5});
+    let (status, body) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "end_time");
+}
+
+#[actix_web::test]
+async fn unknown_types_are_rejected_unless_configured() {
+    let app = app_with(Config {
+        custom_types: vec![ItemType::from("recipe")],
+        ..test_config()
+    })
+    .await;
+
+    let (status, body) = send(&app, post("/items", json!({"type": "taks", "title": "x"}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "validation_failed");
+    assert_eq!(body["details"][0]["field"], "type");
+
+    let body = json!({"type": " Task", "title": "x", "due_date": 1_000});
+    let (status, item) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(item["type"], "task");
+
+    let (status, item) = send(
+        &app,
+        post("/items", json!({"type": "Recipe", "title": "y"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(item["type"], "recipe");
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/items/{}", item["id"].as_str().unwrap()))
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"type": "evnet"}));
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn update_clears_fields_sent_as_null() {
+    let app = app().await;
+    let (_, item) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "task", "title": "t", "content": "c", "due_date": 1_000}),
+        ),
+    )
+    .await;
+    let uri = format!("/items/{}", item["id"].as_str().unwrap());
+
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"content": null}));
+    let (status, updated) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["content"], Value::Null);
+    assert_eq!(updated["due_date"], 1_000);
+
+    // Tasks must keep a due date.
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"due_date": null}));
+    let (status, body) = send(&app, req).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "due_date");
+}
+
+#[actix_web::test]
+async fn capture_parses_tags_and_type() {
+    let app = app().await;
+
+    let (status, item) = send(
+        &app,
+        post(
+            "/items/capture",
+            json!({"text": "Buy milk #todo #errands\nsemi-skimmed"}),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(item["type"], "task");
+    assert_eq!(item["title"], "Buy milk");
+    assert_eq!(item["tags"], json!(["todo", "errands"]));
+    assert_eq!(item["content"], "semi-skimmed");
+
+    let (status, item) = send(
+        &app,
+        post("/items/capture", json!({"text": "Plain thought"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(item["type"], "note");
+    assert_eq!(item["tags"], json!([]));
+}
+
+#[actix_web::test]
+async fn filters_by_type_and_tags() {
+    let app = app().await;
+
+    for body in [
+        json!({"type": "note", "title": "n1", "tags": ["work"]}),
+        json!({"type": "task", "title": "t1", "tags": ["work", "urgent"], "due_date": 1_000}),
+        json!({"type": "task", "title": "t2", "tags": ["home"], "due_date": 1_000}),
+    ] {
+        let (status, _) = send(&app, post("/items", body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let titles = |items: Value| {
+        let mut titles: Vec<String> = items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["title"].as_str().unwrap().to_string())
+            .collect();
+        titles.sort();
+        titles
+    };
+
+    let (_, items) = send(&app, get("/items?type=task")).await;
+    assert_eq!(titles(items), ["t1", "t2"]);
+
+    let (_, items) = send(&app, get("/items?tags=work")).await;
+    assert_eq!(titles(items), ["n1", "t1"]);
+
+    let (_, items) = send(&app, get("/items?type=task&tags=work")).await;
+    assert_eq!(titles(items), ["t1"]);
+
+    let (_, items) = send(&app, get("/items?tags=urgent,home&tags_mode=any")).await;
+    assert_eq!(titles(items), ["t1", "t2"]);
+
+    let (_, items) = send(&app, get("/items?tags=-urgent")).await;
+    assert_eq!(titles(items), ["n1", "t2"]);
+
+    let (_, items) = send(
+        &app,
+        get("/items?type=task&tags_any=urgent,home&tags=-work"),
+    )
+    .await;
+    assert_eq!(titles(items), ["t2"]);
+
+    let (status, _) = send(&app, get("/items?limit=nope")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn admin_info_reports_fresh_store_and_count() {
+    let app = app().await;
+    send(
+        &app,
+        post("/items", json!({"type": "note", "title": "seed"})),
+    )
+    .await;
+
+    let (status, info) = send(&app, get("/admin/info")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info["fresh"], true);
+    assert_eq!(info["item_count"], 1);
+}
+
+#[actix_web::test]
+async fn unrouted_method_is_405_with_allow() {
+    let app = app().await;
+
+    let req = test::TestRequest::post()
+        .uri("/items/some-id")
+        .insert_header(("X-API-Key", API_KEY))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        res.headers().get(header::ALLOW).unwrap(),
+        "GET, PUT, PATCH, DELETE"
+    );
+}
+
+#[actix_web::test]
+async fn map_shape_keys_items_by_id() {
+    let app = app().await;
+    let (_, a) = send(&app, post("/items", json!({"type": "note", "title": "a"}))).await;
+    let (_, b) = send(&app, post("/items", json!({"type": "note", "title": "b"}))).await;
+
+    let (status, map) = send(&app, get("/items?shape=map")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(map.as_object().unwrap().len(), 2);
+    assert_eq!(map[a["id"].as_str().unwrap()], a);
+    assert_eq!(map[b["id"].as_str().unwrap()], b);
+
+    let (_, envelope) = send(&app, get("/items?shape=map&envelope=true&limit=1")).await;
+    assert_eq!(envelope["items"].as_object().unwrap().len(), 1);
+    assert_eq!(envelope["total"], 2);
+
+    let (status, _) = send(&app, get("/items?shape=list")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn filters_items_missing_a_field() {
+    let app = app().await;
+    for body in [
+        json!({"type": "task", "title": "dated", "due_date": 1_000}),
+        json!({"type": "note", "title": "bare"}),
+    ] {
+        send(&app, post("/items", body)).await;
+    }
+    // Only a capture can leave a task without its due date.
+    send(
+        &app,
+        post("/items/capture", json!({"text": "undated #todo"})),
+    )
+    .await;
+
+    let (_, items) = send(&app, get("/items?type=task&missing=due_date")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    assert_eq!(items[0]["title"], "undated");
+
+    let (_, items) = send(&app, get("/items?missing=due_date,tags")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    assert_eq!(items[0]["title"], "bare");
+
+    let (status, _) = send(&app, get("/items?missing=colour")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn tenants_only_see_their_own_items() {
+    let app = app_with(Config {
+        tenant_keys: vec![("alice".into(), "alice-key".into())],
+        ..test_config()
+    })
+    .await;
+    let as_alice = |req: test::TestRequest| req.insert_header(("X-API-Key", "alice-key"));
+
+    let (status, _) = send(
+        &app,
+        post("/items", json!({"type": "note", "title": "primary"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, mine) = send(
+        &app,
+        as_alice(post(
+            "/items",
+            json!({"type": "note", "title": "alice", "tags": ["t"]}),
+        )),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (_, items) = send(&app, as_alice(get("/items"))).await;
+    assert_eq!(items, json!([mine.clone()]));
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    assert_eq!(items[0]["title"], "primary");
+
+    let id = mine["id"].as_str().unwrap();
+    let (status, _) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, tags) = send(&app, get("/tags")).await;
+    assert_eq!(tags, json!([]));
+}
+
+#[actix_web::test]
+async fn read_only_mode_blocks_writes_but_not_reads() {
+    let app = app_with(Config {
+        read_only: true,
+        ..test_config()
+    })
+    .await;
+
+    let (status, body) = send(&app, post("/items", json!({"type": "note", "title": "x"}))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "read_only");
+
+    let req = test::TestRequest::delete()
+        .uri("/items/some-id")
+        .insert_header(("X-API-Key", API_KEY));
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(&app, get("/items")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        post("/items/validate", json!({"type": "note", "title": "x"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, found) = send(&app, post("/items/get-many", json!({"ids": ["some-id"]}))).await;
+    assert_eq!(status, StatusCode::OK, "{found}");
+
+    let req = test::TestRequest::post().uri("/items");
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn iso_time_mode_formats_timestamps() {
+    let app = app().await;
+    let (_, item) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "task", "title": "t", "due_date": "2025-07-01T09:30:00Z"}),
+        ),
+    )
+    .await;
+    let id = item["id"].as_str().unwrap();
+
+    let (_, iso) = send(&app, get(&format!("/items/{id}?time=iso"))).await;
+    assert_eq!(iso["due_date"], "2025-07-01T09:30:00.000Z");
+    assert_eq!(iso["created_at"], "2025-06-15T15:06:40.000Z");
+    assert_eq!(iso["start_time"], Value::Null);
+
+    let (_, items) = send(&app, get("/items?time=iso")).await;
+    assert_eq!(items[0], iso);
+
+    let (_, millis) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(millis["created_at"], NOW);
+}
+
+#[actix_web::test]
+async fn strict_listing_rejects_unknown_params() {
+    let app = app().await;
+
+    let (status, _) = send(&app, get("/items?tpye=task")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let req = get("/items?tpye=task&limit=1&strict=true");
+    let res = test::call_service(&app, req.to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body = test::read_body(res).await;
+    assert!(std::str::from_utf8(&body).unwrap().contains("tpye"));
+
+    let app = app_with(Config {
+        strict_query: true,
+        ..test_config()
+    })
+    .await;
+    let (status, _) = send(&app, get("/items?tpye=task")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, get("/items?tpye=task&strict=false")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn deleting_an_item_strips_links_to_it() {
+    let app = app().await;
+    let (_, target) = send(
+        &app,
+        post("/items", json!({"type": "note", "title": "target"})),
+    )
+    .await;
+    let target_id = target["id"].as_str().unwrap();
+    let (_, source) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "note", "title": "source", "links": [target_id, "gone"]}),
+        ),
+    )
+    .await;
+    let source_id = source["id"].as_str().unwrap();
+
+    let (_, orphans) = send(&app, get("/admin/orphan-links")).await;
+    assert_eq!(orphans, json!([{"id": source_id, "dangling": ["gone"]}]));
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/items/{target_id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(res.headers().get("x-links-cleaned").unwrap(), "1");
+
+    let (_, source) = send(&app, get(&format!("/items/{source_id}"))).await;
+    assert_eq!(source["links"], json!(["gone"]));
+}
+
+#[actix_web::test]
+async fn capture_uses_configured_type_tags() {
+    let app = app_with(Config {
+        capture_type_tags: [("meeting".to_string(), ItemType::Event)].into(),
+        ..test_config()
+    })
+    .await;
+
+    let (status, item) = send(
+        &app,
+        post("/items/capture", json!({"text": "Sync #Meeting"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(item["type"], "event");
+
+    let (_, item) = send(&app, post("/items/capture", json!({"text": "Later #todo"}))).await;
+    assert_eq!(item["type"], "note");
+    assert_eq!(item["tags"], json!(["todo"]));
+}
+
+#[actix_web::test]
+async fn batch_create_is_atomic_unless_asked_otherwise() {
+    let app = app().await;
+    let rows = json!([
+        {"type": "note", "title": "ok"},
+        {"type": "note", "title": ""},
+        {"title": "no type"},
+    ]);
+
+    let (status, failures) = send(&app, post("/items/batch", rows.clone())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(failures["details"].as_array().unwrap().len(), 2);
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items, json!([]));
+
+    let (status, outcomes) = send(&app, post("/items/batch?atomic=false", rows)).await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    assert_eq!(outcomes[0]["status"], 201);
+    assert_eq!(outcomes[1]["status"], 422);
+    assert_eq!(outcomes[1]["error"]["details"][0]["field"], "title");
+    assert_eq!(outcomes[2]["status"], 400);
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items[0]["id"], outcomes[0]["id"]);
+
+    let (status, created) = send(
+        &app,
+        post(
+            "/items/batch",
+            json!([{"type": "task", "title": "a", "due_date": 1_000}]),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created[0]["title"], "a");
+}
+
+#[actix_web::test]
+async fn batch_mixes_creates_updates_and_deletes() {
+    let app = app().await;
+    let (_, kept) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "task", "title": "kept", "due_date": 1_000}),
+        ),
+    )
+    .await;
+    let (_, gone) = send(
+        &app,
+        post("/items", json!({"type": "note", "title": "gone"})),
+    )
+    .await;
+    let (kept, gone) = (kept["id"].as_str().unwrap(), gone["id"].as_str().unwrap());
+
+    let rows = json!([
+        {"op": "create", "item": {"type": "note", "title": "new"}},
+        {"op": "update", "id": kept, "changes": {"title": "renamed"}},
+        {"op": "delete", "id": gone},
+        {"op": "delete", "id": "missing"},
+        {"op": "update", "id": kept, "changes": {"title": "twice"}},
+    ]);
+    let (status, failures) = send(&app, post("/items/batch", rows.clone())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(failures["details"][0]["status"], 404);
+    assert_eq!(failures["details"][1]["status"], 409);
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items.as_array().unwrap().len(), 2);
+
+    let (status, outcomes) = send(&app, post("/items/batch?atomic=false", rows)).await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    let statuses: Vec<&Value> = outcomes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|o| &o["status"])
+        .collect();
+    assert_eq!(statuses, [201, 200, 204, 404, 409]);
+    let (_, item) = send(&app, get(&format!("/items/{kept}"))).await;
+    assert_eq!(item["title"], "renamed");
+    let (_, trash) = send(&app, get("/items/trash")).await;
+    assert_eq!(trash[0]["item"]["id"], gone);
+    let (_, audit) = send(&app, get("/admin/audit")).await;
+    assert_eq!(audit.as_array().unwrap().len(), 5);
+
+    let rows = json!([
+        {"op": "create", "item": {"id": "mine", "type": "note", "title": "a"}},
+        {"op": "create", "item": {"id": "mine", "type": "note", "title": "b"}},
+        {"op": "create", "item": {"id": kept, "type": "note", "title": "c"}},
+        {"op": "create", "item": {"id": "a/b", "type": "note", "title": "d"}},
+    ]);
+    let (_, outcomes) = send(&app, post("/items/batch?atomic=false", rows)).await;
+    let statuses: Vec<&Value> = outcomes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|o| &o["status"])
+        .collect();
+    assert_eq!(statuses, [201, 409, 409, 422]);
+    let (_, item) = send(&app, get("/items/mine")).await;
+    assert_eq!(item["title"], "a");
+}
+
+#[actix_web::test]
+async fn batch_rows_honour_if_match_and_child_policies() {
+    let app = app().await;
+    for (id, parent) in [("p", None), ("c", Some("p")), ("g", Some("c")), ("q", None)] {
+        let body =
+            json!({"id": id, "type": "task", "title": id, "parent_id": parent, "due_date": 1_000});
+        send(&app, post("/items", body)).await;
+    }
+    send(
+        &app,
+        test::TestRequest::patch()
+            .uri("/items/q")
+            .insert_header(("X-API-Key", API_KEY))
+            .set_json(json!({"title": "edited"})),
+    )
+    .await;
+
+    let rows = json!([
+        {"op": "update", "id": "q", "changes": {"title": "stale"}, "if_match": "\"1\""},
+        {"op": "delete", "id": "p", "children": "refuse"},
+    ]);
+    let (status, failures) = send(&app, post("/items/batch", rows)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let statuses: Vec<&Value> = failures["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|o| &o["status"])
+        .collect();
+    assert_eq!(statuses, [412, 409]);
+
+    let rows = json!([
+        {"op": "delete", "id": "p", "children": "delete"},
+        {"op": "update", "id": "g", "changes": {"title": "gone"}},
+    ]);
+    let (_, failures) = send(&app, post("/items/batch", rows)).await;
+    assert_eq!(failures["details"][0]["index"], 1);
+    assert_eq!(failures["details"][0]["status"], 409);
+
+    let rows = json!([
+        {"op": "update", "id": "q", "changes": {"title": "fresh"}, "if_match": "\"2\""},
+        {"op": "delete", "id": "p", "children": "delete"},
+    ]);
+    let (status, _) = send(&app, post("/items/batch", rows)).await;
+    assert_eq!(status, StatusCode::OK);
+    for id in ["p", "c", "g"] {
+        let (status, _) = send(&app, get(&format!("/items/{id}"))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    let (_, trash) = send(&app, get("/items/trash")).await;
+    assert_eq!(trash.as_array().unwrap().len(), 3);
+}
+
+#[actix_web::test]
+async fn bulk_update_edits_every_match_after_a_dry_run() {
+    let app = app().await;
+    for body in [
+        json!({"type": "task", "title": "a", "tags": ["inbox"], "due_date": 1_000}),
+        json!({"type": "task", "title": "b", "tags": ["inbox", "done"], "due_date": 1_000}),
+        json!({"type": "note", "title": "c", "tags": ["inbox"]}),
+    ] {
+        send(&app, post("/items", body)).await;
+    }
+    let edit =
+        json!({"add_tags": ["weekly"], "remove_tags": ["inbox"], "set": {"completed": true}});
+
+    let (_, preview) = send(
+        &app,
+        post("/items/bulk-update?type=task&dry_run=true", edit.clone()),
+    )
+    .await;
+    assert_eq!(preview["dry_run"], true);
+    assert_eq!(preview["ids"].as_array().unwrap().len(), 2);
+    let (_, items) = send(&app, get("/items?tags=weekly")).await;
+    assert_eq!(items, json!([]));
+
+    let (status, result) = send(&app, post("/items/bulk-update?type=task", edit.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["ids"], preview["ids"]);
+    let (_, items) = send(&app, get("/items?tags=weekly&completed=true")).await;
+    assert_eq!(items.as_array().unwrap().len(), 2);
+    assert!(items[0]["tags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|t| t != "inbox"));
+
+    let (_, again) = send(&app, post("/items/bulk-update?type=task", edit.clone())).await;
+    assert_eq!(again["ids"], json!([]));
+    let (status, _) = send(&app, post("/items/bulk-update", edit.clone())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, post("/items/bulk-update?typo=task", edit)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn stale_if_match_versions_are_refused() {
+    let app = app().await;
+    let (_, item) = send(&app, post("/items", json!({"type": "note", "title": "v1"}))).await;
+    let id = item["id"].as_str().unwrap();
+    assert_eq!(item["version"], 1);
+    let patch = |if_match: &str, title: &str| {
+        test::TestRequest::patch()
+            .uri(&format!("/items/{id}"))
+            .insert_header(("X-API-Key", API_KEY))
+            .insert_header(("If-Match", if_match.to_string()))
+            .set_json(json!({"title": title}))
+    };
+
+    let res = test::call_service(&app, get(&format!("/items/{id}")).to_request()).await;
+    assert_eq!(res.headers().get("ETag").unwrap(), "\"1\"");
+    let res = test::call_service(&app, patch("\"1\"", "v2").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("ETag").unwrap(), "\"2\"");
+
+    let res = test::call_service(&app, patch("\"1\"", "lost").to_request()).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(res.headers().get("ETag").unwrap(), "\"2\"");
+    let (_, item) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(item["title"], "v2");
+    assert_eq!(item["version"], 2);
+
+    let delete = test::TestRequest::delete()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .insert_header(("If-Match", "\"1\""));
+    let (status, _) = send(&app, delete).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+}
+
+#[actix_web::test]
+async fn retried_creates_with_an_idempotency_key_return_the_first_item() {
+    let app = app().await;
+    let create =
+        |uri: &str, body: Value| post(uri, body).insert_header(("Idempotency-Key", "retry-1"));
+
+    let (status, first) = send(
+        &app,
+        create("/items", json!({"type": "note", "title": "once"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, again) = send(
+        &app,
+        create("/items", json!({"type": "note", "title": "once"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(again["id"], first["id"]);
+    // The key is scoped to the endpoint it was sent to.
+    let (status, captured) = send(&app, create("/items/capture", json!({"text": "once"}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_ne!(captured["id"], first["id"]);
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items.as_array().unwrap().len(), 2);
+
+    // A request that creates nothing leaves the key free for its retry.
+    let retry = |body: Value| post("/items", body).insert_header(("Idempotency-Key", "retry-2"));
+    let (status, _) = send(&app, retry(json!({"type": "task", "title": "undated"}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(&app, retry(json!({"type": "note", "title": "fixed"}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn frequent_view_ranks_by_reads_when_tracking() {
+    let app = app_with(Config {
+        track_access: true,
+        ..test_config()
+    })
+    .await;
+    let (_, a) = send(&app, post("/items", json!({"type": "note", "title": "a"}))).await;
+    let (_, b) = send(&app, post("/items", json!({"type": "note", "title": "b"}))).await;
+    let (a, b) = (a["id"].as_str().unwrap(), b["id"].as_str().unwrap());
+
+    for id in [a, b, b] {
+        send(&app, get(&format!("/items/{id}"))).await;
+    }
+
+    let (_, frequent) = send(&app, get("/items/frequent?limit=1")).await;
+    assert_eq!(frequent.as_array().unwrap().len(), 1);
+    assert_eq!(frequent[0]["id"], b);
+    assert_eq!(frequent[0]["access_count"], 2);
+    assert_eq!(frequent[0]["last_accessed"], NOW);
+}
+
+#[actix_web::test]
+async fn digest_buckets_the_week() {
+    let app = app().await;
+    // NOW is Sunday 2025-06-15, the last day of 2025-W24.
+    for body in [
+        json!({"type": "note", "title": "this week"}),
+        json!({"type": "task", "title": "overdue", "due_date": "2025-06-01"}),
+        json!({"type": "task", "title": "later", "due_date": "2025-07-01"}),
+        json!({"type": "task", "title": "finished", "completed": true, "due_date": "2025-06-01"}),
+        json!({"type": "event", "title": "standup",
+               "start_time": "2025-06-10T09:00:00Z", "end_time": "2025-06-10T09:15:00Z"}),
+    ] {
+        let (status, _) = send(&app, post("/items", body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (status, digest) = send(&app, get("/digest")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(digest["week"], "2025-W24");
+    assert_eq!(digest["notes_created"][0]["title"], "this week");
+    assert_eq!(digest["open_tasks"].as_array().unwrap().len(), 1);
+    assert_eq!(digest["open_tasks"][0]["title"], "overdue");
+    assert_eq!(digest["completed_tasks"][0]["title"], "finished");
+    assert_eq!(digest["events"][0]["title"], "standup");
+
+    let (_, earlier) = send(&app, get("/digest?week=2025-W20")).await;
+    assert_eq!(earlier["notes_created"], json!([]));
+
+    let res = test::call_service(&app, get("/digest?format=md").to_request()).await;
+    let body = test::read_body(res).await;
+    let md = std::str::from_utf8(&body).unwrap();
+    assert!(md.starts_with("# Weekly digest 2025-W24"));
+    assert!(md.contains("- overdue (due 2025-06-01)"));
+}
+
+#[actix_web::test]
+async fn limits_reject_long_titles_and_tag_floods() {
+    let limits = Limits {
+        max_tags: 2,
+        max_title_chars: 10,
+        max_content_bytes: 16,
+    };
+    let app = app_with(Config {
+        limits,
+        ..test_config()
+    })
+    .await;
+
+    let (status, body) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "note", "title": "much too long a title"}),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "limit_exceeded");
+    assert_eq!(body["details"][0]["code"], "title_too_long");
+
+    let long = json!({"type": "note", "title": "t", "content": "x".repeat(17)});
+    let (status, body) = send(&app, post("/items", long)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["details"][0]["code"], "content_too_long");
+    let (_, note) = send(&app, post("/items", json!({"type": "note", "title": "t"}))).await;
+    let uri = format!("/items/{}/append", note["id"].as_str().unwrap());
+    let (status, _) = send(&app, post(&uri, json!({"text": "x".repeat(16)}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = send(&app, post(&uri, json!({"text": "x"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["details"][0]["code"], "content_too_long");
+
+    let capture = json!({"text": "flood #a #b #c"});
+    let (status, body) = send(&app, post("/items/capture", capture.clone())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["details"][0]["code"], "too_many_tags");
+
+    let app = app_with(Config {
+        limits,
+        capture_tag_overflow: TagOverflow::Truncate,
+        ..test_config()
+    })
+    .await;
+    let (status, item) = send(&app, post("/items/capture", capture)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(item["tags"], json!(["a", "b"]));
+}
+
+#[actix_web::test]
+async fn version_is_public() {
+    let app = app().await;
+    let req = test::TestRequest::get().uri("/version");
+    let (status, info) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["commit"].is_string());
+}
+
+#[actix_web::test]
+async fn append_adds_lines_to_content() {
+    let app = app().await;
+    let (_, item) = send(
+        &app,
+        post("/items", json!({"type": "note", "title": "log"})),
+    )
+    .await;
+    let id = item["id"].as_str().unwrap();
+
+    for line in ["first", "second"] {
+        let (status, _) = send(
+            &app,
+            post(&format!("/items/{id}/append"), json!({"text": line})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (_, item) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(item["content"], "first\nsecond");
+    let (_, revisions) = send(&app, get(&format!("/items/{id}/revisions"))).await;
+    assert_eq!(revisions.as_array().unwrap().len(), 2);
+    let (_, first) = send(&app, get(&format!("/items/{id}/revisions/2"))).await;
+    assert_eq!(first["item"]["content"], "first");
+
+    let (status, _) = send(&app, post("/items/nope/append", json!({"text": "x"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn msgpack_storage_is_invisible_to_the_api() {
+    let app = app_with(Config {
+        storage_encoding: Encoding::MessagePack,
+        ..test_config()
+    })
+    .await;
+    let (status, item) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "task", "title": "packed", "tags": ["a"], "due_date": 1_000}),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = item["id"].as_str().unwrap();
+
+    let (_, fetched) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(fetched["title"], "packed");
+    let (_, listed) = send(&app, get("/items?tags=a")).await;
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn create_with_existing_client_id_conflicts() {
+    let app = app().await;
+    let body = json!({"id": "sync-1", "type": "note", "title": "first"});
+    let (status, item) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(item["id"], "sync-1");
+
+    let body = json!({"id": "sync-1", "type": "note", "title": "second"});
+    let (status, _) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, item) = send(&app, get("/items/sync-1")).await;
+    assert_eq!(item["title"], "first");
+    // The refused create leaves no trace in the log or the indexes.
+    let (_, audit) = send(&app, get("/admin/audit?item_id=sync-1")).await;
+    assert_eq!(audit.as_array().unwrap().len(), 1);
+    let (_, found) = send(&app, get("/items/search?q=second")).await;
+    assert_eq!(found, json!([]));
+
+    let body = json!({"id": "a/b", "type": "note", "title": "slash"});
+    let (status, _) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn hierarchical_tags_filter_and_tree() {
+    let app = app().await;
+    for tags in [
+        json!(["project/neonote/backend"]),
+        json!(["project/neonote", "project/neonote/ui"]),
+        json!(["project/other"]),
+    ] {
+        send(
+            &app,
+            post(
+                "/items",
+                json!({"type": "note", "title": "t", "tags": tags}),
+            ),
+        )
+        .await;
+    }
+
+    let (_, items) = send(&app, get("/items?tags=project/neonote")).await;
+    assert_eq!(items.as_array().unwrap().len(), 2);
+    let (_, items) = send(&app, get("/items?tags=project/neonote&hierarchical=false")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+
+    let capture = json!({"text": "Fix login #project/neonote/backend/"});
+    let (_, item) = send(&app, post("/items/capture", capture)).await;
+    assert_eq!(item["tags"], json!(["project/neonote/backend"]));
+
+    let (status, tree) = send(&app, get("/tags/tree")).await;
+    assert_eq!(status, StatusCode::OK);
+    let project = &tree[0];
+    assert_eq!(
+        (project["name"].as_str(), project["total"].as_u64()),
+        (Some("project"), Some(4))
+    );
+    let neonote = &project["children"][0];
+    assert_eq!(neonote["path"], "project/neonote");
+    assert_eq!(
+        (neonote["count"].as_u64(), neonote["total"].as_u64()),
+        (Some(1), Some(3))
+    );
+    assert_eq!(neonote["children"].as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn fields_projects_items() {
+    let app = app().await;
+    let body = json!({"type": "note", "title": "slim", "content": "long", "tags": ["x"]});
+    let (_, item) = send(&app, post("/items", body)).await;
+    let id = item["id"].as_str().unwrap();
+
+    let (_, items) = send(&app, get("/items?fields=title,bogus")).await;
+    let keys: Vec<&String> = items[0].as_object().unwrap().keys().collect();
+    assert_eq!(keys, ["id", "title"]);
+
+    let (status, item) = send(&app, get(&format!("/items/{id}?fields=tags"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(item, json!({"id": id, "tags": ["x"]}));
+
+    let (status, _) = send(&app, get("/items?fields=title,bogus&strict=true")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn parent_reports_progress_of_children() {
+    let app = app().await;
+    let body = json!({"type": "task", "title": "checklist", "completed": false, "due_date": 1_000});
+    let (_, parent) = send(&app, post("/items", body)).await;
+    let parent_id = parent["id"].as_str().unwrap();
+
+    let mut child = Value::Null;
+    for completed in [true, false, true] {
+        let body = json!({
+            "type": "task", "title": "step", "completed": completed, "parent_id": parent_id,
+            "due_date": 1_000,
+        });
+        (_, child) = send(&app, post("/items", body)).await;
+    }
+
+    let (_, item) = send(&app, get(&format!("/items/{parent_id}"))).await;
+    assert_eq!(item["progress"], json!({"done": 2, "total": 3}));
+    assert_eq!(item["title"], "checklist");
+
+    let (_, leaf) = send(
+        &app,
+        get(&format!("/items/{}", child["id"].as_str().unwrap())),
+    )
+    .await;
+    assert_eq!(leaf["parent_id"], parent_id);
+    assert!(leaf.get("progress").is_none());
+}
+
+#[actix_web::test]
+async fn overdue_counts_days_in_the_requested_zone() {
+    let app = app().await;
+    for (title, due) in [
+        ("last week", "2025-06-08T12:00:00Z"),
+        ("this morning", "2025-06-15T09:00:00Z"),
+    ] {
+        let body = json!({"type": "task", "title": title, "completed": false, "due_date": due});
+        send(&app, post("/items", body)).await;
+    }
+
+    let (_, items) = send(&app, get("/items/overdue")).await;
+    let titles: Vec<&str> = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["last week"]);
+
+    // Already the 16th in Kiritimati (UTC+14), so this morning's task is late.
+    let (_, items) = send(&app, get("/items/overdue?tz=Pacific/Kiritimati")).await;
+    assert_eq!(items.as_array().unwrap().len(), 2);
+
+    let (status, _) = send(&app, get("/items/overdue?tz=Nowhere/Special")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn coalesced_appends_are_written_together() {
+    let app = app_with(Config {
+        append_coalesce_ms: Some(20),
+        append_flush_bytes: 16,
+        ..test_config()
+    })
+    .await;
+    let (_, item) = send(
+        &app,
+        post("/items", json!({"type": "note", "title": "journal"})),
+    )
+    .await;
+    let id = item["id"].as_str().unwrap();
+    let uri = format!("/items/{id}/append");
+
+    let (status, body) = send(&app, post(&uri, json!({"text": "one"}))).await;
+    assert_eq!(
+        (status, body["buffered"].as_u64()),
+        (StatusCode::ACCEPTED, Some(1))
+    );
+    let (_, body) = send(&app, post(&uri, json!({"text": "two"}))).await;
+    assert_eq!(body["buffered"], 2);
+
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    let (_, item) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(item["content"], "one\ntwo");
+
+    // Past the flush threshold the write happens in the request itself.
+    let (status, item) = send(&app, post(&uri, json!({"text": "a long enough line"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(item["content"], "one\ntwo\na long enough line");
+
+    let (status, _) = send(&app, post("/items/missing/append", json!({"text": "x"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn cursor_paging_resumes_after_the_last_id() {
+    let app = app().await;
+    for id in ["a", "b", "c", "d", "e"] {
+        send(
+            &app,
+            post("/items", json!({"id": id, "type": "note", "title": id})),
+        )
+        .await;
+    }
+
+    let mut seen = Vec::new();
+    let mut uri = "/items/page?limit=2".to_string();
+    loop {
+        let (status, page) = send(&app, get(&uri)).await;
+        assert_eq!(status, StatusCode::OK);
+        for item in page["items"].as_array().unwrap() {
+            seen.push(item["id"].as_str().unwrap().to_string());
+        }
+        match page["next_after"].as_str() {
+            Some(after) => uri = format!("/items/page?limit=2&after={after}"),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ["a", "b", "c", "d", "e"]);
+}
+
+#[actix_web::test]
+async fn create_from_template_lets_the_body_override() {
+    let app = app().await;
+    let template = json!({
+        "type": "note",
+        "title_pattern": "Meeting {date}",
+        "content": "## Attendees\n\n## Actions",
+        "tags": ["meeting"],
+    });
+    let req = test::TestRequest::put()
+        .uri("/templates/meeting")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(template);
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, item) = send(&app, post("/items?template=meeting", json!({}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(item["title"], "Meeting 2025-06-15");
+    assert_eq!(item["tags"], json!(["meeting"]));
+
+    let body = json!({"title": "Retro", "tags": ["retro"]});
+    let (_, item) = send(&app, post("/items?template=meeting", body)).await;
+    assert_eq!(item["title"], "Retro");
+    assert_eq!(item["tags"], json!(["retro"]));
+    assert_eq!(item["content"], "## Attendees\n\n## Actions");
+
+    let (status, _) = send(&app, post("/items?template=nope", json!({}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn aggregates_are_well_formed_on_an_empty_store() {
+    let app = app().await;
+    for uri in [
+        "/items",
+        "/tags",
+        "/tags/tree",
+        "/types",
+        "/items/recent",
+        "/items/inbox",
+        "/items/overdue",
+        "/items/frequent",
+        "/items/search?q=x",
+        "/items/autocomplete?prefix=x",
+        "/admin/orphan-links",
+    ] {
+        let (status, body) = send(&app, get(uri)).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert_eq!(body, json!([]), "{uri}");
+    }
+
+    let (_, digest) = send(&app, get("/digest")).await;
+    for list in ["completed_tasks", "open_tasks", "events", "notes_created"] {
+        assert_eq!(digest[list], json!([]), "{list}");
+    }
+    let (_, page) = send(&app, get("/items/page")).await;
+    assert_eq!(page, json!({"items": [], "next_after": null}));
+    let (_, map) = send(&app, get("/items?shape=map")).await;
+    assert_eq!(map, json!({}));
+    let (_, envelope) = send(&app, get("/items?envelope=true")).await;
+    assert_eq!(envelope["total"], 0);
+    let (_, info) = send(&app, get("/admin/info")).await;
+    assert_eq!(info["item_count"], 0);
+}
+
+#[actix_web::test]
+async fn hashed_capture_ids_make_recaptures_return_the_item() {
+    let app = app_with(Config {
+        capture_id: CaptureId::Hash,
+        ..test_config()
+    })
+    .await;
+    let (status, first) = send(
+        &app,
+        post("/items/capture", json!({"text": "buy milk #todo"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = first["id"].as_str().unwrap();
+
+    let patch = test::TestRequest::patch()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"completed": true, "title": "buy oat milk", "due_date": 1_000}));
+    send(&app, patch).await;
+
+    let (status, again) = send(
+        &app,
+        post("/items/capture", json!({"text": "buy  milk #todo\n"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(again["id"], id);
+    assert_eq!(again["completed"], true);
+    assert_eq!(again["title"], "buy oat milk");
+    assert_eq!(again["version"], 2);
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    let (_, revisions) = send(&app, get(&format!("/items/{id}/revisions"))).await;
+    assert_eq!(revisions.as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn due_soon_returns_open_tasks_inside_the_window() {
+    let app = app().await;
+    for (title, due, completed) in [
+        ("tomorrow", "2025-06-16T10:00:00Z", false),
+        ("tonight", "2025-06-15T20:00:00Z", false),
+        ("next week", "2025-06-22T10:00:00Z", false),
+        ("done", "2025-06-15T21:00:00Z", true),
+        ("late", "2025-06-15T08:00:00Z", false),
+    ] {
+        let body = json!({"type": "task", "title": title, "completed": completed, "due_date": due});
+        send(&app, post("/items", body)).await;
+    }
+
+    let titles = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let (_, items) = send(&app, get("/items/due-soon?within=2d")).await;
+    assert_eq!(titles(items), ["tonight", "tomorrow"]);
+    let (_, items) = send(&app, get("/items/due-soon?within=12h")).await;
+    assert_eq!(titles(items), ["tonight"]);
+
+    let (status, _) = send(&app, get("/items/due-soon?within=soon")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn audit_log_records_each_write_with_its_key() {
+    let app = app_with(Config {
+        tenant_keys: vec![("alice".into(), "alice-key".into())],
+        ..test_config()
+    })
+    .await;
+    let as_alice = |req: test::TestRequest| req.insert_header(("X-API-Key", "alice-key"));
+
+    let (_, item) = send(
+        &app,
+        as_alice(post("/items", json!({"type": "note", "title": "Audited"}))),
+    )
+    .await;
+    let id = item["id"].as_str().unwrap();
+    send(
+        &app,
+        as_alice(post("/items", json!({"type": "note", "title": "Other"}))),
+    )
+    .await;
+    let req = test::TestRequest::patch()
+        .uri(&format!("/items/{id}"))
+        .set_json(json!({"title": "Renamed"}));
+    send(&app, as_alice(req)).await;
+    let req = test::TestRequest::delete().uri(&format!("/items/{id}"));
+    let (status, _) = send(&app, as_alice(req)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, entries) = send(&app, as_alice(get(&format!("/admin/audit?item_id={id}")))).await;
+    assert_eq!(status, StatusCode::OK);
+    let actions: Vec<&str> = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["create", "update", "delete"]);
+    assert_eq!(entries[0]["api_key_label"], "alice");
+    assert_eq!(entries[0]["timestamp"], NOW);
+
+    let (_, entries) = send(
+        &app,
+        as_alice(get("/admin/audit?from=2025-06-15T15:06:40Z")),
+    )
+    .await;
+    assert_eq!(entries.as_array().unwrap().len(), 4);
+    let (_, entries) = send(&app, as_alice(get(&format!("/admin/audit?to={}", NOW - 1)))).await;
+    assert_eq!(entries, json!([]));
+    let (_, entries) = send(&app, get("/admin/audit")).await;
+    assert_eq!(entries, json!([]));
+    let (status, _) = send(&app, get("/admin/audit?from=yesterday")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn get_many_returns_found_items_and_missing_ids() {
+    let app = app().await;
+    let mut ids = Vec::new();
+    for title in ["one", "two"] {
+        let (_, item) = send(
+            &app,
+            post("/items", json!({"type": "note", "title": title})),
+        )
+        .await;
+        ids.push(item["id"].as_str().unwrap().to_string());
+    }
+
+    let body = json!({"ids": [ids[1], "nope", ids[0]]});
+    let (status, found) = send(&app, post("/items/get-many", body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(found["items"][0]["title"], "two");
+    assert_eq!(found["items"][1]["title"], "one");
+    assert_eq!(found["missing"], json!(["nope"]));
+
+    let too_many: Vec<String> = (0..101).map(|i| i.to_string()).collect();
+    let (status, _) = send(&app, post("/items/get-many", json!({"ids": too_many}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn equal_sort_keys_fall_back_to_id_order() {
+    let app = app().await;
+    for id in ["c", "a", "b"] {
+        let body =
+            json!({"id": id, "type": "task", "title": id, "due_date": "2025-06-15T20:00:00Z"});
+        send(&app, post("/items", body)).await;
+    }
+
+    let (_, items) = send(&app, get("/items/due-soon")).await;
+    let ids: Vec<&str> = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, ["a", "b", "c"]);
+}
+
+#[actix_web::test]
+async fn search_index_follows_writes() {
+    let app = app().await;
+    let (_, item) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "note", "title": "Sled internals", "content": "Pagecache notes", "tags": ["db/storage"]}),
+        ),
+    )
+    .await;
+    let id = item["id"].as_str().unwrap();
+    send(
+        &app,
+        post("/items", json!({"type": "task", "title": "Buy milk"})),
+    )
+    .await;
+
+    let titles = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let (_, items) = send(&app, get("/items/search?q=page+SLED")).await;
+    assert_eq!(titles(items), ["Sled internals"]);
+    let (_, items) = send(&app, get("/items/search?q=storage&type=task")).await;
+    assert_eq!(titles(items), Vec::<String>::new());
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"title": "Tree internals"}));
+    send(&app, req).await;
+    let (_, items) = send(&app, get("/items/search?q=sled")).await;
+    assert_eq!(titles(items), Vec::<String>::new());
+    send(
+        &app,
+        post(&format!("/items/{id}/append"), json!({"text": "Flushing"})),
+    )
+    .await;
+    let (_, items) = send(&app, get("/items/search?q=flush")).await;
+    assert_eq!(titles(items), ["Tree internals"]);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY));
+    send(&app, req).await;
+    let (_, items) = send(&app, get("/items/search?q=tree")).await;
+    assert_eq!(titles(items), Vec::<String>::new());
+}
+
+#[actix_web::test]
+async fn lists_sort_and_page_deterministically() {
+    let app = app().await;
+    for (id, title, due) in [
+        ("a", "beta", Some("2025-06-20T00:00:00Z")),
+        ("b", "Alpha", None),
+        ("c", "gamma", Some("2025-06-18T00:00:00Z")),
+        ("d", "delta", Some("2025-06-20T00:00:00Z")),
+    ] {
+        let item_type = if due.is_some() { "task" } else { "note" };
+        let body = json!({"id": id, "type": item_type, "title": title, "due_date": due});
+        send(&app, post("/items", body)).await;
+    }
+
+    let ids = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let (_, items) = send(&app, get("/items?sort=title")).await;
+    assert_eq!(ids(items), ["b", "a", "d", "c"]);
+    let (_, items) = send(&app, get("/items?sort=due_date")).await;
+    assert_eq!(ids(items), ["c", "a", "d", "b"]);
+    let (_, items) = send(&app, get("/items?sort=due_date&order=desc")).await;
+    assert_eq!(ids(items), ["a", "d", "c", "b"]);
+    let (_, items) = send(
+        &app,
+        get("/items?sort=due_date&order=desc&offset=1&limit=2"),
+    )
+    .await;
+    assert_eq!(ids(items), ["d", "c"]);
+
+    for uri in [
+        "/items?sort=importance",
+        "/items?order=desc",
+        "/items?sort=title&order=up",
+    ] {
+        let (status, _) = send(&app, get(uri)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+    }
+}
+
+#[actix_web::test]
+async fn priorities_filter_and_sort() {
+    let app = app().await;
+    for (id, priority) in [("a", json!(3)), ("b", json!("P1")), ("c", Value::Null)] {
+        let body =
+            json!({"id": id, "type": "task", "title": id, "priority": priority, "due_date": 1_000});
+        let (status, item) = send(&app, post("/items", body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(
+            item["priority"],
+            if id == "b" { json!(1) } else { priority }
+        );
+    }
+    let (_, item) = send(
+        &app,
+        post("/items/capture", json!({"text": "Call back !p2 #todo"})),
+    )
+    .await;
+    assert_eq!(
+        (item["title"].as_str(), item["priority"].as_u64()),
+        (Some("Call back"), Some(2))
+    );
+    let captured = item["id"].as_str().unwrap().to_string();
+
+    let ids = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let (_, items) = send(&app, get("/items?sort=priority")).await;
+    assert_eq!(ids(items), ["b", captured.as_str(), "a", "c"]);
+    let (_, items) = send(&app, get("/items?sort=priority&order=desc")).await;
+    assert_eq!(ids(items), ["a", captured.as_str(), "b", "c"]);
+    let (_, items) = send(&app, get("/items?priority=p1,3&sort=priority")).await;
+    assert_eq!(ids(items), ["b", "a"]);
+
+    let (status, _) = send(&app, get("/items?priority=urgent")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let body = json!({"type": "task", "title": "t", "priority": 7});
+    let (status, body) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "priority");
+
+    let req = test::TestRequest::patch()
+        .uri("/items/a")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"priority": null}));
+    let (_, item) = send(&app, req).await;
+    assert_eq!(item["priority"], Value::Null);
+}
+
+#[actix_web::test]
+async fn completing_a_blocker_unblocks_its_dependents() {
+    let app = app().await;
+    for (id, blocked_by) in [("a", json!([])), ("b", json!(["a"])), ("c", json!(null))] {
+        let body = json!({
+            "id": id, "type": "task", "title": id, "blocked_by": blocked_by, "due_date": 1_000,
+        });
+        let (status, _) = send(&app, post("/items", body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let ids = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let (_, items) = send(&app, get("/items?blocked=false")).await;
+    assert_eq!(ids(items), ["a", "c"]);
+    let (_, items) = send(&app, get("/items?blocked=true")).await;
+    assert_eq!(ids(items), ["b"]);
+
+    let body = json!({"type": "task", "title": "t", "blocked_by": ["missing"], "due_date": 1_000});
+    let (status, body) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "blocked_by");
+    let cycle = test::TestRequest::patch()
+        .uri("/items/a")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"blocked_by": ["b"]}));
+    let (status, _) = send(&app, cycle).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = send(&app, post("/items/a/complete", json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, item) = send(&app, get("/items/b")).await;
+    assert_eq!(item["blocked_by"], json!([]));
+    let (_, items) = send(&app, get("/items?blocked=true")).await;
+    assert_eq!(items, json!([]));
+}
+
+#[actix_web::test]
+async fn subtasks_list_roll_up_and_cascade() {
+    let app = app().await;
+    for (id, parent) in [
+        ("p", None),
+        ("c1", Some("p")),
+        ("c2", Some("p")),
+        ("g", Some("c1")),
+    ] {
+        let body =
+            json!({"id": id, "type": "task", "title": id, "parent_id": parent, "due_date": 1_000});
+        let (status, _) = send(&app, post("/items", body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    send(&app, post("/items/c2/complete", json!({}))).await;
+
+    let (_, children) = send(&app, get("/items/p/children")).await;
+    assert_eq!(children[0]["id"], "c1");
+    assert_eq!(children[0]["progress"], json!({"done": 0, "total": 1}));
+    assert_eq!(children[1]["id"], "c2");
+    let (_, parent) = send(&app, get("/items/p")).await;
+    assert_eq!(parent["progress"], json!({"done": 1, "total": 2}));
+
+    let body = json!({"type": "task", "title": "t", "parent_id": "missing", "due_date": 1_000});
+    let (status, _) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let cycle = test::TestRequest::patch()
+        .uri("/items/p")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"parent_id": "g"}));
+    let (status, body) = send(&app, cycle).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "parent_id");
+
+    let delete = |uri: &str| {
+        test::TestRequest::delete()
+            .uri(uri)
+            .insert_header(("X-API-Key", API_KEY))
+    };
+    let (status, _) = send(&app, delete("/items/p?children=refuse")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(&app, delete("/items/c1?children=detach")).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, orphan) = send(&app, get("/items/g")).await;
+    assert_eq!(orphan["parent_id"], Value::Null);
+    let (status, _) = send(&app, delete("/items/p?children=delete")).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, get("/items/c2")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, get("/items/g")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn subtasks_of_a_trashed_parent_stay_editable() {
+    let app = app().await;
+    for (id, parent) in [("p", None), ("c", Some("p"))] {
+        let body =
+            json!({"id": id, "type": "task", "title": id, "parent_id": parent, "due_date": 1_000});
+        send(&app, post("/items", body)).await;
+    }
+    let req = test::TestRequest::delete()
+        .uri("/items/p")
+        .insert_header(("X-API-Key", API_KEY));
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let edit = test::TestRequest::patch()
+        .uri("/items/c")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"title": "still here"}));
+    let (status, child) = send(&app, edit).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(child["parent_id"], "p");
+
+    let (status, _) = send(&app, post("/items/p/restore", json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, children) = send(&app, get("/items/p/children")).await;
+    assert_eq!(children[0]["title"], "still here");
+}
+
+#[actix_web::test]
+async fn board_groups_items_by_status() {
+    let app = app().await;
+    let (_, web) = send(&app, post("/projects", json!({"name": "Web"}))).await;
+    let (_, other) = send(&app, post("/projects", json!({"name": "Other"}))).await;
+    for (id, status, project) in [
+        ("a", json!("In-Progress"), &web),
+        ("b", Value::Null, &web),
+        ("c", json!("done"), &web),
+        ("d", json!("blocked"), &other),
+    ] {
+        let body = json!({
+            "id": id, "type": "task", "title": id, "status": status, "project_id": project["id"],
+            "due_date": 1_000,
+        });
+        let (status, item) = send(&app, post("/items", body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        if id == "c" {
+            assert_eq!(item["completed"], true);
+        }
+    }
+    let body = json!({"type": "task", "title": "t", "status": "someday", "due_date": 1_000});
+    let (status, body) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "status");
+
+    let uri = format!("/board?project={}", web["id"].as_str().unwrap());
+    let (status, board) = send(&app, get(&uri)).await;
+    assert_eq!(status, StatusCode::OK);
+    let columns: Vec<(String, Vec<String>)> = board
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|column| {
+            let ids = column["items"].as_array().unwrap().iter();
+            let ids = ids.map(|i| i["id"].as_str().unwrap().to_string());
+            (
+                column["status"].as_str().unwrap().to_string(),
+                ids.collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        columns,
+        [
+            ("backlog".to_string(), vec!["b".to_string()]),
+            ("in-progress".to_string(), vec!["a".to_string()]),
+            ("blocked".to_string(), vec![]),
+            ("done".to_string(), vec!["c".to_string()]),
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn moving_items_orders_them_by_rank() {
+    let app = app().await;
+    for id in ["a", "b", "c", "d"] {
+        send(
+            &app,
+            post(
+                "/items",
+                json!({"id": id, "type": "task", "title": id, "due_date": 1_000}),
+            ),
+        )
+        .await;
+    }
+    for (id, body) in [
+        ("b", json!({"before": "a"})),
+        ("c", json!({"after": "b"})),
+        ("d", json!({"before": "b"})),
+        ("a", json!({"after": "d"})),
+    ] {
+        let (status, item) = send(&app, post(&format!("/items/{id}/move"), body)).await;
+        assert_eq!(status, StatusCode::OK, "{id}");
+        assert!(item["rank"].is_string());
+    }
+    let (_, items) = send(&app, get("/items?sort=rank")).await;
+    let ids: Vec<&str> = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, ["d", "a", "b", "c"]);
+
+    let body = json!({"before": "a", "after": "b"});
+    let (status, _) = send(&app, post("/items/c/move", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(&app, post("/items/c/move", json!({"before": "missing"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn next_occurrences_start_unranked() {
+    let app = app().await;
+    let body = json!({"id": "r", "type": "task", "title": "r",
+                      "due_date": "2025-06-16T09:00:00Z", "recurrence": "FREQ=DAILY"});
+    send(&app, post("/items", body)).await;
+    send(
+        &app,
+        post(
+            "/items",
+            json!({"id": "x", "type": "task", "title": "x", "due_date": 1_000}),
+        ),
+    )
+    .await;
+    send(&app, post("/items/r/move", json!({"before": "x"}))).await;
+    let res = test::call_service(&app, post("/items/r/complete", json!({})).to_request()).await;
+    let next_uri = res
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let (_, next) = send(&app, get(&next_uri)).await;
+    assert_eq!(next["rank"], Value::Null);
+    let next_id = next["id"].as_str().unwrap();
+
+    send(
+        &app,
+        post(
+            "/items",
+            json!({"id": "y", "type": "task", "title": "y", "due_date": 1_000}),
+        ),
+    )
+    .await;
+    for (id, body) in [
+        ("y", json!({"after": "r"})),
+        (next_id, json!({"after": "y"})),
+    ] {
+        let (status, _) = send(&app, post(&format!("/items/{id}/move"), body)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (_, items) = send(&app, get("/items?sort=rank")).await;
+    let ids: Vec<&str> = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, ["r", "y", next_id, "x"]);
+}
+
+#[actix_web::test]
+async fn projects_group_items_and_report_stats() {
+    let app = app().await;
+    let (status, project) = send(&app, post("/projects", json!({"name": " Home "}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(project["name"], "Home");
+    let id = project["id"].as_str().unwrap().to_string();
+
+    let later = NOW + 24 * 60 * 60 * 1000;
+    for (item, due) in [("a", NOW - 1), ("b", later), ("c", later)] {
+        let body =
+            json!({"id": item, "type": "task", "title": item, "due_date": due, "project_id": id});
+        let (status, _) = send(&app, post("/items", body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    send(&app, post("/items/c/complete", json!({}))).await;
+    send(
+        &app,
+        post("/items", json!({"type": "note", "title": "elsewhere"})),
+    )
+    .await;
+    let body = json!({"type": "note", "title": "t", "project_id": "missing"});
+    let (status, body) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "project_id");
+
+    let (_, items) = send(&app, get(&format!("/projects/{id}/items?completed=false"))).await;
+    assert_eq!(items.as_array().unwrap().len(), 2);
+    let (_, stats) = send(&app, get(&format!("/projects/{id}/stats"))).await;
+    assert_eq!(
+        stats,
+        json!({"total": 3, "open": 2, "completed": 1, "overdue": 1, "by_type": {"task": 3}})
+    );
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/projects/{id}"))
+        .insert_header(("X-API-Key", API_KEY));
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, item) = send(&app, get("/items/a")).await;
+    assert_eq!(item["project_id"], Value::Null);
+    let (status, _) = send(&app, get(&format!("/projects/{id}"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn daily_notes_are_made_once_per_day() {
+    let app = app().await;
+    let (status, note) = send(&app, get("/daily/today")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        (note["id"].as_str(), note["title"].as_str()),
+        (Some("daily-2025-06-15"), Some("2025-06-15"))
+    );
+    assert_eq!(note["tags"], json!(["daily"]));
+
+    let (_, again) = send(&app, get("/daily/2025-06-15")).await;
+    assert_eq!(again["version"], note["version"]);
+    let (_, note) = send(&app, post("/daily/today/append", json!({"text": "ran 5k"}))).await;
+    assert_eq!(note["content"], "ran 5k");
+    let (_, items) = send(&app, get("/items?tags=daily")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+
+    let template = json!({"type": "note", "title_pattern": "Journal {date}", "tags": ["journal"]});
+    let req = test::TestRequest::put()
+        .uri("/templates/daily")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(template);
+    send(&app, req).await;
+    let (_, note) = send(&app, get("/daily/2025-06-16")).await;
+    assert_eq!(note["title"], "Journal 2025-06-16");
+    let (status, _) = send(&app, get("/daily/yesterday")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn agenda_groups_items_by_day() {
+    let app = app().await;
+    for body in [
+        json!({"type": "task", "title": "late", "due_date": "2025-06-10T09:00:00Z"}),
+        json!({"type": "task", "title": "stretch", "due_date": "2025-06-16T07:00:00Z",
+               "recurrence": "FREQ=DAILY"}),
+        json!({"type": "event", "title": "night shift",
+               "start_time": "2025-06-17T22:00:00Z", "end_time": "2025-06-18T02:00:00Z"}),
+    ] {
+        send(&app, post("/items", body)).await;
+    }
+
+    let (status, agenda) = send(&app, get("/agenda")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agenda["overdue"][0]["title"], "late");
+    let days = agenda["days"].as_array().unwrap();
+    assert_eq!(days.len(), 7);
+    assert_eq!(days[0]["date"], "2025-06-15");
+    assert_eq!(days[0]["entries"], json!([]));
+    let titles = |day: &Value| -> Vec<(String, String)> {
+        day["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| {
+                (
+                    e["kind"].as_str().unwrap().into(),
+                    e["item"]["title"].as_str().unwrap().into(),
+                )
+            })
+            .collect()
+    };
+    let stretch = ("due".to_string(), "stretch".to_string());
+    let shift = ("event".to_string(), "night shift".to_string());
+    assert_eq!(titles(&days[1]), vec![stretch.clone()]);
+    assert_eq!(titles(&days[2]), vec![stretch.clone(), shift.clone()]);
+    // Carried on from the day before, the shift comes first.
+    assert_eq!(titles(&days[3]), vec![shift, stretch.clone()]);
+    assert_eq!(titles(&days[6]), vec![stretch]);
+
+    let (_, later) = send(&app, get("/agenda?from=2025-06-20&to=2025-06-20")).await;
+    assert_eq!(later["overdue"], json!([]));
+    assert_eq!(later["days"][0]["entries"][0]["at"], 1_750_402_800_000_i64);
+    let (status, _) = send(&app, get("/agenda?from=2025-06-20&to=2025-06-19")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    for uri in [
+        "/agenda?from=%2B262142-12-28",
+        "/agenda?from=%2B262142-12-30&to=%2B262142-12-31",
+        "/calendar/262142/12",
+    ] {
+        let (status, _) = send(&app, get(uri)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+    }
+}
+
+#[actix_web::test]
+async fn calendar_lays_out_a_month() {
+    let app = app().await;
+    for body in [
+        json!({"type": "task", "title": "rent", "due_date": "2025-07-01T09:00:00Z"}),
+        json!({"type": "event", "title": "trip",
+               "start_time": "2025-06-29T10:00:00Z", "end_time": "2025-07-02T18:00:00Z"}),
+    ] {
+        send(&app, post("/items", body)).await;
+    }
+
+    let (status, june) = send(&app, get("/calendar/2025/6")).await;
+    assert_eq!(status, StatusCode::OK);
+    let days = june["days"].as_array().unwrap();
+    assert_eq!(days.len(), 30);
+    assert_eq!(days[28]["entries"][0]["item"]["title"], "trip");
+    assert_eq!(days[29]["entries"][0]["kind"], "event");
+
+    let (_, july) = send(&app, get("/calendar/2025/7")).await;
+    let days = july["days"].as_array().unwrap();
+    assert_eq!((days.len(), july["month"].as_u64()), (31, Some(7)));
+    assert_eq!(days[0]["entries"][0]["item"]["title"], "trip");
+    assert_eq!(days[0]["entries"][1]["item"]["title"], "rent");
+    assert_eq!(days[1]["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(days[2]["entries"], json!([]));
+
+    let (status, _) = send(&app, get("/calendar/2025/13")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn validate_checks_references_like_a_write() {
+    let app = app().await;
+    let body = json!({"type": "note", "title": "x", "parent_id": "nope", "project_id": "nope"});
+    let (status, report) = send(&app, post("/items/validate", body.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["valid"], false);
+    let fields: Vec<&Value> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| &e["field"])
+        .collect();
+    assert_eq!(fields, ["parent_id", "project_id"]);
+    let (status, _) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn types_require_their_dates() {
+    let app = app().await;
+    let fields = |body: &Value| -> Vec<String> {
+        body["details"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["field"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let (status, body) = send(&app, post("/items", json!({"type": "task", "title": "t"}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(fields(&body), ["due_date"]);
+    let event = json!({"type": "event", "title": "e", "start_time": 1_000});
+    let (status, body) = send(&app, post("/items", event)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(fields(&body), ["end_time"]);
+    let (status, body) = send(&app, post("/items", json!({"type": "event", "title": "e"}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(fields(&body), ["start_time", "end_time"]);
+
+    let (_, schema) = send(&app, get("/schema/task")).await;
+    assert_eq!(schema["required"], json!(["type", "title", "due_date"]));
+
+    let (_, note) = send(&app, post("/items", json!({"type": "note", "title": "n"}))).await;
+    let uri = format!("/items/{}/convert", note["id"].as_str().unwrap());
+    let (status, _) = send(&app, post(&uri, json!({"to": "task"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, task) = send(&app, post(&uri, json!({"to": "task", "due_date": 1_000}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(task["due_date"], 1_000);
+}
+
+#[actix_web::test]
+async fn validate_prepares_creates_like_a_write() {
+    let app = app().await;
+    let template = json!({"type": "note", "title_pattern": "Notes {date}", "tags": ["log"]});
+    let req = test::TestRequest::put()
+        .uri("/templates/log")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(template);
+    send(&app, req).await;
+    send(
+        &app,
+        post(
+            "/items",
+            json!({"id": "taken", "type": "note", "title": "t"}),
+        ),
+    )
+    .await;
+
+    let (_, report) = send(&app, post("/items/validate?template=log", json!({}))).await;
+    assert_eq!(report["valid"], true);
+    assert_eq!(report["normalized"]["title"], "Notes 2025-06-15");
+    assert_eq!(report["normalized"]["tags"], json!(["log"]));
+
+    for id in ["taken", "a/b"] {
+        let body = json!({"id": id, "type": "note", "title": "t"});
+        let (_, report) = send(&app, post("/items/validate", body.clone())).await;
+        assert_eq!(report["valid"], false, "{id}");
+        assert_eq!(report["errors"][0]["field"], "id", "{id}");
+        let (status, _) = send(&app, post("/items", body)).await;
+        assert!(status.is_client_error(), "{id}");
+    }
+}
+
+#[actix_web::test]
+async fn filters_by_modification_time() {
+    let app = app().await;
+    send(&app, post("/items", json!({"type": "note", "title": "n"}))).await;
+
+    let count = |items: Value| items.as_array().unwrap().len();
+    for (uri, expected) in [
+        (format!("/items?updated_after={}", NOW - 1), 1),
+        (format!("/items?updated_after={NOW}"), 0),
+        (format!("/items?updated_before={NOW}"), 0),
+        ("/items?updated_before=2025-06-16".to_string(), 1),
+        ("/items?updated_after=2025-06-15T15:06:39Z".to_string(), 1),
+    ] {
+        let (status, items) = send(&app, get(&uri)).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert_eq!(count(items), expected, "{uri}");
+    }
+
+    let (status, _) = send(&app, get("/items?updated_after=lastweek")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn filters_by_date_windows_and_completion() {
+    let app = app().await;
+    for body in [
+        json!({"type": "task", "title": "soon", "due_date": NOW + 10}),
+        json!({"type": "task", "title": "later", "due_date": NOW + 100}),
+        json!({"type": "event", "title": "meeting", "start_time": NOW + 50, "end_time": NOW + 60}),
+    ] {
+        send(&app, post("/items", body)).await;
+    }
+
+    let titles = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+    for (uri, expected) in [
+        (format!("/items?due_before={}", NOW + 50), vec!["soon"]),
+        (format!("/items?due_after={}", NOW + 10), vec!["later"]),
+        (
+            format!("/items?start_between={},{}", NOW, NOW + 50),
+            vec!["meeting"],
+        ),
+        (
+            format!("/items?start_between={},{}", NOW + 51, NOW + 99),
+            vec![],
+        ),
+        (format!("/items?created_before={NOW}"), vec![]),
+        (
+            format!("/items?created_after={}&type=event", NOW - 1),
+            vec!["meeting"],
+        ),
+    ] {
+        let (status, items) = send(&app, get(&uri)).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert_eq!(titles(items), expected, "{uri}");
+    }
+
+    let done = json!({"type": "task", "title": "done", "due_date": NOW - 5, "completed": true});
+    send(&app, post("/items", done)).await;
+    let late = json!({"type": "task", "title": "late", "due_date": NOW - 5});
+    send(&app, post("/items", late)).await;
+    for (uri, expected) in [
+        ("/items?overdue=true", vec!["late"]),
+        ("/items?completed=true", vec!["done"]),
+        (
+            "/items?type=task&completed=false&overdue=false&sort=due_date",
+            vec!["soon", "later"],
+        ),
+    ] {
+        let (_, items) = send(&app, get(uri)).await;
+        assert_eq!(titles(items), expected, "{uri}");
+    }
+
+    let uri = format!("/items?start_between={},{}", NOW + 1, NOW);
+    let (status, _) = send(&app, get(&uri)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn q_expressions_filter_listings() {
+    let app = app().await;
+    for body in [
+        json!({"type": "task", "title": "report", "tags": ["work"], "due_date": NOW - 1}),
+        json!({"type": "task", "title": "slides", "tags": ["work"], "completed": true}),
+        json!({"type": "note", "title": "ideas", "tags": ["home"]}),
+    ] {
+        send(&app, post("/items", body)).await;
+    }
+
+    let (status, items) = send(
+        &app,
+        get("/items?q=type:task%20AND%20tag:work%20AND%20NOT%20completed"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    assert_eq!(items[0]["title"], "report");
+
+    let (_, items) = send(&app, get("/items?q=overdue%20OR%20tag:home&sort=title")).await;
+    assert_eq!(items[0]["title"], "ideas");
+    assert_eq!(items[1]["title"], "report");
+
+    let (status, _) = send(&app, get("/items?q=colour:red")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn saved_views_return_their_current_matches() {
+    let app = app().await;
+    for body in [
+        json!({"type": "task", "title": "today", "due_date": NOW + 1_000}),
+        json!({"type": "task", "title": "next week", "due_date": NOW + 7 * 86_400_000}),
+        json!({"type": "task", "title": "waiting", "tags": ["waiting"],
+               "due_date": NOW + 30 * 86_400_000}),
+    ] {
+        send(&app, post("/items", body)).await;
+    }
+
+    let today = json!({"name": "Today", "type": "task", "q": "due<now+1d -completed"});
+    let (status, _) = send(&app, post("/views", today)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let waiting = json!({"name": "Waiting", "tags": ["waiting"]});
+    send(&app, post("/views", waiting)).await;
+
+    let (_, items) = send(&app, get("/views/Today/items?tags=waiting")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    assert_eq!(items[0]["title"], "today");
+    let (_, items) = send(&app, get("/views/Waiting/items")).await;
+    assert_eq!(items[0]["title"], "waiting");
+    let (_, views) = send(&app, get("/views")).await;
+    assert_eq!(views.as_array().unwrap().len(), 2);
+
+    let broken = json!({"name": "Broken", "q": "due:tomorrow"});
+    let (status, _) = send(&app, post("/views", broken)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, get("/views/Broken/items")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn revisions_keep_edits_and_revert_restores_one() {
+    let app = app().await;
+    let (_, item) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "note", "title": "Draft", "content": "original"}),
+        ),
+    )
+    .await;
+    let id = item["id"].as_str().unwrap();
+    let patch = |body: Value| {
+        test::TestRequest::patch()
+            .uri(&format!("/items/{id}"))
+            .insert_header(("X-API-Key", API_KEY))
+            .set_json(body)
+    };
+    send(&app, patch(json!({"content": "oops"}))).await;
+    send(&app, patch(json!({"title": "Final"}))).await;
+
+    let (status, list) = send(&app, get(&format!("/items/{id}/revisions"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(list[0]["n"], 1);
+    assert_eq!(list[0]["title"], "Draft");
+    assert_eq!(list.as_array().unwrap().len(), 2);
+
+    let (_, first) = send(&app, get(&format!("/items/{id}/revisions/1"))).await;
+    assert_eq!(first["item"]["content"], "original");
+    assert_eq!(first["changed"], json!(["content", "title"]));
+
+    let (status, reverted) = send(&app, post(&format!("/items/{id}/revert/1"), json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reverted["content"], "original");
+    assert_eq!(reverted["title"], "Draft");
+    let (_, list) = send(&app, get(&format!("/items/{id}/revisions"))).await;
+    assert_eq!(list[2]["title"], "Final");
+
+    let (status, _) = send(&app, get(&format!("/items/{id}/revisions/9"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn reverting_checks_the_restored_version() {
+    let app = app().await;
+    for id in ["a", "b"] {
+        send(
+            &app,
+            post(
+                "/items",
+                json!({"id": id, "type": "task", "title": id, "due_date": 1_000}),
+            ),
+        )
+        .await;
+    }
+    let patch = |body: Value| {
+        test::TestRequest::patch()
+            .uri("/items/a")
+            .insert_header(("X-API-Key", API_KEY))
+            .set_json(body)
+    };
+    send(&app, patch(json!({"blocked_by": ["b"]}))).await;
+    send(&app, patch(json!({"blocked_by": []}))).await;
+    send(&app, post("/items/b/complete", json!({}))).await;
+
+    let (status, body) = send(&app, post("/items/a/revert/2", json!({}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "blocked_by");
+
+    let stale = test::TestRequest::post()
+        .uri("/items/a/revert/1")
+        .insert_header(("X-API-Key", API_KEY))
+        .insert_header(("If-Match", "\"0\""))
+        .set_json(json!({}));
+    let (status, _) = send(&app, stale).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (_, item) = send(&app, get("/items/a")).await;
+    assert_eq!(item["blocked_by"], json!([]));
+}
+
+#[actix_web::test]
+async fn deleted_items_go_to_trash_until_purged() {
+    let app = app().await;
+    let (_, item) = send(
+        &app,
+        post("/items", json!({"type": "note", "title": "Oops"})),
+    )
+    .await;
+    let id = item["id"].as_str().unwrap();
+    let delete = |uri: String| {
+        test::TestRequest::delete()
+            .uri(&uri)
+            .insert_header(("X-API-Key", API_KEY))
+    };
+
+    let (status, _) = send(&app, delete(format!("/items/{id}"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items, json!([]));
+    let (_, trash) = send(&app, get("/items/trash")).await;
+    assert_eq!(trash[0]["item"]["title"], "Oops");
+    assert_eq!(trash[0]["deleted_at"], NOW);
+
+    let (status, restored) = send(&app, post(&format!("/items/{id}/restore"), json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored, item);
+    let (_, items) = send(&app, get("/items?type=note")).await;
+    assert_eq!(items, json!([item.clone()]));
+    let (_, trash) = send(&app, get("/items/trash")).await;
+    assert_eq!(trash, json!([]));
+
+    let (status, _) = send(&app, delete(format!("/items/{id}/purge"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    send(&app, delete(format!("/items/{id}"))).await;
+    let (status, _) = send(&app, delete(format!("/items/{id}/purge"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, trash) = send(&app, get("/items/trash")).await;
+    assert_eq!(trash, json!([]));
+    let (status, _) = send(&app, post(&format!("/items/{id}/restore"), json!({}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Purging a trashed item leaves a new one under its ID alone.
+    let body = json!({"id": id, "type": "note", "title": "Again"});
+    send(&app, post("/items", body.clone())).await;
+    send(&app, delete(format!("/items/{id}"))).await;
+    send(&app, post("/items", body)).await;
+    let req = test::TestRequest::patch()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"title": "Again, edited"}));
+    send(&app, req).await;
+    let (status, _) = send(&app, delete(format!("/items/{id}/purge"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, revisions) = send(&app, get(&format!("/items/{id}/revisions"))).await;
+    assert_eq!(revisions[0]["title"], "Again");
+}
+
+#[actix_web::test]
+async fn archived_items_are_hidden_unless_asked_for() {
+    let app = app().await;
+    let (_, done) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "task", "title": "Done", "due_date": 1_000}),
+        ),
+    )
+    .await;
+    send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "task", "title": "Open", "due_date": 1_000}),
+        ),
+    )
+    .await;
+    let id = done["id"].as_str().unwrap();
+
+    let (status, archived) = send(&app, post(&format!("/items/{id}/archive"), json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(archived["archived"], true);
+
+    let (_, items) = send(&app, get("/items?type=task")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    assert_eq!(items[0]["title"], "Open");
+    let (_, items) = send(&app, get("/items?include_archived=true")).await;
+    assert_eq!(items.as_array().unwrap().len(), 2);
+    let (status, _) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, restored) = send(&app, post(&format!("/items/{id}/unarchive"), json!({}))).await;
+    assert_eq!(restored["archived"], false);
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items.as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn replacing_an_item_keeps_it_archived() {
+    let app = app().await;
+    let (_, item) = send(
+        &app,
+        post("/items", json!({"type": "note", "title": "Old"})),
+    )
+    .await;
+    let id = item["id"].as_str().unwrap();
+    send(&app, post(&format!("/items/{id}/archive"), json!({}))).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/items/{id}"))
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"type": "note", "title": "Replaced"}));
+    let (status, replaced) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(replaced["title"], "Replaced");
+    assert_eq!(replaced["archived"], true);
+    let (_, items) = send(&app, get("/items")).await;
+    assert_eq!(items, json!([]));
+}
+
+#[actix_web::test]
+async fn inbox_skips_archived_and_snoozed_notes() {
+    let app = app().await;
+    let mut ids = Vec::new();
+    for title in ["archived", "snoozed", "waiting"] {
+        let (_, note) = send(
+            &app,
+            post("/items", json!({"type": "note", "title": title})),
+        )
+        .await;
+        ids.push(note["id"].as_str().unwrap().to_string());
+    }
+    send(&app, post(&format!("/items/{}/archive", ids[0]), json!({}))).await;
+    send(
+        &app,
+        post(&format!("/items/{}/snooze", ids[1]), json!({"for": "1d"})),
+    )
+    .await;
+
+    let (_, inbox) = send(&app, get("/items/inbox")).await;
+    let titles: Vec<&str> = inbox
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["waiting"]);
+}
+
+#[actix_web::test]
+async fn snoozed_items_leave_listings_until_woken() {
+    let app = app().await;
+    let (_, task) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "task", "title": "later", "due_date": 5}),
+        ),
+    )
+    .await;
+    let uri = format!("/items/{}/snooze", task["id"].as_str().unwrap());
+
+    let (status, _) = send(&app, post(&uri, json!({"until": 5}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, snoozed) = send(&app, post(&uri, json!({"for": "1d"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(snoozed["snoozed_until"], NOW + 24 * 60 * 60 * 1000);
+    assert_eq!(snoozed["due_date"], 5);
+
+    let (_, items) = send(&app, get("/items?type=task")).await;
+    assert_eq!(items, json!([]));
+    let (_, items) = send(&app, get("/items?type=task&include_snoozed=true")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+
+    let req = test::TestRequest::delete()
+        .uri(&uri)
+        .insert_header(("X-API-Key", API_KEY));
+    let (status, woken) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(woken["snoozed_until"], Value::Null);
+    let (_, items) = send(&app, get("/items?type=task")).await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn completing_a_recurring_task_spawns_the_next_one() {
+    let app = app().await;
+    let body =
+        json!({"type": "task", "title": "t", "due_date": 1_000, "recurrence": "FREQ=HOURLY"});
+    let (status, body) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "recurrence");
+
+    let body = json!({"type": "task", "title": "Report", "due_date": "2025-06-16T09:00:00Z",
+                      "recurrence": "freq=weekly;count=3"});
+    let (status, task) = send(&app, post("/items", body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(task["recurrence"], "FREQ=WEEKLY;COUNT=3");
+    let id = task["id"].as_str().unwrap();
+
+    let uri = format!("/items/{id}/occurrences?until=2025-07-01");
+    let (status, occurrences) = send(&app, get(&uri)).await;
+    assert_eq!(status, StatusCode::OK);
+    let week = 7 * 24 * 60 * 60 * 1000;
+    let due = task["due_date"].as_i64().unwrap();
+    assert_eq!(
+        occurrences,
+        json!([{"start": due}, {"start": due + week}, {"start": due + 2 * week}])
+    );
+
+    let req = post(&format!("/items/{id}/complete"), json!({}));
+    let res = test::call_service(&app, req.to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let next_uri = res
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let (_, done) = send(&app, get(&format!("/items/{id}"))).await;
+    assert_eq!(done["completed"], true);
+    assert_eq!(done["recurrence"], Value::Null);
+
+    let (_, next) = send(&app, get(&next_uri)).await;
+    assert_eq!(next["title"], "Report");
+    assert_eq!(next["due_date"], due + week);
+    assert_eq!(next["completed"], Value::Null);
+    assert_eq!(next["recurrence"], "FREQ=WEEKLY;COUNT=2");
+}
+
+#[actix_web::test]
+async fn every_way_of_completing_a_recurring_task_rolls_it_forward() {
+    let app = app().await;
+    for id in ["patched", "bulk", "batched"] {
+        let body = json!({"id": id, "type": "task", "title": id, "tags": [id],
+                          "due_date": 1_000, "recurrence": "FREQ=DAILY"});
+        send(&app, post("/items", body)).await;
+    }
+
+    let patch = test::TestRequest::patch()
+        .uri("/items/patched")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"completed": true}));
+    let res = test::call_service(&app, patch.to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().contains_key("Location"));
+    let edit = json!({"set": {"completed": true}});
+    let (status, _) = send(&app, post("/items/bulk-update?tags=bulk", edit)).await;
+    assert_eq!(status, StatusCode::OK);
+    let rows = json!([{"op": "update", "id": "batched", "changes": {"completed": true}}]);
+    let (status, _) = send(&app, post("/items/batch", rows)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let day = 24 * 60 * 60 * 1000;
+    let (_, items) = send(&app, get("/items")).await;
+    for id in ["patched", "bulk", "batched"] {
+        let series: Vec<&Value> = items
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|item| item["title"] == id)
+            .collect();
+        assert_eq!(series.len(), 2, "{id}");
+        let done = series.iter().find(|item| item["id"] == id).unwrap();
+        assert_eq!(done["completed"], true);
+        assert_eq!(done["recurrence"], Value::Null);
+        let next = series.iter().find(|item| item["id"] != id).unwrap();
+        assert_eq!(next["due_date"], 1_000 + day);
+        assert_eq!(next["recurrence"], "FREQ=DAILY");
+    }
+}
+
+#[actix_web::test]
+async fn reminders_crud_under_items() {
+    let app = app().await;
+    let (_, task) = send(
+        &app,
+        post(
+            "/items",
+            json!({"type": "task", "title": "t", "due_date": 3_600_000}),
+        ),
+    )
+    .await;
+    let uri = format!("/items/{}/reminders", task["id"].as_str().unwrap());
+
+    let (status, body) = send(&app, post(&uri, json!({"at": 1, "before": "1h"}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], "at");
+    let (status, _) = send(&app, post(&uri, json!({"before": "soon"}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, reminder) = send(&app, post(&uri, json!({"before": "15m"}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(reminder["before"], 15 * 60 * 1000);
+    assert_eq!(reminder["fire_at"], 45 * 60 * 1000);
+    let one = format!("{uri}/{}", reminder["id"].as_str().unwrap());
+
+    let req = test::TestRequest::put()
+        .uri(&one)
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"at": "1970-01-01T00:30:00Z"}));
+    let (status, replaced) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(replaced["fire_at"], 30 * 60 * 1000);
+
+    let (_, listed) = send(&app, get(&uri)).await;
+    assert_eq!(listed, json!([replaced]));
+
+    let req = test::TestRequest::delete()
+        .uri(&one)
+        .insert_header(("X-API-Key", API_KEY));
+    let (status, _) = send(&app, req).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, get(&one)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn complete_and_uncomplete_track_completed_at() {
+    let app = app().await;
+    let body = json!({"type": "task", "title": "t", "due_date": 1_000});
+    let (_, task) = send(&app, post("/items", body)).await;
+    assert_eq!(task["completed_at"], Value::Null);
+    let id = task["id"].as_str().unwrap();
+
+    let (status, done) = send(&app, post(&format!("/items/{id}/complete"), json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(done["completed"], true);
+    assert_eq!(done["completed_at"], NOW);
+
+    let (_, again) = send(&app, post(&format!("/items/{id}/complete"), json!({}))).await;
+    assert_eq!(again["version"], done["version"]);
+
+    let (_, undone) = send(&app, post(&format!("/items/{id}/uncomplete"), json!({}))).await;
+    assert_eq!(undone["completed"], false);
+    assert_eq!(undone["completed_at"], Value::Null);
+
+    let (status, _) = send(&app, post("/items/missing/complete", json!({}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn tags_rename_merge_and_delete_across_items() {
+    let app = app().await;
+    for tags in [json!(["wip", "work"]), json!(["todo"]), json!(["wip"])] {
+        send(
+            &app,
+            post(
+                "/items",
+                json!({"type": "note", "title": "t", "tags": tags}),
+            ),
+        )
+        .await;
+    }
+    let meta = test::TestRequest::put()
+        .uri("/tags/wip/meta")
+        .insert_header(("X-API-Key", API_KEY))
+        .set_json(json!({"color": "red"}));
+    send(&app, meta).await;
+
+    let rename = json!({"from": "wip", "to": "todo"});
+    let (status, _) = send(&app, post("/tags/rename", rename)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let rename = json!({"from": "wip", "to": "doing"});
+    let (status, body) = send(&app, post("/tags/rename", rename)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["updated"], 2);
+    let (_, meta) = send(&app, get("/tags/doing/meta")).await;
+    assert_eq!(meta["color"], "red");
+
+    let merge = json!({"tags": ["doing", "work"], "into": "todo"});
+    let (_, body) = send(&app, post("/tags/merge", merge)).await;
+    assert_eq!(body["updated"], 2);
+    let (_, tags) = send(&app, get("/tags")).await;
+    assert_eq!(tags, json!([{"name": "todo", "count": 3}]));
+
+    let delete = test::TestRequest::delete()
+        .uri("/tags/todo")
+        .insert_header(("X-API-Key", API_KEY));
+    let (_, body) = send(&app, delete).await;
+    assert_eq!(body["updated"], 3);
+    let (_, tags) = send(&app, get("/tags")).await;
+    assert_eq!(tags, json!([]));
+    let (_, items) = send(&app, get("/items?tags=todo")).await;
+    assert_eq!(items, json!([]));
+}
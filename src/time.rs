@@ -0,0 +1,214 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::stream::Keyed;
+
+/// Item fields holding epoch-millisecond timestamps.
+const TIMESTAMP_FIELDS: &[&str] = &[
+    "created_at",
+    "updated_at",
+    "due_date",
+    "start_time",
+    "end_time",
+    "completed_at",
+    "snoozed_until",
+];
+
+/// Parses an ISO-8601 timestamp into epoch milliseconds. Accepts full
+/// RFC 3339 (`2025-07-01T09:30:00+02:00`), a naive date-time taken as UTC
+/// (`2025-07-01T09:30:00`), or a bare date meaning midnight UTC.
+pub fn parse_iso_millis(raw: &str) -> Option<i64> {
+    let raw = raw.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        return Some(dt.timestamp_millis());
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Some(dt.and_utc().timestamp_millis());
+    }
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc().timestamp_millis())
+}
+
+/// Parses a timestamp given as epoch milliseconds or in any form
+/// [`parse_iso_millis`] accepts.
+pub fn parse_millis(raw: &str) -> Option<i64> {
+    raw.trim().parse().ok().or_else(|| parse_iso_millis(raw))
+}
+
+/// Parses a relative duration such as `90s`, `30m`, `12h`, `2d` or `1w`
+/// into milliseconds. Units are required; the amount must be positive.
+pub fn parse_duration_millis(raw: &str) -> Option<i64> {
+    let raw = raw.trim();
+    let unit_at = raw.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = raw.split_at(unit_at);
+    let amount: i64 = amount.parse().ok().filter(|n| *n > 0)?;
+    let unit_millis = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 60 * 60_000,
+        "d" => 24 * 60 * 60_000,
+        "w" => 7 * 24 * 60 * 60_000,
+        _ => return None,
+    };
+    amount.checked_mul(unit_millis)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MillisOrIso {
+    Millis(i64),
+    Iso(String),
+}
+
+/// Deserializes an optional timestamp given either as epoch milliseconds or
+/// as an ISO-8601 string, normalizing to milliseconds. Use together with
+/// `#[serde(default)]` so absent fields stay `None`.
+pub fn deserialize_opt_millis<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<MillisOrIso>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(MillisOrIso::Millis(millis)) => Ok(Some(millis)),
+        Some(MillisOrIso::Iso(raw)) => parse_iso_millis(&raw).map(Some).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid timestamp '{raw}', expected epoch millis or ISO-8601"
+            ))
+        }),
+    }
+}
+
+/// Like `deserialize_opt_millis`, but a present `null` is `Some(None)`, so
+/// an update can tell clearing a timestamp from leaving it alone.
+pub fn deserialize_nullable_millis<'de, D>(deserializer: D) -> Result<Option<Option<i64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_opt_millis(deserializer).map(Some)
+}
+
+/// Formats epoch milliseconds as an RFC 3339 UTC string.
+pub fn millis_to_iso(millis: i64) -> Option<String> {
+    DateTime::from_timestamp_millis(millis)
+        .map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// How timestamps are written in responses, chosen with `?time=`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeFormat {
+    /// Epoch milliseconds, as stored.
+    #[default]
+    Millis,
+    /// RFC 3339 UTC strings.
+    Iso,
+}
+
+impl TimeFormat {
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        match query.get("time").map(String::as_str) {
+            None | Some("millis") => Ok(TimeFormat::Millis),
+            Some("iso") => Ok(TimeFormat::Iso),
+            Some(other) => Err(format!(
+                "Invalid time '{other}', expected 'millis' or 'iso'"
+            )),
+        }
+    }
+
+    /// Wraps `value` so it serializes with timestamps in this format.
+    pub fn view<T>(self, value: T) -> TimeView<T> {
+        TimeView {
+            value,
+            format: self,
+        }
+    }
+}
+
+/// Typed form of `?time=` for handlers that take a struct query.
+#[derive(Debug, Default, Deserialize)]
+pub struct TimeQuery {
+    #[serde(default)]
+    pub time: TimeFormat,
+}
+
+/// Response view of an item (or anything shaped like one) that rewrites its
+/// timestamp fields in the requested format. The stored data is untouched.
+pub struct TimeView<T> {
+    value: T,
+    format: TimeFormat,
+}
+
+impl<T: Serialize> Serialize for TimeView<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.format == TimeFormat::Millis {
+            return self.value.serialize(serializer);
+        }
+        let mut value = serde_json::to_value(&self.value).map_err(serde::ser::Error::custom)?;
+        rewrite_timestamps(&mut value);
+        value.serialize(serializer)
+    }
+}
+
+impl<T: Keyed> Keyed for TimeView<T> {
+    fn key(&self) -> &str {
+        self.value.key()
+    }
+}
+
+/// Replaces millisecond timestamps with ISO strings, descending into arrays
+/// so a list of items can be rewritten in one go.
+fn rewrite_timestamps(value: &mut Value) {
+    match value {
+        Value::Array(values) => values.iter_mut().for_each(rewrite_timestamps),
+        Value::Object(fields) => {
+            for field in TIMESTAMP_FIELDS {
+                if let Some(iso) = fields
+                    .get(*field)
+                    .and_then(Value::as_i64)
+                    .and_then(millis_to_iso)
+                {
+                    fields.insert(field.to_string(), Value::String(iso));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_relative_durations() {
+        assert_eq!(parse_duration_millis("12h"), Some(12 * 60 * 60 * 1000));
+        assert_eq!(parse_duration_millis(" 2d"), Some(2 * 24 * 60 * 60 * 1000));
+        assert_eq!(parse_duration_millis("1w"), Some(7 * 24 * 60 * 60 * 1000));
+        for bad in ["", "d", "5", "0h", "3y", "-1d", "1.5h"] {
+            assert_eq!(parse_duration_millis(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn iso_view_rewrites_every_timestamp() {
+        let item = serde_json::json!({
+            "created_at": 0,
+            "completed_at": 1_000,
+            "snoozed_until": 1_750_000_000_000_i64,
+            "title": "t",
+        });
+        let view = serde_json::to_value(TimeFormat::Iso.view(&item)).unwrap();
+        assert_eq!(view["created_at"], "1970-01-01T00:00:00.000Z");
+        assert_eq!(view["completed_at"], "1970-01-01T00:00:01.000Z");
+        assert_eq!(view["snoozed_until"], "2025-06-15T15:06:40.000Z");
+        assert_eq!(view["title"], "t");
+        assert_eq!(
+            serde_json::to_value(TimeFormat::Millis.view(&item)).unwrap(),
+            item
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Deleted items. `DELETE /items/{id}` moves an item here instead of
+//! destroying it, so it can be restored until it is purged. Attachments,
+//! access counts and revisions stay with a trashed item until then.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access, attachments,
+    audit::{self, Action, Entry},
+    clock::SharedClock,
+    codec,
+    error::ApiError,
+    index::{self, IndexOps},
+    reminders, revisions, still_at,
+    store::{BatchOp, StoreResult},
+    tenant::{Tenant, TenantStore},
+    Item, SharedStore,
+};
+
+pub const TRASH_TREE: &str = "trash";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trashed {
+    deleted_at: i64,
+    item: Item,
+}
+
+/// The [`TRASH_TREE`] write that files `item` as deleted at `now`.
+pub fn trash_op(db: &SharedStore, item: &Item, now: i64) -> StoreResult<BatchOp> {
+    let trashed = Trashed {
+        deleted_at: now,
+        item: item.clone(),
+    };
+    let bytes = codec::encode(db.encoding(), &trashed)?;
+    Ok(BatchOp::Insert(item.id.as_bytes().to_vec(), bytes))
+}
+
+/// Moves `item` out of the item keyspace and into the trash, logged as a
+/// delete and dropped from the indexes in the same batch. Applied only while
+/// `item` is still stored as read; returns whether it was.
+pub fn trash(db: &SharedStore, tenant: &Tenant, item: &Item, now: i64) -> StoreResult<bool> {
+    let mut index_ops = IndexOps::default();
+    index_ops.add(Some(item), None);
+    let mut sides = vec![(TRASH_TREE, vec![trash_op(db, item, now)?])];
+    sides.extend(index_ops.into_sides());
+    let check = still_at(Some(item.version));
+    audit::commit_if(
+        db,
+        &[(item.id.as_bytes(), check.as_ref())],
+        vec![BatchOp::Remove(item.id.as_bytes().to_vec())],
+        sides,
+        &[Entry::new(tenant, Action::Delete, &item.id, now)],
+    )
+}
+
+/// Whether an item deleted under `id` is waiting in the trash.
+pub fn contains(db: &SharedStore, id: &str) -> StoreResult<bool> {
+    db.tree(TRASH_TREE)?
+        .get(id.as_bytes())
+        .map(|raw| raw.is_some())
+}
+
+fn find(db: &SharedStore, id: &str) -> Result<Trashed, ApiError> {
+    let raw = db
+        .tree(TRASH_TREE)?
+        .get(id.as_bytes())?
+        .ok_or(ApiError::NotFound("Item not in trash"))?;
+    codec::decode(&raw).map_err(|_| ApiError::Internal("Deserialization failed"))
+}
+
+/// `GET /items/trash`: every trashed item with when it was deleted.
+pub async fn list(db: TenantStore) -> Result<HttpResponse, ApiError> {
+    let tree = db.tree(TRASH_TREE)?;
+    let trashed = web::block(move || {
+        tree.iter()
+            .filter_map(|entry| codec::decode::<Trashed>(&entry.ok()?.1).ok())
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(trashed))
+}
+
+/// `POST /items/{id}/restore`: puts a trashed item back as it was. Fails
+/// with 409 if an item has since been created under the same ID.
+pub async fn restore(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let Trashed { item, .. } = find(&db, &id)?;
+    if db.get(id.as_bytes())?.is_some() {
+        return Err(ApiError::Conflict(
+            "An item with this id already exists".to_string(),
+        ));
+    }
+
+    let bytes = codec::encode(db.encoding(), &item)
+        .map_err(|_| ApiError::Internal("Serialization failed"))?;
+    let key = id.as_bytes().to_vec();
+    let entry = Entry::new(&tenant, Action::Restore, &id, clock.now_millis());
+    let restored = audit::commit_with(
+        &db,
+        vec![BatchOp::Insert(key.clone(), bytes)],
+        vec![(TRASH_TREE, vec![BatchOp::Remove(key)])],
+        &[entry],
+    )
+    .and_then(|()| index::reindex(&db, None, Some(&item)));
+
+    restored.map_err(|_| ApiError::Internal("Failed to restore item"))?;
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// `DELETE /items/{id}/purge`: removes a trashed item for good, along with
+/// its attachments, access counts, revisions and reminders. When an item has
+/// since been created under the same ID, the data kept by ID is that item's
+/// and stays.
+pub async fn purge(
+    db: TenantStore,
+    tenant: Tenant,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let Trashed { item, .. } = find(&db, &id)?;
+
+    let reused = db.get(id.as_bytes())?.is_some();
+
+    let entry = Entry::new(&tenant, Action::Purge, &id, clock.now_millis());
+    let removal = vec![(TRASH_TREE, vec![BatchOp::Remove(id.clone().into_bytes())])];
+    let purged = audit::commit_with(&db, Vec::new(), removal, &[entry])
+        .and_then(|()| attachments::remove_blobs(&db, &item))
+        .and_then(|()| {
+            if reused {
+                return Ok(());
+            }
+            access::forget(&db, &id)
+                .and_then(|()| revisions::forget(&db, &id))
+                .and_then(|()| reminders::forget(&db, &id))
+        });
+
+    purged.map_err(|_| ApiError::Internal("Failed to purge item"))?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,209 @@
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+use crate::{
+    codec,
+    error::ApiError,
+    filter::{self, ItemFilter},
+    index,
+    store::{BatchOp, StoreResult},
+    tenant::{self, TenantStore},
+    Item, SharedStore,
+};
+
+/// What kind of thing an item is. The built-in types have their own rules;
+/// any other name is `Custom`, and only accepted on writes if it is listed
+/// in `CUSTOM_TYPES`. Names are compared trimmed and lowercased, so `Task`
+/// and ` task` are both `Task`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ItemType {
+    Note,
+    Task,
+    Event,
+    Custom(String),
+}
+
+impl ItemType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ItemType::Note => "note",
+            ItemType::Task => "task",
+            ItemType::Event => "event",
+            ItemType::Custom(name) => name,
+        }
+    }
+
+    /// Whether writes may use this type: it is built in, or one of `custom`.
+    pub fn is_known(&self, custom: &[ItemType]) -> bool {
+        !matches!(self, ItemType::Custom(_)) || custom.contains(self)
+    }
+}
+
+impl From<&str> for ItemType {
+    fn from(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "note" => ItemType::Note,
+            "task" => ItemType::Task,
+            "event" => ItemType::Event,
+            other => ItemType::Custom(other.to_string()),
+        }
+    }
+}
+
+impl From<String> for ItemType {
+    fn from(name: String) -> Self {
+        ItemType::from(name.as_str())
+    }
+}
+
+impl From<ItemType> for String {
+    fn from(item_type: ItemType) -> Self {
+        item_type.as_str().to_string()
+    }
+}
+
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TypeSummary {
+    name: String,
+    count: usize,
+}
+
+/// Every item type present in the store with how many items have it, most
+/// common first.
+pub async fn list_types(db: TenantStore) -> Result<HttpResponse, ApiError> {
+    let items = filter::scan_blocking(&db, ItemFilter::default()).await?;
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for item in items {
+        *counts.entry(item.item_type.to_string()).or_default() += 1;
+    }
+
+    let mut types: Vec<TypeSummary> = counts
+        .into_iter()
+        .map(|(name, count)| TypeSummary { name, count })
+        .collect();
+    types.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(HttpResponse::Ok().json(types))
+}
+
+/// What the startup normalization pass did.
+#[derive(Debug, Default)]
+pub struct Normalized {
+    /// Items whose stored type was rewritten in its canonical spelling.
+    pub rewritten: usize,
+    /// Stored types that are neither built in nor configured. Those items
+    /// stay readable, but can't be saved again until their type is fixed.
+    pub unknown: BTreeSet<String>,
+}
+
+/// Rewrites every stored item, in the default keyspace and every tenant,
+/// whose type isn't spelled canonically, such as `Task` or ` note`, and
+/// collects the types that are neither built in nor in `custom`.
+pub fn normalize(
+    db: &SharedStore,
+    tenant_labels: &[&str],
+    custom: &[ItemType],
+) -> StoreResult<Normalized> {
+    let mut keyspaces = vec![db.clone()];
+    for label in tenant_labels {
+        keyspaces.push(tenant::tenant_store(db, label)?);
+    }
+
+    let mut normalized = Normalized::default();
+    for keyspace in keyspaces {
+        let mut ops = Vec::new();
+        let mut retyped = Vec::new();
+        for entry in keyspace.iter() {
+            let (key, raw) = entry?;
+            let Ok(item) = codec::decode::<Item>(&raw) else {
+                continue;
+            };
+            if !item.item_type.is_known(custom) {
+                normalized.unknown.insert(item.item_type.to_string());
+            }
+            let Ok(mut value) = codec::decode::<serde_json::Value>(&raw) else {
+                continue;
+            };
+            let stored = value["type"].as_str().unwrap_or_default().to_string();
+            if stored == item.item_type.as_str() {
+                continue;
+            }
+            value["type"] = item.item_type.to_string().into();
+            if let Ok(bytes) = codec::encode(keyspace.encoding(), &value) {
+                ops.push(BatchOp::Insert(key, bytes));
+                retyped.push((stored, item));
+            }
+        }
+        normalized.rewritten += ops.len();
+        keyspace.batch(ops)?;
+        // The type index was keyed on the lowercased, untrimmed spelling.
+        for (stored, item) in retyped {
+            let old = Item {
+                item_type: ItemType::Custom(stored.to_lowercase()),
+                ..item.clone()
+            };
+            index::reindex(&keyspace, Some(&old), Some(&item))?;
+        }
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Encoding, store::MemoryStore};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn parses_names_case_and_space_insensitively() {
+        assert_eq!(ItemType::from(" Task "), ItemType::Task);
+        assert_eq!(ItemType::from("NOTE"), ItemType::Note);
+        assert_eq!(
+            ItemType::from("Recipe"),
+            ItemType::Custom("recipe".to_string())
+        );
+        assert_eq!(ItemType::Event.to_string(), "event");
+    }
+
+    #[test]
+    fn normalize_rewrites_misspelled_types_and_reports_unknown_ones() {
+        let db: SharedStore = Arc::new(MemoryStore::new(Encoding::Json));
+        let stored = |id: &str, item_type: &str| {
+            let value = json!({
+                "id": id, "type": item_type, "title": id, "content": null, "tags": [],
+                "code_location": null, "created_at": 0, "completed": null,
+                "due_date": null, "start_time": null, "end_time": null,
+            });
+            db.insert(
+                id.as_bytes(),
+                codec::encode(Encoding::Json, &value).unwrap(),
+            )
+            .unwrap();
+        };
+        stored("a", " Task");
+        stored("b", "note");
+        stored("c", "taks");
+
+        let normalized = normalize(&db, &[], &[]).unwrap();
+        assert_eq!(normalized.rewritten, 1);
+        assert_eq!(normalized.unknown, BTreeSet::from(["taks".to_string()]));
+
+        let raw = db.get(b"a").unwrap().unwrap();
+        let value: serde_json::Value = codec::decode(&raw).unwrap();
+        assert_eq!(value["type"], "task");
+        assert_eq!(normalize(&db, &[], &[]).unwrap().rewritten, 0);
+    }
+}
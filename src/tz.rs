@@ -0,0 +1,82 @@
+//! Calendar days in the caller's time zone. Anything that buckets by day
+//! ("today", "overdue", a week) goes through here so an item near midnight
+//! lands on the same day everywhere for a given `tz`.
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// Typed form of `?tz=` for handlers that take a struct query.
+#[derive(Debug, Default, Deserialize)]
+pub struct TzQuery {
+    pub tz: Option<String>,
+}
+
+impl TzQuery {
+    pub fn zone(&self) -> Result<Tz, String> {
+        parse(self.tz.as_deref())
+    }
+}
+
+/// Parses an IANA zone name such as `Europe/Berlin`, defaulting to UTC.
+pub fn parse(raw: Option<&str>) -> Result<Tz, String> {
+    match raw {
+        None => Ok(Tz::UTC),
+        Some(name) => name.trim().parse().map_err(|_| {
+            format!("Unknown time zone '{name}', expected an IANA name such as Europe/Berlin")
+        }),
+    }
+}
+
+/// The calendar day `millis` falls on in `tz`.
+pub fn day_of(millis: i64, tz: Tz) -> NaiveDate {
+    DateTime::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .with_timezone(&tz)
+        .date_naive()
+}
+
+/// The first instant of `date` in `tz`, as epoch milliseconds. Where a DST
+/// change skips midnight, the day starts at the first local time that exists.
+pub fn start_of_day(date: NaiveDate, tz: Tz) -> i64 {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    (0..=96)
+        .map(|quarter| midnight + Duration::minutes(15 * quarter))
+        .find_map(|local| tz.from_local_datetime(&local).earliest())
+        .map(|start| start.timestamp_millis())
+        .unwrap_or_else(|| midnight.and_utc().timestamp_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_follow_the_zone() {
+        // 2025-06-15 23:30 UTC is already the 16th in Berlin.
+        let millis = 1_750_030_200_000;
+        assert_eq!(day_of(millis, Tz::UTC).to_string(), "2025-06-15");
+        assert_eq!(
+            day_of(millis, chrono_tz::Europe::Berlin).to_string(),
+            "2025-06-16"
+        );
+    }
+
+    #[test]
+    fn day_starts_after_a_skipped_midnight() {
+        // Chile moved clocks from 00:00 to 01:00 on 2022-09-11.
+        let date = NaiveDate::from_ymd_opt(2022, 9, 11).unwrap();
+        let start = start_of_day(date, chrono_tz::America::Santiago);
+        assert_eq!(day_of(start, chrono_tz::America::Santiago), date);
+        assert_eq!(
+            day_of(start - 1, chrono_tz::America::Santiago),
+            date.pred_opt().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_zones() {
+        assert!(parse(Some("Mars/Olympus")).is_err());
+        assert_eq!(parse(None).unwrap(), Tz::UTC);
+    }
+}
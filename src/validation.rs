@@ -0,0 +1,350 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+
+use crate::{
+    clock::SharedClock, config::Config, dependencies, error::ApiError, load_item, priority,
+    projects, recurrence, rules, subtasks, templates, tenant::TenantStore, Item, SharedStore,
+    UpdateItemPayload,
+};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+    /// Machine-readable reason, set for limit violations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, message: impl Into<String>) -> Self {
+        FieldError {
+            field,
+            message: message.into(),
+            code: None,
+        }
+    }
+
+    fn limit(field: &'static str, code: &'static str, message: impl Into<String>) -> Self {
+        FieldError {
+            code: Some(code),
+            ..FieldError::new(field, message)
+        }
+    }
+}
+
+/// Trims tags, drops a leading `#` and empty entries, and removes duplicates
+/// while keeping the first occurrence's position.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        let tag = tag.strip_prefix('#').unwrap_or(tag);
+        // `work//a/` means `work/a`: empty hierarchy segments are dropped.
+        let tag = tag
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Why `tag` can't be stored, if it can't. Commas separate tags in
+/// `?tags=` and a leading `-` excludes one there, so neither could be
+/// filtered on.
+fn tag_problem(tag: &str) -> Option<&'static str> {
+    if tag.chars().any(char::is_whitespace) {
+        Some("must not contain whitespace")
+    } else if tag.chars().any(char::is_control) {
+        Some("must not contain control characters")
+    } else if tag.contains(',') {
+        Some("must not contain ','")
+    } else if tag.starts_with('-') {
+        Some("must not start with '-'")
+    } else {
+        None
+    }
+}
+
+/// The latest time an item may carry, 9999-12-31T23:59:59.999Z, so every
+/// stored time can be rendered as an ISO 8601 date.
+pub const MAX_MILLIS: i64 = 253_402_300_799_999;
+
+fn is_missing(item: &Item, field: &str) -> bool {
+    match field {
+        "type" => item.item_type.as_str().is_empty(),
+        "title" => item.title.trim().is_empty(),
+        "content" => item.content.is_none(),
+        "tags" => item.tags.is_empty(),
+        "code_location" => item.code_location.is_none(),
+        "completed" => item.completed.is_none(),
+        "due_date" => item.due_date.is_none(),
+        "start_time" => item.start_time.is_none(),
+        "end_time" => item.end_time.is_none(),
+        "recurrence" => item.recurrence.is_none(),
+        "priority" => item.priority.is_none(),
+        "status" => item.status.is_none(),
+        _ => false,
+    }
+}
+
+/// Checks an ID chosen by the client rather than generated, which must be
+/// usable as a path segment.
+pub fn validate_client_id(id: &str) -> Result<(), Vec<FieldError>> {
+    if id.trim().is_empty() {
+        Err(vec![FieldError::new("id", "must not be empty")])
+    } else if id.contains('/') {
+        Err(vec![FieldError::new("id", "must not contain '/'")])
+    } else {
+        Ok(())
+    }
+}
+
+/// Normalizes `item` in place and checks it against the rules every write
+/// path enforces. All problems are reported, not just the first.
+pub fn validate_item(item: &mut Item, config: &Config) -> Result<(), Vec<FieldError>> {
+    let mut errors = check_fields(item, config);
+    errors.extend(missing_required(item));
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// [`validate_item`] less the fields the item's type requires, for captures:
+/// their text has no way to give a due date or times, so those are asked for
+/// when the item is next edited.
+pub fn validate_capture(item: &mut Item, config: &Config) -> Result<(), Vec<FieldError>> {
+    let errors = check_fields(item, config);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// The fields `item`'s type requires that it doesn't have.
+fn missing_required(item: &Item) -> Vec<FieldError> {
+    let Some(rules) = rules::rules_for(item.item_type.as_str()) else {
+        return Vec::new();
+    };
+    rules
+        .required
+        .iter()
+        .filter(|f| !matches!(**f, "type" | "title"))
+        .filter(|field| is_missing(item, field))
+        .map(|field| FieldError::new(field, format!("is required for {} items", rules.name)))
+        .collect()
+}
+
+fn check_fields(item: &mut Item, config: &Config) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let limits = &config.limits;
+
+    item.tags = normalize_tags(&item.tags);
+    let mut blockers = HashSet::new();
+    item.blocked_by.retain(|id| blockers.insert(id.clone()));
+
+    if item.item_type.as_str().is_empty() {
+        errors.push(FieldError::new("type", "must not be empty"));
+    } else if !config.knows_type(&item.item_type) {
+        errors.push(FieldError::new(
+            "type",
+            format!(
+                "is '{}', which is not a known type (expected one of {})",
+                item.item_type,
+                config.known_types().join(", ")
+            ),
+        ));
+    }
+    if item.title.trim().is_empty() {
+        errors.push(FieldError::new("title", "must not be empty"));
+    }
+    let title_chars = item.title.chars().count();
+    if title_chars > limits.max_title_chars {
+        errors.push(FieldError::limit(
+            "title",
+            "title_too_long",
+            format!(
+                "is {title_chars} characters, the limit is {}",
+                limits.max_title_chars
+            ),
+        ));
+    }
+    if item.tags.len() > limits.max_tags {
+        errors.push(FieldError::limit(
+            "tags",
+            "too_many_tags",
+            format!(
+                "has {} tags, the limit is {}",
+                item.tags.len(),
+                limits.max_tags
+            ),
+        ));
+    }
+    for tag in &item.tags {
+        if let Some(problem) = tag_problem(tag) {
+            errors.push(FieldError::new("tags", format!("'{tag}' {problem}")));
+        }
+    }
+    let content_bytes = item.content.as_ref().map_or(0, String::len);
+    if content_bytes > limits.max_content_bytes {
+        errors.push(FieldError::limit(
+            "content",
+            "content_too_long",
+            format!(
+                "is {content_bytes} bytes, the limit is {}",
+                limits.max_content_bytes
+            ),
+        ));
+    }
+    let times = [
+        ("due_date", item.due_date),
+        ("start_time", item.start_time),
+        ("end_time", item.end_time),
+    ];
+    for (field, millis) in times {
+        if millis.is_some_and(|millis| !(0..=MAX_MILLIS).contains(&millis)) {
+            errors.push(FieldError::new(
+                field,
+                "must be between 1970 and the end of year 9999",
+            ));
+        }
+    }
+    if let Some(status) = &mut item.status {
+        *status = status.trim().to_lowercase();
+        if !config.statuses.contains(status) {
+            let message = format!(
+                "is '{status}', which is not a known status (expected one of {})",
+                config.statuses.join(", ")
+            );
+            errors.push(FieldError::new("status", message));
+        } else if status == config.done_status() {
+            item.completed = Some(true);
+        }
+    }
+    if item
+        .priority
+        .is_some_and(|p| !(priority::HIGHEST..=priority::LOWEST).contains(&p))
+    {
+        errors.push(FieldError::new(
+            "priority",
+            format!("must be between 1 and {}", priority::LOWEST),
+        ));
+    }
+    if let Some(raw) = &item.recurrence {
+        match raw.parse::<recurrence::Rule>() {
+            Ok(_) if recurrence::anchor(item).is_none() => errors.push(FieldError::new(
+                "recurrence",
+                "needs a due_date or start_time to repeat from",
+            )),
+            Ok(rule) => item.recurrence = Some(rule.to_string()),
+            Err(message) => errors.push(FieldError::new("recurrence", message)),
+        }
+    }
+    if item.parent_id.as_deref() == Some(item.id.as_str()) {
+        errors.push(FieldError::new("parent_id", "must not be the item itself"));
+    }
+
+    if let (Some(start), Some(end)) = (item.start_time, item.end_time) {
+        if end < start {
+            errors.push(FieldError::new("end_time", "must not be before start_time"));
+        }
+    }
+
+    if let Some(location) = &item.code_location {
+        if location.file_path.trim().is_empty() {
+            errors.push(FieldError::new(
+                "code_location.file_path",
+                "must not be empty",
+            ));
+        }
+        if location.line_number == 0 {
+            errors.push(FieldError::new(
+                "code_location.line_number",
+                "must be at least 1",
+            ));
+        }
+    }
+    errors
+}
+
+/// Checks the IDs `item` refers to, its parent, project and blockers, against what
+/// `db` holds. Run after [`validate_item`], which can't see the store.
+pub fn check_references(db: &SharedStore, item: &Item) -> Result<(), ApiError> {
+    let mut errors = subtasks::check_parent(db, item)?;
+    errors.extend(projects::check_project(db, item)?);
+    errors.extend(dependencies::check_blockers(db, item)?);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::Invalid(errors))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateQuery {
+    /// When set, the body is treated as an update to this item.
+    id: Option<String>,
+    /// `template` and `tz`, applied to a create as `POST /items` would.
+    #[serde(flatten)]
+    create: templates::CreateQuery,
+}
+
+/// Dry run of a create (or, with `?id=`, an update): reports what would be
+/// stored, or why it would be rejected, without writing anything.
+pub async fn validate_payload(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    config: web::Data<Config>,
+    query: web::Query<ValidateQuery>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let mut errors = Vec::new();
+    let mut item = match &query.id {
+        Some(id) => {
+            let payload: UpdateItemPayload =
+                serde_json::from_value(body).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+            let mut item = load_item(&db, id)?;
+            item.apply_update(&payload);
+            item
+        }
+        None => {
+            let created_at = clock.now_millis();
+            let payload = templates::prefill(&db, &query.create, body, created_at)?;
+            let id = payload.id.clone().unwrap_or_default();
+            if payload.id.is_some() {
+                match validate_client_id(&id) {
+                    Err(found) => errors.extend(found),
+                    Ok(()) if db.get(id.as_bytes())?.is_some() => {
+                        errors.push(FieldError::new("id", "an item with this id already exists"));
+                    }
+                    Ok(()) => {}
+                }
+            }
+            Item::from_payload(id, created_at, &payload)
+        }
+    };
+
+    // Checked like the writes do, references included, so a payload is only
+    // reported valid when storing it would succeed.
+    errors.extend(validate_item(&mut item, &config).err().unwrap_or_default());
+    match check_references(&db, &item) {
+        Ok(()) => {}
+        Err(ApiError::Invalid(found)) => errors.extend(found),
+        Err(e) => return Err(e),
+    }
+    Ok(if errors.is_empty() {
+        HttpResponse::Ok().json(json!({ "valid": true, "normalized": item }))
+    } else {
+        HttpResponse::Ok().json(json!({ "valid": false, "errors": errors }))
+    })
+}
@@ -0,0 +1,138 @@
+//! Saved searches. A view names a filter once, so every client asking for
+//! "Today" sees the same items without carrying the filter logic itself.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    clock::SharedClock,
+    codec,
+    error::ApiError,
+    filter::{self, ItemFilter, Page, Sort},
+    tenant::TenantStore,
+    time::TimeFormat,
+    SharedStore,
+};
+
+/// Saved views, keyed by name.
+pub const VIEW_TREE: &str = "views";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct View {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub item_type: Option<String>,
+    /// Items must carry all of these; `-tag` excludes, as in `?tags=`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// A filter expression in the `?q=` language.
+    pub q: Option<String>,
+}
+
+impl View {
+    /// The view as listing query parameters.
+    fn params(&self) -> HashMap<String, String> {
+        let mut params = HashMap::new();
+        if let Some(item_type) = &self.item_type {
+            params.insert("type".to_string(), item_type.clone());
+        }
+        if !self.tags.is_empty() {
+            params.insert("tags".to_string(), self.tags.join(","));
+        }
+        if let Some(q) = &self.q {
+            params.insert("q".to_string(), q.clone());
+        }
+        params
+    }
+}
+
+fn load(db: &SharedStore, name: &str) -> Result<View, ApiError> {
+    let raw = db
+        .tree(VIEW_TREE)?
+        .get(name.as_bytes())?
+        .ok_or(ApiError::NotFound("View not found"))?;
+    codec::decode(&raw).map_err(|_| ApiError::Internal("Deserialization failed"))
+}
+
+/// `GET /views`: every saved view, by name.
+pub async fn list(db: TenantStore) -> Result<HttpResponse, ApiError> {
+    let views: Vec<View> = db
+        .tree(VIEW_TREE)?
+        .iter()
+        .filter_map(|entry| codec::decode(&entry.ok()?.1).ok())
+        .collect();
+    Ok(HttpResponse::Ok().json(views))
+}
+
+/// `POST /views`: saves a view, replacing any of the same name. The filter
+/// is checked now so a broken view can't be saved.
+pub async fn save(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    payload: web::Json<View>,
+) -> Result<HttpResponse, ApiError> {
+    let view = payload.into_inner();
+    if view.name.trim().is_empty() || view.name.contains('/') {
+        return Err(ApiError::BadRequest(
+            "View name must be non-empty and contain no '/'".to_string(),
+        ));
+    }
+    ItemFilter::from_query(&view.params(), clock.now_millis()).map_err(ApiError::BadRequest)?;
+
+    let bytes = codec::encode(db.encoding(), &view)
+        .map_err(|_| ApiError::Internal("Serialization failed"))?;
+    db.tree(VIEW_TREE)
+        .and_then(|tree| tree.insert(view.name.as_bytes(), bytes))
+        .map_err(|_| ApiError::Internal("Failed to store view"))?;
+    Ok(HttpResponse::Created().json(view))
+}
+
+pub async fn get(db: TenantStore, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let view = load(&db, &path.into_inner())?;
+    Ok(HttpResponse::Ok().json(view))
+}
+
+pub async fn delete(db: TenantStore, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let name = path.into_inner();
+    db.tree(VIEW_TREE)?
+        .remove(name.as_bytes())?
+        .ok_or(ApiError::NotFound("View not found"))?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// `GET /views/{name}/items`: the items the view selects right now. Takes
+/// the listing's parameters; the view's `type`, `tags` and `q` replace any
+/// given, while the rest narrow, sort and page its results.
+pub async fn items(
+    db: TenantStore,
+    clock: web::Data<SharedClock>,
+    path: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let view = load(&db, &path.into_inner())?;
+    let mut params = query.into_inner();
+    for key in ["type", "tags", "q"] {
+        params.remove(key);
+    }
+    params.extend(view.params());
+
+    let (filter, page, sort, time) = ItemFilter::from_query(&params, clock.now_millis())
+        .and_then(|filter| {
+            let page = Page::from_query(&params)?;
+            let sort = Sort::from_query(&params)?;
+            let time = TimeFormat::from_query(&params)?;
+            Ok((filter, page, sort, time))
+        })
+        .map_err(ApiError::BadRequest)?;
+
+    let mut items = filter::scan_blocking(&db, filter).await?;
+    if let Some(sort) = sort {
+        sort.apply(&mut items);
+    }
+    let items: Vec<_> = page
+        .apply(items.into_iter())
+        .map(|item| time.view(item))
+        .collect();
+    Ok(HttpResponse::Ok().json(items))
+}